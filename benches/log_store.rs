@@ -0,0 +1,77 @@
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use mini_raft::log::{LogEntry, LogStore};
+use mini_raft::types::{LogIndex, Term};
+
+const ENTRIES: u64 = 1_000_000;
+const SAMPLES: u64 = 10_000;
+
+fn entry(index: u64) -> LogEntry {
+    LogEntry {
+        term: Term::new(index / 1_000 + 1),
+        index: LogIndex::new(index),
+        command: vec![0; 16],
+    }
+}
+
+fn log() -> LogStore {
+    let mut log = LogStore::new();
+    for index in 1..=ENTRIES {
+        log.append(entry(index));
+    }
+    log
+}
+
+// Runs `op` `SAMPLES` times and reports the mean time of one call.
+fn bench(name: &str, mut op: impl FnMut(u64)) {
+    let start = Instant::now();
+    for i in 0..SAMPLES {
+        op(i);
+    }
+    let per_op = start.elapsed() / SAMPLES as u32;
+    println!("{:<32} {:>10?}", name, per_op);
+}
+
+// Spreads samples over the whole log.
+fn index(i: u64) -> LogIndex {
+    LogIndex::new(i * (ENTRIES / SAMPLES) + 1)
+}
+
+fn main() {
+    let start = Instant::now();
+    let mut log = log();
+    let built = start.elapsed();
+    println!("{:<32} {:>10?}", "append", built / ENTRIES as u32);
+
+    bench("get", |i| {
+        black_box(log.get(index(i)));
+    });
+    bench("term_at", |i| {
+        black_box(log.term_at(index(i)));
+    });
+    bench("entries_from (last 64)", |_| {
+        black_box(log.entries_from(LogIndex::new(ENTRIES - 63)));
+    });
+    bench("entries_between (64)", |i| {
+        let from = index(i);
+        black_box(log.entries_between(from, LogIndex::new(from.get() + 63)));
+    });
+    bench("last_log_index", |_| {
+        black_box(log.last_log_index());
+    });
+
+    // Truncating and re-appending the tail, as a follower does on conflict.
+    let mut elapsed = Duration::ZERO;
+    for _ in 0..SAMPLES {
+        let start = Instant::now();
+        log.truncate(LogIndex::new(ENTRIES));
+        log.append(entry(ENTRIES));
+        elapsed += start.elapsed();
+    }
+    println!(
+        "{:<32} {:>10?}",
+        "truncate + append (tail)",
+        elapsed / SAMPLES as u32
+    );
+}
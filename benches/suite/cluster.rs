@@ -0,0 +1,276 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::{env, fs, process};
+
+use mini_raft::config::RaftConfig;
+use mini_raft::grpc::{GrpcTransport, Listener};
+use mini_raft::handshake::Handshake;
+use mini_raft::host::NodeHost;
+use mini_raft::node::RaftNode;
+use mini_raft::raft::RaftRunner;
+use mini_raft::segment::SegmentStorage;
+use mini_raft::session::ClientId;
+use mini_raft::state_machine::KvStore;
+use mini_raft::transport::{ChannelTransport, Transport};
+use mini_raft::types::NodeId;
+use serde::Serialize;
+
+// How often the client polls the leader for results; commit latencies are
+// only as precise as this.
+const POLL_INTERVAL: Duration = Duration::from_millis(1);
+
+#[derive(Debug, Clone, Copy)]
+pub enum Setup {
+    // Nodes in one process over tokio channels.
+    Channel,
+    // As `Channel`, with the log written to segment files.
+    Segment,
+    // Nodes in one process over gRPC on localhost.
+    Grpc,
+}
+
+impl Setup {
+    fn name(self) -> &'static str {
+        match self {
+            Setup::Channel => "channel",
+            Setup::Segment => "segment",
+            Setup::Grpc => "grpc",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct Case {
+    pub setup: Setup,
+    pub nodes: u64,
+    pub payload: usize,
+    pub batch: usize,
+}
+
+pub struct Options {
+    pub duration: Duration,
+    // Clients with one proposal outstanding each.
+    pub clients: u64,
+}
+
+// Where the segment setup keeps its logs, one directory per node.
+fn data_dir() -> PathBuf {
+    env::temp_dir().join(format!("mini-raft-bench-{}", process::id()))
+}
+
+fn host<T: Transport>(
+    id: NodeId,
+    ids: &[NodeId],
+    config: &RaftConfig,
+    transport: T,
+    on_disk: bool,
+) -> Arc<Mutex<RaftRunner>> {
+    let peers = ids.iter().copied().filter(|&peer| peer != id).collect();
+    let node = RaftNode::with_config(id, peers, config.clone());
+    let runner = if on_disk {
+        let dir = data_dir().join(format!("node-{}", id.get()));
+        let storage = SegmentStorage::open(&dir).expect("open the segment storage");
+        RaftRunner::with_storage(node, Box::new(KvStore::new()), Box::new(storage))
+    } else {
+        RaftRunner::new(node)
+    };
+    let host = NodeHost::new(runner, transport);
+    let runner = host.runner();
+    tokio::spawn(host.run());
+    runner
+}
+
+async fn start(case: &Case) -> Vec<Arc<Mutex<RaftRunner>>> {
+    let ids: Vec<_> = (1..=case.nodes).map(NodeId::new).collect();
+    let config = RaftConfig {
+        max_batch_size: case.batch,
+        ..RaftConfig::default()
+    };
+
+    match case.setup {
+        Setup::Channel | Setup::Segment => ChannelTransport::cluster(&ids)
+            .into_iter()
+            .map(|(id, transport)| {
+                host(
+                    id,
+                    &ids,
+                    &config,
+                    transport,
+                    matches!(case.setup, Setup::Segment),
+                )
+            })
+            .collect(),
+        Setup::Grpc => {
+            let mut listeners = BTreeMap::new();
+            for &id in &ids {
+                let listener = Listener::bind(&"127.0.0.1:0".parse().unwrap())
+                    .await
+                    .expect("bind a localhost port");
+                listeners.insert(id, listener);
+            }
+            let addrs: BTreeMap<_, _> = listeners
+                .iter()
+                .map(|(&id, listener)| (id, listener.local_addr().unwrap()))
+                .collect();
+
+            listeners
+                .into_iter()
+                .map(|(id, listener)| {
+                    let mut peers = addrs.clone();
+                    peers.remove(&id);
+                    let transport = GrpcTransport::new(Handshake::new("bench"), &peers);
+                    tokio::spawn(transport.serve(listener));
+                    host(id, &ids, &config, transport, false)
+                })
+                .collect()
+        }
+    }
+}
+
+async fn leader(runners: &[Arc<Mutex<RaftRunner>>]) -> Arc<Mutex<RaftRunner>> {
+    for _ in 0..1_000 {
+        let leader = runners.iter().find(|runner| {
+            let runner = runner.lock().unwrap();
+            runner.node().is_leader() && runner.node().has_committed_in_current_term()
+        });
+        if let Some(leader) = leader {
+            return leader.clone();
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("no leader was elected");
+}
+
+// In microseconds.
+fn percentile(sorted: &[Duration], p: f64) -> f64 {
+    let rank = ((sorted.len() as f64 * p).ceil() as usize).clamp(1, sorted.len());
+    sorted[rank - 1].as_secs_f64() * 1e6
+}
+
+#[derive(Serialize)]
+pub struct Measurement {
+    transport: &'static str,
+    nodes: u64,
+    payload_bytes: usize,
+    max_batch_size: usize,
+    clients: u64,
+    ops: usize,
+    throughput_ops_per_sec: f64,
+    commit_latency_us: Latencies,
+}
+
+#[derive(Serialize)]
+struct Latencies {
+    p50: f64,
+    p90: f64,
+    p99: f64,
+    max: f64,
+}
+
+struct Clients {
+    value: String,
+    sequences: HashMap<ClientId, u64>,
+    outstanding: HashMap<(ClientId, u64), Instant>,
+}
+
+impl Clients {
+    fn propose(&mut self, runner: &mut RaftRunner, client: ClientId) {
+        let sequence = self.sequences.entry(client).or_default();
+        *sequence += 1;
+        let command = format!("k{}={}", client, self.value).into_bytes();
+        runner
+            .propose(client, *sequence, command)
+            .expect("leadership changed during the run");
+        self.outstanding.insert((client, *sequence), Instant::now());
+    }
+}
+
+// Closed loop: every client proposes, waits for its result, and proposes
+// again until the run is over.
+async fn drive(
+    leader: &Mutex<RaftRunner>,
+    case: &Case,
+    options: &Options,
+) -> (Vec<Duration>, Duration) {
+    let mut clients = Clients {
+        value: "v".repeat(case.payload),
+        sequences: HashMap::new(),
+        outstanding: HashMap::new(),
+    };
+    let mut latencies = Vec::new();
+
+    let start = Instant::now();
+    {
+        let mut runner = leader.lock().unwrap();
+        for client in 1..=options.clients {
+            clients.propose(&mut runner, client);
+        }
+    }
+
+    let mut last = start;
+    while !clients.outstanding.is_empty() {
+        tokio::time::sleep(POLL_INTERVAL).await;
+        let now = Instant::now();
+        if now > start + options.duration * 10 {
+            panic!("proposals stopped committing");
+        }
+
+        let mut runner = leader.lock().unwrap();
+        for result in runner.take_command_results() {
+            let key = (result.client_id, result.sequence);
+            let Some(proposed) = clients.outstanding.remove(&key) else {
+                continue;
+            };
+            latencies.push(now - proposed);
+            last = now;
+            if now < start + options.duration {
+                clients.propose(&mut runner, result.client_id);
+            }
+        }
+    }
+    (latencies, last - start)
+}
+
+pub fn run(case: &Case, options: &Options) -> Measurement {
+    let _ = fs::remove_dir_all(data_dir());
+    // A runtime per case, so nothing of one cluster outlives its run.
+    let runtime = tokio::runtime::Runtime::new().unwrap();
+    let (mut latencies, elapsed) = runtime.block_on(async {
+        let runners = start(case).await;
+        let leader = leader(&runners).await;
+        drive(&leader, case, options).await
+    });
+    runtime.shutdown_background();
+    let _ = fs::remove_dir_all(data_dir());
+
+    latencies.sort();
+    let throughput = latencies.len() as f64 / elapsed.as_secs_f64();
+    eprintln!(
+        "{:<8} {} nodes, {:>5} B, batch {:>3}: {:>8.0} ops/s, p50 {:>6.0?} p99 {:>6.0?}",
+        case.setup.name(),
+        case.nodes,
+        case.payload,
+        case.batch,
+        throughput,
+        latencies[latencies.len() / 2],
+        latencies[latencies.len() * 99 / 100],
+    );
+
+    Measurement {
+        transport: case.setup.name(),
+        nodes: case.nodes,
+        payload_bytes: case.payload,
+        max_batch_size: case.batch,
+        clients: options.clients,
+        ops: latencies.len(),
+        throughput_ops_per_sec: throughput,
+        commit_latency_us: Latencies {
+            p50: percentile(&latencies, 0.5),
+            p90: percentile(&latencies, 0.9),
+            p99: percentile(&latencies, 0.99),
+            max: percentile(&latencies, 1.0),
+        },
+    }
+}
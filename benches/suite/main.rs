@@ -0,0 +1,79 @@
+// Benchmarks for mini-raft: microbenchmarks of the hot paths and commit
+// latency and throughput of whole clusters. Progress goes to stderr, the
+// results to stdout as one JSON document, and to `--output <file>` if given.
+// `--quick` runs a smaller set for smoke testing.
+//
+//     cargo bench --bench suite -- --output bench.json
+
+mod cluster;
+mod micro;
+
+use std::time::Duration;
+use std::{env, fs};
+
+use serde::Serialize;
+
+use cluster::{Case, Setup};
+
+#[derive(Serialize)]
+struct Report {
+    suite: &'static str,
+    version: &'static str,
+    micro: Vec<micro::Measurement>,
+    cluster: Vec<cluster::Measurement>,
+}
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let quick = args.iter().any(|arg| arg == "--quick");
+    let output = args
+        .iter()
+        .position(|arg| arg == "--output")
+        .and_then(|i| args.get(i + 1));
+
+    let micro_options = micro::Options {
+        entries: if quick { 100_000 } else { 1_000_000 },
+        samples: if quick { 10_000 } else { 100_000 },
+    };
+    let cluster_options = cluster::Options {
+        duration: Duration::from_millis(if quick { 300 } else { 2_000 }),
+        clients: 64,
+    };
+
+    let micro = micro::run(&micro_options);
+
+    let mut cases = Vec::new();
+    for setup in [Setup::Channel, Setup::Segment, Setup::Grpc] {
+        for nodes in [3, 5] {
+            for payload in [16, 1024] {
+                for batch in [1, 64] {
+                    cases.push(Case {
+                        setup,
+                        nodes,
+                        payload,
+                        batch,
+                    });
+                }
+            }
+        }
+    }
+    if quick {
+        cases.retain(|case| case.nodes == 3 && case.payload == 16);
+    }
+    let cluster = cases
+        .iter()
+        .map(|case| cluster::run(case, &cluster_options))
+        .collect();
+
+    let report = serde_json::to_string(&Report {
+        suite: "mini-raft",
+        version: env!("CARGO_PKG_VERSION"),
+        micro,
+        cluster,
+    })
+    .unwrap();
+    println!("{}", report);
+    if let Some(path) = output {
+        fs::write(path, format!("{}\n", report)).expect("write the results");
+    }
+}
@@ -0,0 +1,168 @@
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+use mini_raft::log::{LogEntry, LogStore};
+use mini_raft::node::RaftNode;
+use mini_raft::rpc::AppendEntriesRequest;
+use mini_raft::types::{LogIndex, NodeId, Term};
+use serde::Serialize;
+
+pub struct Options {
+    // Entries in the log every operation runs against.
+    pub entries: u64,
+    pub samples: u64,
+}
+
+fn entry(index: u64, term: Term) -> LogEntry {
+    LogEntry {
+        term,
+        index: LogIndex::new(index),
+        command: vec![0; 16],
+    }
+}
+
+fn log(entries: u64, term: Term) -> LogStore {
+    let mut log = LogStore::new();
+    for index in 1..=entries {
+        log.append(entry(index, term));
+    }
+    log
+}
+
+#[derive(Serialize)]
+pub struct Measurement {
+    name: String,
+    log_entries: u64,
+    ops: u64,
+    ns_per_op: f64,
+}
+
+fn result(name: &str, options: &Options, elapsed: Duration, ops: u64) -> Measurement {
+    let ns_per_op = elapsed.as_nanos() as f64 / ops as f64;
+    eprintln!("{:<40} {:>10.1} ns", name, ns_per_op);
+    Measurement {
+        name: name.to_string(),
+        log_entries: options.entries,
+        ops,
+        ns_per_op,
+    }
+}
+
+// Runs `op` once per sample and reports the mean time of one call.
+fn bench(name: &str, options: &Options, mut op: impl FnMut(u64)) -> Measurement {
+    let start = Instant::now();
+    for i in 0..options.samples {
+        op(i);
+    }
+    result(name, options, start.elapsed(), options.samples)
+}
+
+pub fn run(options: &Options) -> Vec<Measurement> {
+    let mut results = Vec::new();
+    results.extend(log_store(options));
+    results.extend(handle_append_entries(options));
+    results.push(update_commit_index(options));
+    results
+}
+
+fn log_store(options: &Options) -> Vec<Measurement> {
+    let term = Term::new(1);
+    let start = Instant::now();
+    let mut log = log(options.entries, term);
+    let mut results = vec![result(
+        "log_store/append",
+        options,
+        start.elapsed(),
+        options.entries,
+    )];
+
+    // Samples are spread over the whole log.
+    let stride = (options.entries / options.samples).max(1);
+    let index = |i: u64| LogIndex::new((i * stride) % options.entries + 1);
+    let last = options.entries;
+
+    results.push(bench("log_store/get", options, |i| {
+        black_box(log.get(index(i)));
+    }));
+    results.push(bench("log_store/term_at", options, |i| {
+        black_box(log.term_at(index(i)));
+    }));
+    results.push(bench("log_store/entries_from(tail 64)", options, |_| {
+        black_box(log.entries_from(LogIndex::new(last.saturating_sub(63))));
+    }));
+    results.push(bench("log_store/entries_between(64)", options, |i| {
+        let from = index(i);
+        black_box(log.entries_between(from, LogIndex::new(from.get() + 63)));
+    }));
+    // Replacing the tail, as a follower does on a conflict.
+    results.push(bench("log_store/truncate+append(tail)", options, |_| {
+        log.truncate(LogIndex::new(last));
+        log.append(entry(last, term));
+    }));
+    results
+}
+
+// A follower with a long log taking heartbeats and new entries at its end.
+fn handle_append_entries(options: &Options) -> Vec<Measurement> {
+    let term = Term::new(1);
+    let mut follower = RaftNode::new(NodeId::new(2), vec![NodeId::new(1), NodeId::new(3)]);
+    follower.current_term = term;
+    follower.log = log(options.entries, term);
+
+    let request = |node: &RaftNode, entries: u64| {
+        let last = node.log.last_log_index();
+        AppendEntriesRequest {
+            term,
+            leader_id: NodeId::new(1),
+            prev_log_index: last,
+            prev_log_term: term,
+            entries: (1..=entries)
+                .map(|offset| entry(last.get() + offset, term))
+                .collect(),
+            leader_commit: last,
+            heartbeat_round: 0,
+        }
+    };
+
+    let mut results = Vec::new();
+    for entries in [0, 1, 64] {
+        let name = match entries {
+            0 => "handle_append_entries/heartbeat".to_string(),
+            1 => "handle_append_entries/1 entry".to_string(),
+            n => format!("handle_append_entries/{} entries", n),
+        };
+        // Requests are built outside the timed region.
+        let mut elapsed = Duration::ZERO;
+        for _ in 0..options.samples / 10 {
+            let request = request(&follower, entries);
+            let start = Instant::now();
+            black_box(follower.handle_append_entries(request));
+            elapsed += start.elapsed();
+        }
+        results.push(result(&name, options, elapsed, options.samples / 10));
+    }
+    results
+}
+
+// A leader of five advancing its commit index one entry at a time.
+fn update_commit_index(options: &Options) -> Measurement {
+    let peers: Vec<_> = (2..=5).map(NodeId::new).collect();
+    let mut leader = RaftNode::new(NodeId::new(1), peers.clone());
+    leader.become_candidate();
+    leader.become_leader();
+    let term = leader.current_term;
+    for index in leader.log.last_log_index().get() + 1..=options.entries {
+        leader.log.append(entry(index, term));
+    }
+    leader.durable_index = leader.log.last_log_index();
+
+    let start = leader.commit_index.get();
+    bench("update_commit_index", options, |i| {
+        let matched = LogIndex::new((start + i) % options.entries + 1);
+        for peer in &peers[..2] {
+            leader.match_index.insert(*peer, matched);
+        }
+        leader.update_commit_index();
+        black_box(leader.commit_index);
+    })
+}
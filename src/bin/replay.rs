@@ -0,0 +1,56 @@
+use std::{env, fs, process};
+
+use mini_raft::{spec, trace};
+
+// Replays every node of each cluster trace given on the command line, as
+// written by `scenario --trace`, and checks the trace against the spec
+// model. Reports where a node diverged.
+fn main() {
+    let paths: Vec<String> = env::args().skip(1).collect();
+    if paths.is_empty() {
+        eprintln!("usage: replay <trace.jsonl>...");
+        process::exit(2);
+    }
+
+    let mut failed = 0;
+    for path in &paths {
+        let cluster_trace = match fs::read_to_string(path)
+            .map_err(|err| err.to_string())
+            .and_then(|text| trace::from_cluster_json_lines(&text).map_err(|err| err.to_string()))
+        {
+            Ok(cluster_trace) => cluster_trace,
+            Err(err) => {
+                println!("FAIL {}: {}", path, err);
+                failed += 1;
+                continue;
+            }
+        };
+
+        match spec::check(&cluster_trace) {
+            Ok(()) => println!("OK   {} matches the spec model", path),
+            Err(violation) => {
+                println!("FAIL {}: {}", path, violation);
+                failed += 1;
+            }
+        }
+
+        for (id, node_trace) in trace::by_node(cluster_trace) {
+            match trace::replay(&node_trace) {
+                Ok(()) => println!(
+                    "OK   {} node {} ({} events)",
+                    path,
+                    id.get(),
+                    node_trace.len()
+                ),
+                Err(err) => {
+                    println!("FAIL {} node {}: {}", path, id.get(), err);
+                    failed += 1;
+                }
+            }
+        }
+    }
+
+    if failed > 0 {
+        process::exit(1);
+    }
+}
@@ -0,0 +1,68 @@
+use std::path::Path;
+use std::{env, fs, process};
+
+use mini_raft::fuzz::Driver;
+use mini_raft::scenario::Scenario;
+
+// Runs each scenario file given on the command line and reports which failed.
+// With `--trace <dir>`, each run's cluster trace is written to
+// `<dir>/<name>.jsonl` for `replay`.
+fn main() {
+    let mut args = env::args().skip(1);
+    let mut trace_dir = None;
+    let mut paths = Vec::new();
+    while let Some(arg) = args.next() {
+        if arg == "--trace" {
+            trace_dir = args.next();
+        } else {
+            paths.push(arg);
+        }
+    }
+    if paths.is_empty() {
+        eprintln!("usage: scenario [--trace <dir>] <file>...");
+        process::exit(2);
+    }
+
+    let mut results = Vec::new();
+    for path in &paths {
+        let result = fs::read_to_string(path)
+            .map_err(|err| err.to_string())
+            .and_then(|text| Scenario::parse(&text).map_err(|err| err.to_string()))
+            .and_then(|scenario| {
+                let mut driver = scenario.driver();
+                let result = scenario.run_on(&mut driver).map_err(|err| err.to_string());
+                if let Some(dir) = &trace_dir {
+                    write_trace(dir, path, &driver)?;
+                }
+                result
+            });
+        results.push((path, result));
+    }
+
+    println!("\n=== Scenarios ===");
+    let mut failed = 0;
+    for (path, result) in results {
+        match result {
+            Ok(()) => println!("PASS {}", path),
+            Err(err) => {
+                println!("FAIL {}: {}", path, err);
+                failed += 1;
+            }
+        }
+    }
+
+    if failed > 0 {
+        process::exit(1);
+    }
+}
+
+fn write_trace(dir: &str, path: &str, driver: &Driver) -> Result<(), String> {
+    let name = Path::new(path)
+        .file_stem()
+        .map_or("trace".into(), |stem| stem.to_string_lossy());
+    let out = Path::new(dir).join(format!("{}.jsonl", name));
+
+    fs::create_dir_all(dir)
+        .and_then(|_| fs::write(&out, driver.sim().trace_json_lines()))
+        .map_err(|err| format!("writing {}: {}", out.display(), err))
+}
@@ -0,0 +1,90 @@
+use std::io::{self, Write};
+use std::time::Duration;
+use std::{env, process};
+
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::terminal::{self, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{cursor, execute};
+
+use mini_raft::tui::{App, Key};
+
+const USAGE: &str = "usage: tui [nodes] [seed]";
+
+// How long a frame waits for keys before the simulation moves on.
+const FRAME: Duration = Duration::from_millis(50);
+
+// Raw mode on the alternate screen. Dropping it restores the terminal, also
+// when `main` returns early with an error.
+struct RawMode;
+
+impl RawMode {
+    fn enable() -> io::Result<Self> {
+        terminal::enable_raw_mode()?;
+        let raw = Self;
+        execute!(io::stdout(), EnterAlternateScreen, cursor::Hide)?;
+        Ok(raw)
+    }
+}
+
+impl Drop for RawMode {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), cursor::Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+fn parse_arg(value: Option<String>, default: u64) -> u64 {
+    match value {
+        None => default,
+        Some(value) => value.parse().unwrap_or_else(|_| {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }),
+    }
+}
+
+fn main() -> io::Result<()> {
+    let mut args = env::args().skip(1);
+    let nodes = parse_arg(args.next(), 5);
+    let seed = parse_arg(args.next(), 0);
+    if !(1..=9).contains(&nodes) {
+        eprintln!("{} (1 to 9 nodes)", USAGE);
+        process::exit(2);
+    }
+
+    let mut app = App::new(nodes, seed);
+    let _raw = RawMode::enable()?;
+    let mut stdout = io::stdout();
+
+    while !app.should_quit() {
+        // The first key may take up to a frame; any more already waiting are
+        // handled in the same frame.
+        let mut timeout = FRAME;
+        while event::poll(timeout)? {
+            timeout = Duration::ZERO;
+            let Event::Key(event) = event::read()? else {
+                continue;
+            };
+            if event.kind != KeyEventKind::Press {
+                continue;
+            }
+            // Raw mode turns Ctrl-C into a key instead of a signal.
+            if event.modifiers.contains(KeyModifiers::CONTROL) && event.code == KeyCode::Char('c') {
+                return Ok(());
+            }
+            let key = match event.code {
+                KeyCode::Enter => Key::Enter,
+                KeyCode::Backspace => Key::Backspace,
+                KeyCode::Esc => Key::Escape,
+                KeyCode::Char(c) if c.is_ascii() && !c.is_ascii_control() => Key::Char(c),
+                _ => continue,
+            };
+            app.handle_key(key);
+        }
+
+        app.frame();
+        write!(stdout, "\x1b[H\x1b[2J{}", app.render().replace('\n', "\r\n"))?;
+        stdout.flush()?;
+    }
+    Ok(())
+}
@@ -0,0 +1,103 @@
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+pub trait Clock: Debug + Send + Sync {
+    fn now(&self) -> Instant;
+}
+
+pub type SharedClock = Arc<dyn Clock>;
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+// Virtual time that only moves when advanced explicitly.
+#[derive(Debug)]
+pub struct ManualClock {
+    origin: Instant,
+    elapsed: Mutex<Duration>,
+}
+
+impl ManualClock {
+    pub fn new() -> Self {
+        Self {
+            origin: Instant::now(),
+            elapsed: Mutex::new(Duration::ZERO),
+        }
+    }
+
+    pub fn advance(&self, duration: Duration) {
+        *self.elapsed.lock().unwrap() += duration;
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        *self.elapsed.lock().unwrap()
+    }
+}
+
+impl Default for ManualClock {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> Instant {
+        self.origin + self.elapsed()
+    }
+}
+
+#[derive(Debug)]
+struct Skew {
+    inner_anchor: Instant,
+    local_anchor: Instant,
+    rate: f64,
+}
+
+// Runs at `rate` times the speed of the wrapped clock, to simulate drift
+// between nodes. Changing the rate never makes the clock jump.
+#[derive(Debug)]
+pub struct SkewedClock {
+    inner: SharedClock,
+    skew: Mutex<Skew>,
+}
+
+impl SkewedClock {
+    pub fn new(inner: SharedClock, rate: f64) -> Self {
+        let now = inner.now();
+        Self {
+            inner,
+            skew: Mutex::new(Skew {
+                inner_anchor: now,
+                local_anchor: now,
+                rate,
+            }),
+        }
+    }
+
+    pub fn set_rate(&self, rate: f64) {
+        let inner_now = self.inner.now();
+        let mut skew = self.skew.lock().unwrap();
+        skew.local_anchor = Self::local_time(&skew, inner_now);
+        skew.inner_anchor = inner_now;
+        skew.rate = rate;
+    }
+
+    fn local_time(skew: &Skew, inner_now: Instant) -> Instant {
+        let inner_elapsed = inner_now.saturating_duration_since(skew.inner_anchor);
+        skew.local_anchor + inner_elapsed.mul_f64(skew.rate)
+    }
+}
+
+impl Clock for SkewedClock {
+    fn now(&self) -> Instant {
+        let inner_now = self.inner.now();
+        Self::local_time(&self.skew.lock().unwrap(), inner_now)
+    }
+}
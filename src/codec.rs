@@ -0,0 +1,328 @@
+use std::fmt;
+
+use tonic::Status;
+
+use crate::log::LogEntry;
+use crate::raft_proto;
+use crate::rpc;
+use crate::types::{LogIndex, NodeId, Term};
+
+// Conversions between the wire messages in `raft_proto` and the internal
+// `rpc` types. Encoding never fails; decoding checks what the protocol
+// guarantees about a well-formed message and reports the first violation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DecodeError {
+    pub message: &'static str,
+    pub field: &'static str,
+    pub reason: String,
+}
+
+impl DecodeError {
+    fn new(message: &'static str, field: &'static str, reason: impl Into<String>) -> Self {
+        Self {
+            message,
+            field,
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "invalid {}.{}: {}",
+            self.message, self.field, self.reason
+        )
+    }
+}
+
+impl From<DecodeError> for Status {
+    fn from(error: DecodeError) -> Self {
+        Status::invalid_argument(error.to_string())
+    }
+}
+
+fn node_id(message: &'static str, field: &'static str, id: u64) -> Result<NodeId, DecodeError> {
+    if id == 0 {
+        return Err(DecodeError::new(message, field, "node ids start at 1"));
+    }
+    Ok(NodeId::new(id))
+}
+
+// The position and term of a log entry something points at: index 0 is
+// the empty log, which only term 0 describes, and no entry is newer than
+// the term of the message carrying it.
+fn log_position(
+    message: &'static str,
+    field: &'static str,
+    index: u64,
+    term: u64,
+    message_term: u64,
+) -> Result<(LogIndex, Term), DecodeError> {
+    if index == 0 && term != 0 {
+        return Err(DecodeError::new(
+            message,
+            field,
+            format!("term {} given for the empty log", term),
+        ));
+    }
+    if index != 0 && term == 0 {
+        return Err(DecodeError::new(
+            message,
+            field,
+            format!("entry {} has term 0", index),
+        ));
+    }
+    if term > message_term {
+        return Err(DecodeError::new(
+            message,
+            field,
+            format!(
+                "term {} is ahead of the message term {}",
+                term, message_term
+            ),
+        ));
+    }
+    Ok((LogIndex::new(index), Term::new(term)))
+}
+
+impl From<LogEntry> for raft_proto::LogEntry {
+    fn from(entry: LogEntry) -> Self {
+        Self {
+            term: entry.term.get(),
+            index: entry.index.get(),
+            command: entry.command,
+        }
+    }
+}
+
+impl TryFrom<raft_proto::LogEntry> for LogEntry {
+    type Error = DecodeError;
+
+    fn try_from(entry: raft_proto::LogEntry) -> Result<Self, Self::Error> {
+        if entry.index == 0 {
+            return Err(DecodeError::new("LogEntry", "index", "entries start at 1"));
+        }
+        if entry.term == 0 {
+            return Err(DecodeError::new(
+                "LogEntry",
+                "term",
+                "entries have a term of at least 1",
+            ));
+        }
+        Ok(Self {
+            term: Term::new(entry.term),
+            index: LogIndex::new(entry.index),
+            command: entry.command,
+        })
+    }
+}
+
+impl From<rpc::RequestVoteRequest> for raft_proto::RequestVoteRequest {
+    fn from(req: rpc::RequestVoteRequest) -> Self {
+        Self {
+            term: req.term.get(),
+            candidate_id: req.candidate_id.get(),
+            last_log_index: req.last_log_index.get(),
+            last_log_term: req.last_log_term.get(),
+        }
+    }
+}
+
+impl TryFrom<raft_proto::RequestVoteRequest> for rpc::RequestVoteRequest {
+    type Error = DecodeError;
+
+    fn try_from(req: raft_proto::RequestVoteRequest) -> Result<Self, Self::Error> {
+        const MESSAGE: &str = "RequestVoteRequest";
+        let candidate_id = node_id(MESSAGE, "candidate_id", req.candidate_id)?;
+        if req.term == 0 {
+            return Err(DecodeError::new(
+                MESSAGE,
+                "term",
+                "candidates start at term 1",
+            ));
+        }
+        let (last_log_index, last_log_term) = log_position(
+            MESSAGE,
+            "last_log_term",
+            req.last_log_index,
+            req.last_log_term,
+            req.term,
+        )?;
+
+        Ok(Self {
+            term: Term::new(req.term),
+            candidate_id,
+            last_log_index,
+            last_log_term,
+        })
+    }
+}
+
+impl From<rpc::RequestVoteResponse> for raft_proto::RequestVoteResponse {
+    fn from(resp: rpc::RequestVoteResponse) -> Self {
+        Self {
+            term: resp.term.get(),
+            vote_granted: resp.vote_granted,
+        }
+    }
+}
+
+impl TryFrom<raft_proto::RequestVoteResponse> for rpc::RequestVoteResponse {
+    type Error = DecodeError;
+
+    fn try_from(resp: raft_proto::RequestVoteResponse) -> Result<Self, Self::Error> {
+        if resp.vote_granted && resp.term == 0 {
+            return Err(DecodeError::new(
+                "RequestVoteResponse",
+                "term",
+                "votes are granted in term 1 or later",
+            ));
+        }
+        Ok(Self {
+            term: Term::new(resp.term),
+            vote_granted: resp.vote_granted,
+        })
+    }
+}
+
+impl From<rpc::AppendEntriesRequest> for raft_proto::AppendEntriesRequest {
+    fn from(req: rpc::AppendEntriesRequest) -> Self {
+        Self {
+            term: req.term.get(),
+            leader_id: req.leader_id.get(),
+            prev_log_index: req.prev_log_index.get(),
+            prev_log_term: req.prev_log_term.get(),
+            entries: req.entries.into_iter().map(Into::into).collect(),
+            leader_commit: req.leader_commit.get(),
+            heartbeat_round: req.heartbeat_round,
+        }
+    }
+}
+
+impl TryFrom<raft_proto::AppendEntriesRequest> for rpc::AppendEntriesRequest {
+    type Error = DecodeError;
+
+    fn try_from(req: raft_proto::AppendEntriesRequest) -> Result<Self, Self::Error> {
+        const MESSAGE: &str = "AppendEntriesRequest";
+        let leader_id = node_id(MESSAGE, "leader_id", req.leader_id)?;
+        if req.term == 0 {
+            return Err(DecodeError::new(MESSAGE, "term", "leaders start at term 1"));
+        }
+        let (prev_log_index, prev_log_term) = log_position(
+            MESSAGE,
+            "prev_log_term",
+            req.prev_log_index,
+            req.prev_log_term,
+            req.term,
+        )?;
+
+        // Entries continue the log right after the previous entry, with
+        // terms that never decrease and never pass the leader's.
+        let mut entries = Vec::with_capacity(req.entries.len());
+        let (mut index, mut term) = (prev_log_index.get(), prev_log_term);
+        for entry in req.entries {
+            let entry = LogEntry::try_from(entry)?;
+            index = index
+                .checked_add(1)
+                .ok_or_else(|| DecodeError::new(MESSAGE, "entries", "log index overflows"))?;
+            if entry.index.get() != index {
+                return Err(DecodeError::new(
+                    MESSAGE,
+                    "entries",
+                    format!("expected entry {}, got {}", index, entry.index.get()),
+                ));
+            }
+            if entry.term < term || entry.term.get() > req.term {
+                return Err(DecodeError::new(
+                    MESSAGE,
+                    "entries",
+                    format!("entry {} has out of order term {}", index, entry.term.get()),
+                ));
+            }
+            term = entry.term;
+            entries.push(entry);
+        }
+
+        Ok(Self {
+            term: Term::new(req.term),
+            leader_id,
+            prev_log_index,
+            prev_log_term,
+            entries,
+            leader_commit: LogIndex::new(req.leader_commit),
+            heartbeat_round: req.heartbeat_round,
+        })
+    }
+}
+
+impl From<rpc::AppendEntriesResponse> for raft_proto::AppendEntriesResponse {
+    fn from(resp: rpc::AppendEntriesResponse) -> Self {
+        Self {
+            term: resp.term.get(),
+            success: resp.success,
+            match_index: resp.match_index.get(),
+            heartbeat_round: resp.heartbeat_round,
+        }
+    }
+}
+
+impl TryFrom<raft_proto::AppendEntriesResponse> for rpc::AppendEntriesResponse {
+    type Error = DecodeError;
+
+    fn try_from(resp: raft_proto::AppendEntriesResponse) -> Result<Self, Self::Error> {
+        Ok(Self {
+            term: Term::new(resp.term),
+            success: resp.success,
+            match_index: LogIndex::new(resp.match_index),
+            heartbeat_round: resp.heartbeat_round,
+        })
+    }
+}
+
+impl From<rpc::ReadIndexRequest> for raft_proto::ReadIndexRequest {
+    fn from(req: rpc::ReadIndexRequest) -> Self {
+        Self {
+            term: req.term.get(),
+            follower_id: req.follower_id.get(),
+            request_id: req.request_id,
+        }
+    }
+}
+
+impl TryFrom<raft_proto::ReadIndexRequest> for rpc::ReadIndexRequest {
+    type Error = DecodeError;
+
+    fn try_from(req: raft_proto::ReadIndexRequest) -> Result<Self, Self::Error> {
+        Ok(Self {
+            term: Term::new(req.term),
+            follower_id: node_id("ReadIndexRequest", "follower_id", req.follower_id)?,
+            request_id: req.request_id,
+        })
+    }
+}
+
+impl From<rpc::ReadIndexResponse> for raft_proto::ReadIndexResponse {
+    fn from(resp: rpc::ReadIndexResponse) -> Self {
+        Self {
+            term: resp.term.get(),
+            request_id: resp.request_id,
+            success: resp.success,
+            read_index: resp.read_index.get(),
+        }
+    }
+}
+
+impl TryFrom<raft_proto::ReadIndexResponse> for rpc::ReadIndexResponse {
+    type Error = DecodeError;
+
+    fn try_from(resp: raft_proto::ReadIndexResponse) -> Result<Self, Self::Error> {
+        Ok(Self {
+            term: Term::new(resp.term),
+            request_id: resp.request_id,
+            success: resp.success,
+            read_index: LogIndex::new(resp.read_index),
+        })
+    }
+}
@@ -0,0 +1,61 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::timer::min_election_timeout;
+use crate::trace::nanos;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RaftConfig {
+    // Leaders step down without a live quorum, followers ignore vote
+    // requests while they hear from a leader.
+    pub check_quorum: bool,
+    // Serve reads on the leader without a heartbeat round while its lease
+    // holds. Only takes effect together with `check_quorum`.
+    pub lease_read: bool,
+    // Maximum relative clock rate difference between any node and real time,
+    // e.g. 0.05 for 5%.
+    pub max_clock_drift: f64,
+    // Client sessions idle for this many log entries are dropped.
+    pub session_timeout: u64,
+    // Proposals are appended and replicated in batches of at most this many
+    // entries, each cut once full or once its oldest proposal has waited
+    // `max_batch_delay`.
+    pub max_batch_size: usize,
+    #[serde(rename = "max_batch_delay_ns", with = "nanos")]
+    pub max_batch_delay: Duration,
+    // AppendEntries carries at most this many entries, and past the first
+    // at most this many bytes of commands, so a lagging follower catches up
+    // one window at a time and only that window is read back from storage.
+    pub max_append_entries: usize,
+    pub max_append_bytes: u64,
+    // Bytes of log commands kept in memory; older ones are read back from
+    // storage when needed. Unbounded if `None` or if the storage cannot read
+    // entries back.
+    pub log_cache_bytes: Option<u64>,
+}
+
+impl RaftConfig {
+    // The lease must run out before any follower, whose clock may run fast,
+    // stops ignoring vote requests, even if the leader's clock runs slow.
+    pub fn lease_duration(&self) -> Duration {
+        let drift = self.max_clock_drift.clamp(0.0, 1.0);
+        min_election_timeout().mul_f64((1.0 - drift) / (1.0 + drift))
+    }
+}
+
+impl Default for RaftConfig {
+    fn default() -> Self {
+        Self {
+            check_quorum: true,
+            lease_read: false,
+            max_clock_drift: 0.05,
+            session_timeout: 100_000,
+            max_batch_size: 64,
+            max_batch_delay: Duration::ZERO,
+            max_append_entries: 1024,
+            max_append_bytes: 1 << 20,
+            log_cache_bytes: None,
+        }
+    }
+}
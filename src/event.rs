@@ -1,11 +1,19 @@
-use crate::{rpc::{AppendEntriesRequest, AppendEntriesResponse, RequestVoteRequest, RequestVoteResponse}};
+use crate::{
+    rpc::{
+        AppendEntriesRequest, AppendEntriesResponse, ReadIndexRequest, ReadIndexResponse,
+        RequestVoteRequest, RequestVoteResponse,
+    },
+    types::NodeId,
+};
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum RaftEvent {
     ElectionTimeout,
     HeartbeatTimeout,
     ReceivedRequestVote(RequestVoteRequest),
-    ReceivedRequestVoteResponse(RequestVoteResponse),
+    ReceivedRequestVoteResponse(NodeId, RequestVoteResponse),
     ReceivedAppendEntries(AppendEntriesRequest),
-    ReceivedAppendEntriesResponse(AppendEntriesResponse)
+    ReceivedAppendEntriesResponse(NodeId, AppendEntriesResponse),
+    ReceivedReadIndex(ReadIndexRequest),
+    ReceivedReadIndexResponse(ReadIndexResponse),
 }
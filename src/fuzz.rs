@@ -0,0 +1,669 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Duration;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+use crate::config::RaftConfig;
+use crate::invariants::Violation;
+use crate::linearizability::{History, KvInput, NonLinearizable, OpId};
+use crate::read_index::{ReadConsistency, ReadId};
+use crate::session::{ClientId, ProposeOutcome, SessionExpired};
+use crate::simulator::Simulator;
+use crate::types::{LogIndex, NodeId};
+
+// Keys written by generated proposals. Few keys keep the writes contending.
+const KEYS: [&str; 2] = ["a", "b"];
+
+// How long a generated schedule lets the cluster settle after its last fault.
+const SETTLE: Duration = Duration::from_secs(1);
+
+// Nodes past the initial ones that generated schedules may add.
+const SPARE_NODES: u64 = 2;
+
+// One action of a schedule.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Step {
+    Propose(Vec<u8>),
+    // A linearizable read of a key through the leader.
+    Read(Vec<u8>),
+    // Resends the oldest unanswered write under the same client and sequence.
+    Retry,
+    // Starts the node if it is new and asks the leader to add it, or to
+    // remove it.
+    Add(NodeId),
+    Remove(NodeId),
+    Advance(Duration),
+    Partition(Vec<Vec<NodeId>>),
+    Isolate(NodeId),
+    Heal,
+    Crash(NodeId),
+    // A crash that also loses every write not yet synced.
+    PowerFail(NodeId),
+    Restart(NodeId),
+}
+
+impl fmt::Display for Step {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Step::Propose(command) => write!(f, "propose 0x{}", to_hex(command)),
+            Step::Read(key) => write!(f, "read 0x{}", to_hex(key)),
+            Step::Retry => write!(f, "retry"),
+            Step::Add(id) => write!(f, "add {}", id.get()),
+            Step::Remove(id) => write!(f, "remove {}", id.get()),
+            Step::Advance(duration) => write!(f, "advance {}ms", duration.as_millis()),
+            Step::Partition(groups) => {
+                write!(f, "partition")?;
+                for group in groups {
+                    let ids: Vec<String> = group.iter().map(|id| id.get().to_string()).collect();
+                    write!(f, " {{{}}}", ids.join(","))?;
+                }
+                Ok(())
+            }
+            Step::Isolate(id) => write!(f, "isolate {}", id.get()),
+            Step::Heal => write!(f, "heal"),
+            Step::Crash(id) => write!(f, "crash {}", id.get()),
+            Step::PowerFail(id) => write!(f, "power-fail {}", id.get()),
+            Step::Restart(id) => write!(f, "restart {}", id.get()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl FromStr for Step {
+    type Err = String;
+
+    fn from_str(line: &str) -> Result<Self, Self::Err> {
+        let (command, rest) = line.split_once(' ').unwrap_or((line, ""));
+        let rest = rest.trim();
+
+        match command {
+            "propose" => parse_bytes(rest).map(Step::Propose),
+            "read" => parse_bytes(rest).map(Step::Read),
+            "retry" => Ok(Step::Retry),
+            "add" => parse_node(rest).map(Step::Add),
+            "remove" => parse_node(rest).map(Step::Remove),
+            "advance" => parse_duration(rest).map(Step::Advance),
+            "partition" => rest
+                .split_whitespace()
+                .map(parse_group)
+                .collect::<Result<_, _>>()
+                .map(Step::Partition),
+            "isolate" => parse_node(rest).map(Step::Isolate),
+            "heal" => Ok(Step::Heal),
+            "crash" => parse_node(rest).map(Step::Crash),
+            "power-fail" => parse_node(rest).map(Step::PowerFail),
+            "restart" => parse_node(rest).map(Step::Restart),
+            _ => Err(format!("unknown command `{}`", command)),
+        }
+    }
+}
+
+// Written out in hex, so any bytes survive; hand-written scenarios can quote
+// plain text instead.
+fn parse_bytes(text: &str) -> Result<Vec<u8>, String> {
+    if let Some(hex) = text.strip_prefix("0x") {
+        return from_hex(hex);
+    }
+    text.strip_prefix('"')
+        .and_then(|text| text.strip_suffix('"'))
+        .map(|quoted| quoted.as_bytes().to_vec())
+        .ok_or_else(|| format!("expected quoted or 0x-prefixed hex bytes, got `{}`", text))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+fn from_hex(text: &str) -> Result<Vec<u8>, String> {
+    if !text.len().is_multiple_of(2) || !text.is_ascii() {
+        return Err(format!("invalid hex `{}`", text));
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|at| {
+            u8::from_str_radix(&text[at..at + 2], 16).map_err(|_| format!("invalid hex `{}`", text))
+        })
+        .collect()
+}
+
+pub fn parse_duration(text: &str) -> Result<Duration, String> {
+    let parsed = if let Some(ms) = text.strip_suffix("ms") {
+        ms.parse().map(Duration::from_millis)
+    } else if let Some(secs) = text.strip_suffix('s') {
+        secs.parse().map(Duration::from_secs)
+    } else {
+        return Err(format!("`{}` needs a unit (ms or s)", text));
+    };
+    parsed.map_err(|_| format!("invalid duration `{}`", text))
+}
+
+pub fn parse_node(text: &str) -> Result<NodeId, String> {
+    text.parse()
+        .map(NodeId::new)
+        .map_err(|_| format!("invalid node id `{}`", text))
+}
+
+fn parse_group(text: &str) -> Result<Vec<NodeId>, String> {
+    text.strip_prefix('{')
+        .and_then(|text| text.strip_suffix('}'))
+        .ok_or_else(|| format!("expected a group like {{1,2}}, got `{}`", text))?
+        .split(',')
+        .map(|id| parse_node(id.trim()))
+        .collect()
+}
+
+// A reproducible run: cluster size, simulator seed and the steps applied to
+// it. Written out as one command per line so failures can be replayed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Schedule {
+    pub seed: u64,
+    pub nodes: u64,
+    // Overrides `RaftConfig::max_append_entries`, e.g. to make followers
+    // catch up in many small windows.
+    pub max_append_entries: Option<usize>,
+    pub steps: Vec<Step>,
+}
+
+// The config a schedule or scenario runs with.
+pub fn config(max_append_entries: Option<usize>) -> RaftConfig {
+    let default = RaftConfig::default();
+    RaftConfig {
+        max_append_entries: max_append_entries.unwrap_or(default.max_append_entries),
+        ..default
+    }
+}
+
+pub fn parse_max_append_entries(text: &str) -> Result<usize, String> {
+    match text.trim().parse() {
+        Ok(value) if value > 0 => Ok(value),
+        _ => Err(format!("invalid entry count `{}`", text.trim())),
+    }
+}
+
+impl Schedule {
+    pub fn generate(seed: u64, nodes: u64, len: usize) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        // Faults hit spare nodes too, once they have been added.
+        let ids: Vec<NodeId> = (1..=nodes + SPARE_NODES).map(NodeId::new).collect();
+        let mut steps = Vec::with_capacity(len + 2 + ids.len());
+
+        for i in 0..len {
+            let step = match rng.random_range(0..100) {
+                0..30 => {
+                    let key = KEYS[rng.random_range(0..KEYS.len())];
+                    Step::Propose(format!("{}=v{}", key, i).into_bytes())
+                }
+                30..38 => Step::Read(KEYS[rng.random_range(0..KEYS.len())].as_bytes().to_vec()),
+                38..43 => Step::Retry,
+                43..63 => Step::Advance(Duration::from_millis(rng.random_range(10..300))),
+                63..69 => {
+                    let mut shuffled = ids.clone();
+                    for j in (1..shuffled.len()).rev() {
+                        shuffled.swap(j, rng.random_range(0..=j));
+                    }
+                    let split = rng.random_range(1..shuffled.len().max(2));
+                    let (a, b) = shuffled.split_at(split.min(shuffled.len()));
+                    Step::Partition(vec![a.to_vec(), b.to_vec()])
+                }
+                69..75 => Step::Heal,
+                75..81 => Step::Crash(ids[rng.random_range(0..ids.len())]),
+                81..84 => Step::PowerFail(ids[rng.random_range(0..ids.len())]),
+                84..91 => Step::Restart(ids[rng.random_range(0..ids.len())]),
+                91..95 => Step::Add(ids[rng.random_range(0..ids.len())]),
+                _ => Step::Remove(ids[rng.random_range(0..ids.len())]),
+            };
+            steps.push(step);
+        }
+
+        steps.push(Step::Heal);
+        steps.extend(ids.iter().map(|&id| Step::Restart(id)));
+        steps.push(Step::Advance(SETTLE));
+
+        Self {
+            seed,
+            nodes,
+            max_append_entries: None,
+            steps,
+        }
+    }
+
+    pub fn parse(text: &str) -> Result<Self, ParseError> {
+        let mut seed = None;
+        let mut nodes = None;
+        let mut max_append_entries = None;
+        let mut steps = Vec::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let error = |message: String| ParseError {
+                line: i + 1,
+                message,
+            };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(value) = line.strip_prefix("seed ") {
+                let value = value.trim();
+                seed = Some(value.parse().map_err(|_| error(format!("invalid seed `{}`", value)))?);
+            } else if let Some(value) = line
+                .strip_prefix("start ")
+                .and_then(|rest| rest.strip_suffix(" nodes"))
+            {
+                let value = value.trim();
+                nodes = Some(value.parse().map_err(|_| error(format!("invalid node count `{}`", value)))?);
+            } else if let Some(value) = line.strip_prefix("max-append-entries ") {
+                max_append_entries = Some(parse_max_append_entries(value).map_err(error)?);
+            } else {
+                steps.push(line.parse().map_err(error)?);
+            }
+        }
+
+        let missing = |what: &str| ParseError {
+            line: 0,
+            message: format!("missing `{}` line", what),
+        };
+        Ok(Self {
+            seed: seed.ok_or_else(|| missing("seed"))?,
+            nodes: nodes.ok_or_else(|| missing("start"))?,
+            max_append_entries,
+            steps,
+        })
+    }
+}
+
+impl fmt::Display for Schedule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "seed {}", self.seed)?;
+        // Before `start`, which starts the cluster in a scenario.
+        if let Some(max) = self.max_append_entries {
+            writeln!(f, "max-append-entries {}", max)?;
+        }
+        writeln!(f, "start {} nodes", self.nodes)?;
+        for step in &self.steps {
+            writeln!(f, "{}", step)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Failure {
+    Invariant(Violation),
+    NonLinearizable(NonLinearizable),
+}
+
+impl Failure {
+    // Whether `other` is the same kind of failure, e.g. while shrinking, when
+    // a smaller schedule may fail at other nodes, terms and indexes.
+    pub fn same_kind(&self, other: &Failure) -> bool {
+        match (self, other) {
+            (Failure::Invariant(a), Failure::Invariant(b)) => {
+                std::mem::discriminant(a) == std::mem::discriminant(b)
+            }
+            (Failure::NonLinearizable(a), Failure::NonLinearizable(b)) => a.key == b.key,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Failure::Invariant(violation) => write!(f, "invariant violated: {}", violation),
+            Failure::NonLinearizable(failure) => write!(f, "{}", failure),
+        }
+    }
+}
+
+// A write waiting for its result, with what a retry has to resend.
+#[derive(Debug, Clone)]
+struct PendingWrite {
+    op: OpId,
+    sequence: u64,
+    command: Vec<u8>,
+}
+
+// Applies a schedule's steps to a simulator while recording the reads and
+// writes it issues. Each client has at most one write outstanding; a write
+// goes to the longest idle client, or to a new one if every client waits.
+pub struct Driver {
+    sim: Simulator,
+    history: History,
+    pending: BTreeMap<ClientId, PendingWrite>,
+    // Idle clients and the last sequence each used.
+    idle: BTreeMap<ClientId, u64>,
+    // Read ids are only unique per node and run.
+    reads: HashMap<(NodeId, ReadId), OpId>,
+    next_client: ClientId,
+}
+
+impl Driver {
+    pub fn new(nodes: u64, seed: u64) -> Self {
+        Self::with_config(nodes, RaftConfig::default(), seed)
+    }
+
+    pub fn with_config(nodes: u64, config: RaftConfig, seed: u64) -> Self {
+        let ids = (1..=nodes).map(NodeId::new).collect();
+        let mut sim = Simulator::with_config(ids, config, seed);
+        sim.record_violations();
+
+        Self {
+            sim,
+            history: History::new(),
+            pending: BTreeMap::new(),
+            idle: BTreeMap::new(),
+            reads: HashMap::new(),
+            next_client: 1,
+        }
+    }
+
+    pub fn sim(&self) -> &Simulator {
+        &self.sim
+    }
+
+    pub fn sim_mut(&mut self) -> &mut Simulator {
+        &mut self.sim
+    }
+
+    pub fn apply(&mut self, step: &Step) -> Result<(), Failure> {
+        match step {
+            Step::Propose(command) => {
+                self.propose(command);
+            }
+            Step::Read(key) => {
+                self.read(key);
+            }
+            Step::Retry => self.retry(),
+            Step::Add(id) => {
+                self.add(*id);
+            }
+            Step::Remove(id) => {
+                self.remove(*id);
+            }
+            Step::Advance(duration) => return self.advance(*duration),
+            Step::Partition(groups) => self.sim.partition(groups),
+            Step::Isolate(id) => self.sim.isolate(*id),
+            Step::Heal => self.sim.heal(),
+            Step::Crash(id) => self.crash(*id, false),
+            Step::PowerFail(id) => self.crash(*id, true),
+            Step::Restart(id) => self.sim.restart(*id),
+        }
+        self.check_invariants()
+    }
+
+    pub fn advance(&mut self, duration: Duration) -> Result<(), Failure> {
+        let until = self.sim.elapsed() + duration;
+        while self.sim.elapsed() < until {
+            self.tick()?;
+        }
+        Ok(())
+    }
+
+    pub fn tick(&mut self) -> Result<(), Failure> {
+        self.sim.tick();
+        self.collect_results();
+        self.check_invariants()
+    }
+
+    // Proposes through the current leader and returns it; without one the
+    // write is never invoked. Only `key=value` writes are checked for
+    // linearizability.
+    pub fn propose(&mut self, command: &[u8]) -> Option<NodeId> {
+        let now = self.sim.elapsed();
+        let (client_id, sequence) = match self.idle.pop_first() {
+            Some((client_id, last)) => (client_id, last + 1),
+            None => {
+                self.next_client += 1;
+                (self.next_client - 1, 1)
+            }
+        };
+
+        let leader = self.sim.find_leader();
+        let outcome = leader
+            .and_then(|leader| self.sim.runner_mut(leader))
+            .and_then(|runner| runner.propose(client_id, sequence, command.to_vec()));
+        if outcome != Some(ProposeOutcome::Queued) {
+            // Nothing was queued, so the sequence can be used again.
+            self.idle.insert(client_id, sequence - 1);
+            return None;
+        }
+
+        if let Some(input) = KvInput::from_command(command) {
+            let op = self.history.invoke(client_id, input, now);
+            self.pending.insert(
+                client_id,
+                PendingWrite {
+                    op,
+                    sequence,
+                    command: command.to_vec(),
+                },
+            );
+        } else {
+            self.idle.insert(client_id, sequence);
+        }
+        leader
+    }
+
+    // Sends the oldest unanswered write to the current leader again. The
+    // session makes sure it takes effect at most once.
+    pub fn retry(&mut self) {
+        let now = self.sim.elapsed();
+        let Some((&client_id, write)) = self.pending.first_key_value() else {
+            return;
+        };
+        let (sequence, command) = (write.sequence, write.command.clone());
+
+        let outcome = self
+            .sim
+            .find_leader()
+            .and_then(|leader| self.sim.runner_mut(leader))
+            .and_then(|runner| runner.propose(client_id, sequence, command));
+        if let Some(ProposeOutcome::Cached(response)) = outcome {
+            self.finish_write(client_id, Ok(response), now);
+        }
+    }
+
+    // A linearizable read through the current leader, by a client of its own.
+    pub fn read(&mut self, key: &[u8]) -> Option<ReadId> {
+        let now = self.sim.elapsed();
+        let leader = self.sim.find_leader()?;
+        let id = self
+            .sim
+            .runner_mut(leader)?
+            .read(key.to_vec(), ReadConsistency::LinearizableLeader)?;
+
+        let client_id = self.next_client;
+        self.next_client += 1;
+        let input = KvInput::Get { key: key.to_vec() };
+        let op = self.history.invoke(client_id, input, now);
+        self.reads.insert((leader, id), op);
+        Some(id)
+    }
+
+    // Starts `id` if it never ran and asks the leader to add it.
+    pub fn add(&mut self, id: NodeId) -> Option<LogIndex> {
+        self.sim.add_node(id);
+        let runner = self
+            .sim
+            .find_leader()
+            .and_then(|leader| self.sim.runner_mut(leader))?;
+
+        let mut members = runner.node().members().to_vec();
+        if members.contains(&id) {
+            return None;
+        }
+        members.push(id);
+        runner.change_membership(members)
+    }
+
+    // Asks the leader to remove `id`, which it refuses for itself.
+    pub fn remove(&mut self, id: NodeId) -> Option<LogIndex> {
+        let runner = self
+            .sim
+            .find_leader()
+            .and_then(|leader| self.sim.runner_mut(leader))?;
+
+        let mut members = runner.node().members().to_vec();
+        members.retain(|&member| member != id);
+        runner.change_membership(members)
+    }
+
+    // Reads the node had not answered are lost with it.
+    fn crash(&mut self, id: NodeId, power_failure: bool) {
+        if power_failure {
+            self.sim.crash_losing_unsynced(id);
+        } else {
+            self.sim.crash(id);
+        }
+        self.reads.retain(|&(node, _), _| node != id);
+    }
+
+    fn finish_write(
+        &mut self,
+        client_id: ClientId,
+        response: Result<Vec<u8>, SessionExpired>,
+        now: Duration,
+    ) {
+        let Some(write) = self.pending.remove(&client_id) else {
+            return;
+        };
+        // An expired session leaves the write's outcome unknown, and the
+        // client cannot go on.
+        if let Ok(response) = response {
+            self.history.complete(write.op, response, now);
+            self.idle.insert(client_id, write.sequence);
+        }
+    }
+
+    pub fn check_history(&self) -> Result<(), Failure> {
+        self.history.check().map_err(Failure::NonLinearizable)
+    }
+
+    fn check_invariants(&self) -> Result<(), Failure> {
+        match self.sim.violation() {
+            Some(violation) => Err(Failure::Invariant(violation.clone())),
+            None => Ok(()),
+        }
+    }
+
+    fn collect_results(&mut self) {
+        let now = self.sim.elapsed();
+        for id in self.sim.node_ids() {
+            let Some(runner) = self.sim.runner_mut(id) else {
+                continue;
+            };
+            let results = runner.take_command_results();
+            let reads = runner.take_read_results();
+
+            for result in results {
+                let current = self
+                    .pending
+                    .get(&result.client_id)
+                    .is_some_and(|write| write.sequence == result.sequence);
+                if current {
+                    self.finish_write(result.client_id, result.response, now);
+                }
+            }
+            for (read_id, result) in reads {
+                if let Some(op) = self.reads.remove(&(id, read_id))
+                    && let Ok(value) = result
+                {
+                    self.history.complete(op, value, now);
+                }
+            }
+        }
+    }
+}
+
+pub fn run(schedule: &Schedule) -> Result<(), Failure> {
+    let config = config(schedule.max_append_entries);
+    let mut driver = Driver::with_config(schedule.nodes, config, schedule.seed);
+    for step in &schedule.steps {
+        driver.apply(step)?;
+    }
+    driver.check_history()
+}
+
+// Generates the schedule for `seed` and runs it; see `check`.
+pub fn check_seed(seed: u64, nodes: u64, len: usize) -> Result<(), (Schedule, Failure)> {
+    check(Schedule::generate(seed, nodes, len))
+}
+
+// Runs `schedule`. A failing schedule is shrunk before it is returned, to one
+// that still fails the same way rather than one that trips over something
+// else.
+pub fn check(schedule: Schedule) -> Result<(), (Schedule, Failure)> {
+    let Err(failure) = run(&schedule) else {
+        return Ok(());
+    };
+
+    let shrunk = shrink(&schedule, |candidate| {
+        run(candidate).is_err_and(|other| other.same_kind(&failure))
+    });
+    let failure = run(&shrunk).unwrap_err();
+    Err((shrunk, failure))
+}
+
+// Removes chunks of steps, halving the chunk size whenever no chunk can go,
+// then shortens the remaining advances, keeping every change after which the
+// schedule still fails.
+pub fn shrink(schedule: &Schedule, mut fails: impl FnMut(&Schedule) -> bool) -> Schedule {
+    let mut current = schedule.clone();
+    let mut chunk = (current.steps.len() / 2).max(1);
+
+    loop {
+        let mut removed = false;
+        let mut start = 0;
+        while start < current.steps.len() {
+            let end = (start + chunk).min(current.steps.len());
+            let mut candidate = current.clone();
+            candidate.steps.drain(start..end);
+
+            if fails(&candidate) {
+                current = candidate;
+                removed = true;
+            } else {
+                start += chunk;
+            }
+        }
+
+        if !removed {
+            if chunk == 1 {
+                break;
+            }
+            chunk /= 2;
+        }
+    }
+
+    for i in 0..current.steps.len() {
+        while let Step::Advance(duration) = current.steps[i] {
+            let shorter = Duration::from_millis(duration.as_millis() as u64 / 2);
+            if shorter.is_zero() {
+                break;
+            }
+            let mut candidate = current.clone();
+            candidate.steps[i] = Step::Advance(shorter);
+            if !fails(&candidate) {
+                break;
+            }
+            current = candidate;
+        }
+    }
+
+    current
+}
@@ -0,0 +1,382 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+
+use hyper_util::rt::TokioIo;
+use tokio::net::{TcpListener, UnixListener, UnixStream};
+use tokio::sync::mpsc;
+use tokio::time;
+use tokio_stream::wrappers::{TcpListenerStream, UnixListenerStream};
+use tonic::transport::{Channel, ClientTlsConfig, Endpoint, Server, Uri};
+use tonic::{Code, Request, Response, Status};
+use tower::service_fn;
+
+use crate::event::RaftEvent;
+use crate::handshake::{Features, Handshake, Negotiated};
+use crate::peer::{PeerConfig, PeerManager};
+use crate::raft::RaftAction;
+use crate::raft_proto::{raft_client::RaftClient, raft_server};
+use crate::rpc;
+use crate::server::{PendingReplies, RaftServer};
+use crate::tls::TlsConfig;
+use crate::transport::Transport;
+use crate::types::{LogIndex, NodeId};
+
+// Where a node serves its gRPC endpoint: `host:port` for TCP, or
+// `unix:<path>` for a Unix domain socket.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PeerAddr {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl FromStr for PeerAddr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix("unix:") {
+            Some(path) if !path.is_empty() => Ok(PeerAddr::Unix(PathBuf::from(path))),
+            Some(_) => Err(format!("missing socket path in '{}'", s)),
+            None => s
+                .parse()
+                .map(PeerAddr::Tcp)
+                .map_err(|e| format!("bad address '{}': {}", s, e)),
+        }
+    }
+}
+
+impl fmt::Display for PeerAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PeerAddr::Tcp(addr) => write!(f, "{}", addr),
+            PeerAddr::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+pub enum Listener {
+    Tcp(TcpListener),
+    Unix(UnixListener),
+}
+
+impl Listener {
+    pub async fn bind(addr: &PeerAddr) -> io::Result<Self> {
+        match addr {
+            PeerAddr::Tcp(addr) => TcpListener::bind(addr).await.map(Listener::Tcp),
+            PeerAddr::Unix(path) => UnixListener::bind(path).map(Listener::Unix),
+        }
+    }
+
+    // The bound address, with the actual port when binding to port 0.
+    pub fn local_addr(&self) -> io::Result<PeerAddr> {
+        match self {
+            Listener::Tcp(listener) => listener.local_addr().map(PeerAddr::Tcp),
+            Listener::Unix(listener) => {
+                let addr = listener.local_addr()?;
+                let path = addr
+                    .as_pathname()
+                    .ok_or_else(|| io::Error::other("unnamed unix socket"))?;
+                Ok(PeerAddr::Unix(path.to_path_buf()))
+            }
+        }
+    }
+}
+
+// Protocol version and features negotiated with each peer, learned from
+// every exchange in either direction. A peer not heard from yet is assumed
+// to support everything.
+#[derive(Clone, Default)]
+pub struct PeerFeatures(Arc<Mutex<HashMap<NodeId, Negotiated>>>);
+
+impl PeerFeatures {
+    pub fn get(&self, peer: NodeId) -> Option<Features> {
+        self.0.lock().unwrap().get(&peer).map(|negotiated| negotiated.features)
+    }
+
+    pub fn version(&self, peer: NodeId) -> Option<u32> {
+        self.0.lock().unwrap().get(&peer).map(|negotiated| negotiated.version)
+    }
+
+    pub(crate) fn insert(&self, peer: NodeId, negotiated: Negotiated) {
+        self.0.lock().unwrap().insert(peer, negotiated);
+    }
+}
+
+// Transport over gRPC. Each outgoing request is a unary call whose reply
+// comes back through `recv`; a failed call drops the message. Incoming calls
+// are served by `serve` and wait until the node answers them. Every call
+// carries the node's handshake, and calls from other clusters or too old
+// protocol versions are refused.
+pub struct GrpcTransport {
+    inbox: mpsc::UnboundedReceiver<RaftEvent>,
+    events: mpsc::UnboundedSender<RaftEvent>,
+    peers: PeerManager,
+    replies: Arc<Mutex<PendingReplies>>,
+    handshake: Handshake,
+    features: PeerFeatures,
+    tls: Option<TlsConfig>,
+}
+
+impl GrpcTransport {
+    // Peers are connected lazily on first use, so they need not be up yet.
+    // Must be called within a tokio runtime.
+    pub fn new(handshake: Handshake, peers: &BTreeMap<NodeId, PeerAddr>) -> Self {
+        Self::build(handshake, peers, None).expect("plaintext channels need no tls setup")
+    }
+
+    // Mutual TLS on both the peer connections and the served endpoint.
+    pub fn with_tls(
+        handshake: Handshake,
+        peers: &BTreeMap<NodeId, PeerAddr>,
+        tls: TlsConfig,
+    ) -> Result<Self, tonic::transport::Error> {
+        Self::build(handshake, peers, Some(tls))
+    }
+
+    fn build(
+        handshake: Handshake,
+        peers: &BTreeMap<NodeId, PeerAddr>,
+        tls: Option<TlsConfig>,
+    ) -> Result<Self, tonic::transport::Error> {
+        let (events, inbox) = mpsc::unbounded_channel();
+        let clients = peers
+            .iter()
+            .map(|(&id, addr)| {
+                let tls = tls.as_ref().map(|tls| tls.client(id));
+                Ok((id, RaftClient::new(channel(addr, tls)?)))
+            })
+            .collect::<Result<_, tonic::transport::Error>>()?;
+
+        Ok(Self {
+            inbox,
+            events,
+            peers: PeerManager::new(PeerConfig::default(), clients),
+            replies: Arc::new(Mutex::new(PendingReplies::default())),
+            handshake,
+            features: PeerFeatures::default(),
+            tls,
+        })
+    }
+
+    pub fn with_peer_config(mut self, config: PeerConfig) -> Self {
+        self.peers = self.peers.reconfigured(config);
+        self
+    }
+
+    // Each peer's connection health, e.g. for the leader to see which
+    // followers it can reach.
+    pub fn peers(&self) -> PeerManager {
+        self.peers.clone()
+    }
+
+    pub fn features(&self) -> PeerFeatures {
+        self.features.clone()
+    }
+
+    // Serves this node's endpoint until the future is dropped.
+    pub fn serve(
+        &self,
+        listener: Listener,
+    ) -> impl Future<Output = Result<(), tonic::transport::Error>> + Send + 'static {
+        let service = raft_server::RaftServer::new(RaftServer::new(
+            self.events.clone(),
+            self.replies.clone(),
+            self.tls.is_some(),
+            self.handshake.clone(),
+            self.features.clone(),
+        ));
+        let tls = self.tls.as_ref().map(TlsConfig::server);
+
+        async move {
+            let mut server = Server::builder();
+            if let Some(tls) = tls {
+                server = server.tls_config(tls)?;
+            }
+            let router = server.add_service(service);
+            match listener {
+                Listener::Tcp(listener) => {
+                    router
+                        .serve_with_incoming(TcpListenerStream::new(listener))
+                        .await
+                }
+                Listener::Unix(listener) => {
+                    router
+                        .serve_with_incoming(UnixListenerStream::new(listener))
+                        .await
+                }
+            }
+        }
+    }
+
+    fn supports(&self, peer: NodeId, feature: Features) -> bool {
+        self.handshake.features.contains(feature)
+            && self
+                .features
+                .get(peer)
+                .is_none_or(|shared| shared.contains(feature))
+    }
+
+    // Runs one call in the background and feeds its reply to the node.
+    // `reply` gets `None` when the peer is not worth a call right now, or
+    // when the call fails, times out, or comes back without an acceptable
+    // handshake or decodable body.
+    fn call<Req, Resp, Fut>(
+        &self,
+        to: NodeId,
+        message: Req,
+        rpc: impl FnOnce(RaftClient<Channel>, Request<Req>) -> Fut + Send + 'static,
+        reply: impl FnOnce(Option<Resp>) -> Option<RaftEvent> + Send + 'static,
+    ) where
+        Req: Send + 'static,
+        Fut: Future<Output = Result<Response<Resp>, Status>> + Send,
+    {
+        let events = self.events.clone();
+        let Some((client, call)) = self.peers.start(to) else {
+            if let Some(event) = reply(None) {
+                let _ = events.send(event);
+            }
+            return;
+        };
+
+        let timeout = self.peers.config().rpc_timeout;
+        let mut request = Request::new(message);
+        request.set_timeout(timeout);
+        self.handshake.write(request.metadata_mut());
+
+        let handshake = self.handshake.clone();
+        let features = self.features.clone();
+        tokio::spawn(async move {
+            // A peer that answered with an error is up, so only transport
+            // errors and timeouts count against it.
+            let event = match time::timeout(timeout, rpc(client, request)).await {
+                Ok(Ok(response)) => {
+                    call.succeeded();
+                    let negotiated = Handshake::read(response.metadata())
+                        .and_then(|theirs| handshake.accept(&theirs));
+                    match negotiated {
+                        Ok(negotiated) => {
+                            features.insert(to, negotiated);
+                            reply(Some(response.into_inner()))
+                        }
+                        Err(_) => reply(None),
+                    }
+                }
+                Ok(Err(status)) if !unreachable(&status) => {
+                    call.succeeded();
+                    reply(None)
+                }
+                _ => {
+                    call.failed();
+                    reply(None)
+                }
+            };
+            if let Some(event) = event {
+                let _ = events.send(event);
+            }
+        });
+    }
+}
+
+// Tonic reports the request's own deadline as cancelled.
+fn unreachable(status: &Status) -> bool {
+    matches!(status.code(), Code::Unavailable | Code::DeadlineExceeded | Code::Cancelled)
+}
+
+impl Transport for GrpcTransport {
+    fn send(&mut self, action: RaftAction) {
+        match action {
+            RaftAction::SendRequestVote(to, req) => self.call(
+                to,
+                req.into(),
+                |mut client, request| async move { client.request_vote(request).await },
+                move |resp| {
+                    let resp = resp?.try_into().ok()?;
+                    Some(RaftEvent::ReceivedRequestVoteResponse(to, resp))
+                },
+            ),
+            RaftAction::SendAppendEntries(to, req) => self.call(
+                to,
+                req.into(),
+                |mut client, request| async move { client.append_entries(request).await },
+                move |resp| {
+                    let resp = resp?.try_into().ok()?;
+                    Some(RaftEvent::ReceivedAppendEntriesResponse(to, resp))
+                },
+            ),
+            // A read the leader cannot be asked about is refused on the
+            // spot, so it fails fast and the client can go to the leader
+            // instead: when either side lacks ReadIndex, or the call fails.
+            RaftAction::SendReadIndex(to, req) => {
+                let refused = rpc::ReadIndexResponse {
+                    term: req.term,
+                    request_id: req.request_id,
+                    success: false,
+                    read_index: LogIndex::ZERO,
+                };
+                if !self.supports(to, Features::READ_INDEX) {
+                    let _ = self
+                        .events
+                        .send(RaftEvent::ReceivedReadIndexResponse(refused));
+                    return;
+                }
+                self.call(
+                    to,
+                    req.into(),
+                    |mut client, request| async move { client.read_index(request).await },
+                    move |resp| {
+                        let resp = resp.and_then(|resp| resp.try_into().ok());
+                        Some(RaftEvent::ReceivedReadIndexResponse(
+                            resp.unwrap_or(refused),
+                        ))
+                    },
+                )
+            }
+            RaftAction::SendRequestVoteResponse(to, resp) => {
+                self.replies.lock().unwrap().reply_vote(to, resp)
+            }
+            RaftAction::SendAppendEntriesResponse(to, resp) => {
+                self.replies.lock().unwrap().reply_append(to, resp)
+            }
+            RaftAction::SendReadIndexResponse(to, resp) => {
+                self.replies.lock().unwrap().reply_read(to, resp)
+            }
+        }
+    }
+
+    fn recv(&mut self) -> impl Future<Output = Option<RaftEvent>> + Send {
+        self.inbox.recv()
+    }
+}
+
+fn channel(
+    addr: &PeerAddr,
+    tls: Option<ClientTlsConfig>,
+) -> Result<Channel, tonic::transport::Error> {
+    let scheme = if tls.is_some() { "https" } else { "http" };
+    // For Unix sockets the host is unused: the connector dials the path.
+    let host = match addr {
+        PeerAddr::Tcp(addr) => addr.to_string(),
+        PeerAddr::Unix(_) => "localhost".to_string(),
+    };
+    let mut endpoint = Endpoint::from_shared(format!("{}://{}", scheme, host))
+        .expect("socket address is a valid uri");
+    if let Some(tls) = tls {
+        endpoint = endpoint.tls_config(tls)?;
+    }
+
+    Ok(match addr {
+        PeerAddr::Tcp(_) => endpoint.connect_lazy(),
+        PeerAddr::Unix(path) => {
+            let path = path.clone();
+            endpoint.connect_with_connector_lazy(service_fn(move |_: Uri| {
+                let path = path.clone();
+                async move { UnixStream::connect(path).await.map(TokioIo::new) }
+            }))
+        }
+    })
+}
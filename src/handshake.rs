@@ -0,0 +1,191 @@
+use std::fmt;
+
+use tonic::Status;
+use tonic::metadata::{MetadataMap, MetadataValue};
+
+// Wire protocol spoken by this build, and the oldest one it still accepts.
+// A node only rejects peers older than its minimum; a newer peer sees this
+// node's version and is the one to speak down to it.
+pub const PROTOCOL_VERSION: u32 = 1;
+pub const MIN_PROTOCOL_VERSION: u32 = 1;
+
+const CLUSTER_ID: &str = "x-raft-cluster-id";
+const VERSION: &str = "x-raft-protocol-version";
+const FEATURES: &str = "x-raft-features";
+
+// Optional parts of the protocol. A node uses one with a peer only once both
+// have advertised it, so mixed-version clusters keep working during a
+// rolling upgrade.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Features(u64);
+
+impl Features {
+    pub const NONE: Features = Features(0);
+    // Followers forward linearizable reads to the leader over ReadIndex.
+    pub const READ_INDEX: Features = Features(1 << 0);
+
+    // Everything this build supports.
+    pub const ALL: Features = Features::READ_INDEX;
+
+    const NAMES: [(Features, &'static str); 1] = [(Features::READ_INDEX, "read-index")];
+
+    pub fn contains(self, other: Features) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    pub fn union(self, other: Features) -> Features {
+        Features(self.0 | other.0)
+    }
+
+    pub fn intersection(self, other: Features) -> Features {
+        Features(self.0 & other.0)
+    }
+
+    // Names this build does not know are skipped: they come from newer peers.
+    fn parse(names: &str) -> Features {
+        names
+            .split(',')
+            .filter_map(|name| {
+                Self::NAMES
+                    .iter()
+                    .find(|&&(_, known)| known == name.trim())
+                    .map(|&(feature, _)| feature)
+            })
+            .fold(Features::NONE, Features::union)
+    }
+}
+
+impl fmt::Display for Features {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let names: Vec<_> = Self::NAMES
+            .iter()
+            .filter(|&&(feature, _)| self.contains(feature))
+            .map(|&(_, name)| name)
+            .collect();
+        write!(f, "{}", names.join(","))
+    }
+}
+
+// What a node states about itself on every RPC, request and response alike.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Handshake {
+    pub cluster_id: String,
+    pub version: u32,
+    pub features: Features,
+}
+
+// What two nodes agreed on: the lower of their protocol versions, which both
+// speak, and the features both have.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Negotiated {
+    pub version: u32,
+    pub features: Features,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HandshakeError {
+    Missing(&'static str),
+    Malformed(&'static str),
+    ClusterMismatch { ours: String, theirs: String },
+    UnsupportedVersion { version: u32, min: u32 },
+}
+
+impl fmt::Display for HandshakeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HandshakeError::Missing(key) => write!(f, "missing {} metadata", key),
+            HandshakeError::Malformed(key) => write!(f, "malformed {} metadata", key),
+            HandshakeError::ClusterMismatch { ours, theirs } => write!(
+                f,
+                "cluster id mismatch: this node belongs to '{}', the peer to '{}'",
+                ours, theirs
+            ),
+            HandshakeError::UnsupportedVersion { version, min } => write!(
+                f,
+                "protocol version {} is no longer supported (minimum {})",
+                version, min
+            ),
+        }
+    }
+}
+
+impl From<HandshakeError> for Status {
+    fn from(error: HandshakeError) -> Self {
+        match error {
+            HandshakeError::Missing(_) | HandshakeError::Malformed(_) => {
+                Status::invalid_argument(error.to_string())
+            }
+            HandshakeError::ClusterMismatch { .. } | HandshakeError::UnsupportedVersion { .. } => {
+                Status::failed_precondition(error.to_string())
+            }
+        }
+    }
+}
+
+impl Handshake {
+    pub fn new(cluster_id: impl Into<String>) -> Self {
+        Self::with_features(cluster_id, Features::ALL)
+    }
+
+    pub fn with_features(cluster_id: impl Into<String>, features: Features) -> Self {
+        Self {
+            cluster_id: cluster_id.into(),
+            version: PROTOCOL_VERSION,
+            features,
+        }
+    }
+
+    pub fn write(&self, metadata: &mut MetadataMap) {
+        // An id that is not a valid header value is left out, and peers
+        // refuse the call for missing it.
+        if let Ok(cluster_id) = MetadataValue::try_from(self.cluster_id.as_str()) {
+            metadata.insert(CLUSTER_ID, cluster_id);
+        }
+        metadata.insert(VERSION, MetadataValue::from(self.version));
+        if let Ok(features) = MetadataValue::try_from(self.features.to_string()) {
+            metadata.insert(FEATURES, features);
+        }
+    }
+
+    pub fn read(metadata: &MetadataMap) -> Result<Self, HandshakeError> {
+        let get = |key: &'static str| {
+            metadata
+                .get(key)
+                .ok_or(HandshakeError::Missing(key))?
+                .to_str()
+                .map_err(|_| HandshakeError::Malformed(key))
+        };
+
+        Ok(Self {
+            cluster_id: get(CLUSTER_ID)?.to_string(),
+            version: get(VERSION)?
+                .parse()
+                .map_err(|_| HandshakeError::Malformed(VERSION))?,
+            features: metadata
+                .get(FEATURES)
+                .and_then(|value| value.to_str().ok())
+                .map_or(Features::NONE, Features::parse),
+        })
+    }
+
+    // Checks a peer's handshake against this node's, and returns the version
+    // and features both sides may use.
+    pub fn accept(&self, peer: &Handshake) -> Result<Negotiated, HandshakeError> {
+        if peer.cluster_id != self.cluster_id {
+            return Err(HandshakeError::ClusterMismatch {
+                ours: self.cluster_id.clone(),
+                theirs: peer.cluster_id.clone(),
+            });
+        }
+        if peer.version < MIN_PROTOCOL_VERSION {
+            return Err(HandshakeError::UnsupportedVersion {
+                version: peer.version,
+                min: MIN_PROTOCOL_VERSION,
+            });
+        }
+        Ok(Negotiated {
+            version: self.version.min(peer.version),
+            features: self.features.intersection(peer.features),
+        })
+    }
+}
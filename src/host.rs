@@ -0,0 +1,56 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::time::{self, MissedTickBehavior};
+
+use crate::raft::RaftRunner;
+use crate::transport::Transport;
+
+// How often the event loop ticks when no message arrives.
+pub const TICK_INTERVAL: Duration = Duration::from_millis(5);
+
+// Runs a node's event loop on real time, exchanging messages with its peers
+// through any `Transport`.
+pub struct NodeHost<T: Transport> {
+    runner: Arc<Mutex<RaftRunner>>,
+    transport: T,
+}
+
+impl<T: Transport> NodeHost<T> {
+    pub fn new(runner: RaftRunner, transport: T) -> Self {
+        Self {
+            runner: Arc::new(Mutex::new(runner)),
+            transport,
+        }
+    }
+
+    // For client requests and inspection while the host runs. The event
+    // loop takes the lock on every message and tick, so hold it briefly.
+    pub fn runner(&self) -> Arc<Mutex<RaftRunner>> {
+        self.runner.clone()
+    }
+
+    // Runs until the transport shuts down.
+    pub async fn run(mut self) {
+        let mut ticker = time::interval(TICK_INTERVAL);
+        ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+        loop {
+            tokio::select! {
+                event = self.transport.recv() => match event {
+                    Some(event) => self.runner.lock().unwrap().push_event(event),
+                    None => return,
+                },
+                _ = ticker.tick() => {}
+            }
+
+            let actions = self.runner.lock().unwrap().tick();
+            for action in actions {
+                self.transport.send(action);
+            }
+            // The leader's new entries are on their way to followers while
+            // they are synced here.
+            self.runner.lock().unwrap().sync();
+        }
+    }
+}
@@ -0,0 +1,268 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::hash::{DefaultHasher, Hash, Hasher};
+
+use crate::log::LogEntry;
+use crate::node::RaftNode;
+use crate::types::{LogIndex, NodeId, Term};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Violation {
+    ElectionSafety { term: Term, leaders: (NodeId, NodeId) },
+    LogMatching { nodes: (NodeId, NodeId), index: LogIndex },
+    LeaderCompleteness { leader: NodeId, term: Term, index: LogIndex },
+    StateMachineSafety { node: NodeId, index: LogIndex },
+    TermRegressed { node: NodeId, from: Term, to: Term },
+    CommitRegressed { node: NodeId, from: LogIndex, to: LogIndex },
+}
+
+impl fmt::Display for Violation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Violation::ElectionSafety { term, leaders } => write!(
+                f,
+                "Election Safety: nodes {:?} and {:?} both leader in term {:?}",
+                leaders.0, leaders.1, term
+            ),
+            Violation::LogMatching { nodes, index } => write!(
+                f,
+                "Log Matching: nodes {:?} and {:?} agree on the term at index {:?} but not on the entries up to it",
+                nodes.0, nodes.1, index
+            ),
+            Violation::LeaderCompleteness { leader, term, index } => write!(
+                f,
+                "Leader Completeness: leader {:?} of term {:?} is missing committed index {:?}",
+                leader, term, index
+            ),
+            Violation::StateMachineSafety { node, index } => write!(
+                f,
+                "State Machine Safety: node {:?} committed a different entry at index {:?}",
+                node, index
+            ),
+            Violation::TermRegressed { node, from, to } => write!(
+                f,
+                "node {:?} current_term went from {:?} to {:?}",
+                node, from, to
+            ),
+            Violation::CommitRegressed { node, from, to } => write!(
+                f,
+                "node {:?} commit_index went from {:?} to {:?}",
+                node, from, to
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct CommittedEntry {
+    entry: LogEntry,
+    // Term of the first node seen committing it; never below the term the
+    // entry was actually committed in.
+    observed_term: Term,
+}
+
+// What any log has held at one index and term. Log Matching follows from
+// every such pair always carrying the same command and previous term.
+#[derive(Debug, Clone, Copy)]
+struct SeenEntry {
+    node: NodeId,
+    command: u64,
+    prev_term: Term,
+}
+
+// How far a node's state has been checked. Everything past these marks, or
+// changed since, is checked on the node's next step.
+#[derive(Debug, Clone, Copy, Default)]
+struct Checked {
+    log: LogIndex,
+    commit: LogIndex,
+    // Committed indexes found in the log while leading in `term`.
+    complete: LogIndex,
+    term: Term,
+}
+
+impl Checked {
+    fn rewind(&mut self, changed_from: LogIndex) {
+        let before = LogIndex::new(changed_from.get().saturating_sub(1));
+        self.log = self.log.min(before);
+        self.commit = self.commit.min(before);
+        self.complete = self.complete.min(before);
+    }
+}
+
+// Checks Raft's safety properties as each node of a running cluster takes a
+// step. Committed entries, leaders per term, each node's term and commit
+// index and every (index, term) seen are remembered across checks, so
+// violations spanning time or nodes are caught while each check only looks
+// at what changed.
+#[derive(Debug, Default)]
+pub struct InvariantChecker {
+    leaders: BTreeMap<Term, NodeId>,
+    committed: Vec<CommittedEntry>,
+    terms: BTreeMap<NodeId, Term>,
+    commits: BTreeMap<NodeId, LogIndex>,
+    seen: BTreeMap<(LogIndex, Term), SeenEntry>,
+    checked: BTreeMap<NodeId, Checked>,
+}
+
+impl InvariantChecker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // The commit index is volatile, so a restarted node may start over at 0,
+    // and its log is checked afresh.
+    pub fn forget_volatile(&mut self, id: NodeId) {
+        self.commits.remove(&id);
+        self.checked.remove(&id);
+    }
+
+    // Checks `node`, which just took a step, given the lowest index its log
+    // changed at since its last check. Its first check covers the whole log.
+    pub fn check(&mut self, node: &RaftNode, changed_from: Option<LogIndex>) -> Result<(), Violation> {
+        self.check_monotonic(node)?;
+        self.check_election_safety(node)?;
+
+        let mut checked = self.checked.get(&node.id).copied().unwrap_or_default();
+        if let Some(from) = changed_from {
+            checked.rewind(from);
+        }
+        self.check_log_matching(node, &mut checked)?;
+        self.check_committed(node, &mut checked)?;
+        if node.is_leader() {
+            self.check_leader_completeness(node, &mut checked)?;
+        }
+        self.checked.insert(node.id, checked);
+
+        Ok(())
+    }
+
+    fn check_monotonic(&mut self, node: &RaftNode) -> Result<(), Violation> {
+        let term = self.terms.entry(node.id).or_insert(node.current_term);
+        if node.current_term < *term {
+            return Err(Violation::TermRegressed {
+                node: node.id,
+                from: *term,
+                to: node.current_term,
+            });
+        }
+        *term = node.current_term;
+
+        let commit = self.commits.entry(node.id).or_insert(node.commit_index);
+        if node.commit_index < *commit {
+            return Err(Violation::CommitRegressed {
+                node: node.id,
+                from: *commit,
+                to: node.commit_index,
+            });
+        }
+        *commit = node.commit_index;
+
+        Ok(())
+    }
+
+    fn check_election_safety(&mut self, node: &RaftNode) -> Result<(), Violation> {
+        if !node.is_leader() {
+            return Ok(());
+        }
+
+        let leader = *self.leaders.entry(node.current_term).or_insert(node.id);
+        if leader != node.id {
+            return Err(Violation::ElectionSafety {
+                term: node.current_term,
+                leaders: (leader, node.id),
+            });
+        }
+
+        Ok(())
+    }
+
+    // If two logs hold an entry with the same index and term, they must be
+    // identical up to and including that index. Checked one entry at a time:
+    // the same index and term must always come with the same command and the
+    // same term before it.
+    fn check_log_matching(&mut self, node: &RaftNode, checked: &mut Checked) -> Result<(), Violation> {
+        let from = LogIndex::new(checked.log.get() + 1).max(node.log.first_index());
+        let mut prev_term = node.log.term_at(LogIndex::new(from.get() - 1));
+
+        for entry in node.log.entries_from(from).iter() {
+            let seen = SeenEntry {
+                node: node.id,
+                command: digest(&entry.command),
+                prev_term: prev_term.unwrap_or(Term::ZERO),
+            };
+            let first = *self.seen.entry((entry.index, entry.term)).or_insert(seen);
+            if first.command != seen.command || first.prev_term != seen.prev_term {
+                return Err(Violation::LogMatching {
+                    nodes: (first.node, node.id),
+                    index: entry.index,
+                });
+            }
+            prev_term = Some(entry.term);
+        }
+
+        checked.log = node.log.last_log_index();
+        Ok(())
+    }
+
+    // Every node must agree with what any node has committed before, and
+    // its own committed prefix must still be in its log.
+    fn check_committed(&mut self, node: &RaftNode, checked: &mut Checked) -> Result<(), Violation> {
+        for index in checked.commit.get() + 1..=node.commit_index.get() {
+            let index = LogIndex::new(index);
+            let Some(entry) = node.log.get(index) else {
+                return Err(Violation::StateMachineSafety {
+                    node: node.id,
+                    index,
+                });
+            };
+
+            match self.committed.get(index.get() as usize - 1) {
+                Some(committed) if committed.entry != *entry => {
+                    return Err(Violation::StateMachineSafety {
+                        node: node.id,
+                        index,
+                    });
+                }
+                Some(_) => {}
+                None => self.committed.push(CommittedEntry {
+                    entry: entry.into_owned(),
+                    observed_term: node.current_term,
+                }),
+            }
+        }
+
+        checked.commit = checked.commit.max(node.commit_index);
+        Ok(())
+    }
+
+    fn check_leader_completeness(&self, leader: &RaftNode, checked: &mut Checked) -> Result<(), Violation> {
+        if checked.term != leader.current_term {
+            checked.term = leader.current_term;
+            checked.complete = LogIndex::ZERO;
+        }
+
+        let missing = self.committed[checked.complete.get() as usize..]
+            .iter()
+            .filter(|committed| committed.observed_term < leader.current_term)
+            .find(|committed| leader.log.get(committed.entry.index).as_deref() != Some(&committed.entry));
+
+        match missing {
+            Some(committed) => Err(Violation::LeaderCompleteness {
+                leader: leader.id,
+                term: leader.current_term,
+                index: committed.entry.index,
+            }),
+            None => {
+                checked.complete = LogIndex::new(self.committed.len() as u64);
+                Ok(())
+            }
+        }
+    }
+}
+
+fn digest(command: &[u8]) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    command.hash(&mut hasher);
+    hasher.finish()
+}
@@ -1,5 +1,9 @@
 pub mod types;
 
+pub mod clock;
+
+pub mod config;
+
 pub mod rpc;
 
 pub mod log;
@@ -12,10 +16,52 @@ pub mod event;
 
 pub mod raft;
 
+pub mod network;
+
+pub mod read_index;
+
+pub mod proposal;
+
+pub mod session;
+
+pub mod state_machine;
+
+pub mod trace;
+
+pub mod storage;
+
+pub mod segment;
+
+pub mod invariants;
+
+pub mod spec;
+
+pub mod linearizability;
+
 pub mod simulator;
 
+pub mod fuzz;
+
+pub mod scenario;
+
+pub mod tui;
+
+pub mod transport;
+
+pub mod host;
+
 pub mod raft_proto {
     tonic::include_proto!("raft");
 }
 
-pub mod server;
\ No newline at end of file
+pub mod handshake;
+
+pub mod peer;
+
+pub mod tls;
+
+pub mod codec;
+
+pub mod server;
+
+pub mod grpc;
\ No newline at end of file
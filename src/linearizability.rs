@@ -0,0 +1,286 @@
+use std::collections::{BTreeMap, HashSet};
+use std::fmt;
+use std::time::Duration;
+
+use crate::session::ClientId;
+
+pub type OpId = usize;
+
+// Operations on the `KvStore` model: a put returns the previous value and a
+// get returns the current one, with a missing key reading as empty.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum KvInput {
+    Put { key: Vec<u8>, value: Vec<u8> },
+    Get { key: Vec<u8> },
+}
+
+impl KvInput {
+    // Parses a `key=value` command as a put.
+    pub fn from_command(command: &[u8]) -> Option<Self> {
+        let split = command.iter().position(|&b| b == b'=')?;
+        Some(KvInput::Put {
+            key: command[..split].to_vec(),
+            value: command[split + 1..].to_vec(),
+        })
+    }
+
+    pub fn key(&self) -> &[u8] {
+        match self {
+            KvInput::Put { key, .. } | KvInput::Get { key } => key,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Operation {
+    pub client_id: ClientId,
+    pub input: KvInput,
+    // `None` while the operation has not completed, in which case it may or
+    // may not have taken effect, or when the output is not checked.
+    pub output: Option<Vec<u8>>,
+    pub call: Duration,
+    pub ret: Option<Duration>,
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.input {
+            KvInput::Put { key, value } => write!(
+                f,
+                "client {}: put({}, {})",
+                self.client_id,
+                String::from_utf8_lossy(key),
+                String::from_utf8_lossy(value)
+            )?,
+            KvInput::Get { key } => write!(
+                f,
+                "client {}: get({})",
+                self.client_id,
+                String::from_utf8_lossy(key)
+            )?,
+        }
+
+        match (&self.output, self.ret) {
+            (Some(output), Some(ret)) => write!(
+                f,
+                " -> {:?} [{:?}, {:?}]",
+                String::from_utf8_lossy(output),
+                self.call,
+                ret
+            ),
+            (None, Some(ret)) => write!(f, " -> ? [{:?}, {:?}]", self.call, ret),
+            (_, None) => write!(f, " -> pending [{:?}, ..]", self.call),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NonLinearizable {
+    pub key: Vec<u8>,
+    // A smallest sub-history of `key` that still cannot be linearized.
+    pub operations: Vec<Operation>,
+}
+
+impl fmt::Display for NonLinearizable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "history of key {} is not linearizable:",
+            String::from_utf8_lossy(&self.key)
+        )?;
+        for operation in &self.operations {
+            writeln!(f, "  {}", operation)?;
+        }
+        Ok(())
+    }
+}
+
+// Client operations as observed from outside the cluster, recorded with the
+// time they were invoked and the time their result came back.
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    operations: Vec<Operation>,
+}
+
+impl History {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn invoke(&mut self, client_id: ClientId, input: KvInput, at: Duration) -> OpId {
+        self.operations.push(Operation {
+            client_id,
+            input,
+            output: None,
+            call: at,
+            ret: None,
+        });
+        self.operations.len() - 1
+    }
+
+    pub fn complete(&mut self, id: OpId, output: Vec<u8>, at: Duration) {
+        let operation = &mut self.operations[id];
+        if operation.ret.is_none() {
+            operation.output = Some(output);
+            operation.ret = Some(at);
+        }
+    }
+
+    pub fn operations(&self) -> &[Operation] {
+        &self.operations
+    }
+
+    pub fn len(&self) -> usize {
+        self.operations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    // Keys are independent in the model, so each one is checked on its own.
+    pub fn check(&self) -> Result<(), NonLinearizable> {
+        let mut by_key: BTreeMap<&[u8], Vec<Operation>> = BTreeMap::new();
+        for operation in &self.operations {
+            by_key
+                .entry(operation.input.key())
+                .or_default()
+                .push(operation.clone());
+        }
+
+        for (key, operations) in by_key {
+            if !is_linearizable(&operations) {
+                return Err(NonLinearizable {
+                    key: key.to_vec(),
+                    operations: shrink(operations),
+                });
+            }
+        }
+
+        Ok(())
+    }
+}
+
+// Wing & Gong's search with Lowe's memoization: linearize one operation at a
+// time, only picking operations that were invoked before every remaining
+// operation returned, and skip (linearized set, value) states seen before.
+pub fn is_linearizable(operations: &[Operation]) -> bool {
+    // A get that never returned constrains nothing.
+    let operations: Vec<&Operation> = operations
+        .iter()
+        .filter(|operation| operation.ret.is_some() || matches!(operation.input, KvInput::Put { .. }))
+        .collect();
+
+    let mut search = Search {
+        operations: &operations,
+        done: vec![false; operations.len()],
+        remaining: operations.iter().filter(|operation| operation.ret.is_some()).count(),
+        seen: HashSet::new(),
+    };
+    search.run(Vec::new())
+}
+
+struct Search<'a> {
+    operations: &'a [&'a Operation],
+    done: Vec<bool>,
+    // Completed operations not yet linearized. Pending ones may be left out.
+    remaining: usize,
+    seen: HashSet<(Vec<bool>, Vec<u8>)>,
+}
+
+impl Search<'_> {
+    fn run(&mut self, value: Vec<u8>) -> bool {
+        if self.remaining == 0 {
+            return true;
+        }
+        if !self.seen.insert((self.done.clone(), value.clone())) {
+            return false;
+        }
+
+        let first_return = self
+            .operations
+            .iter()
+            .zip(&self.done)
+            .filter(|&(_, &done)| !done)
+            .filter_map(|(operation, _)| operation.ret)
+            .min();
+
+        for i in 0..self.operations.len() {
+            let operation = self.operations[i];
+            if self.done[i] || first_return.is_some_and(|first| operation.call > first) {
+                continue;
+            }
+
+            let (output, next) = match &operation.input {
+                KvInput::Put { value: new, .. } => (value.clone(), new.clone()),
+                KvInput::Get { .. } => (value.clone(), value.clone()),
+            };
+            if operation.output.as_ref().is_some_and(|expected| *expected != output) {
+                continue;
+            }
+
+            self.done[i] = true;
+            let completed = operation.ret.is_some();
+            if completed {
+                self.remaining -= 1;
+            }
+
+            if self.run(next) {
+                return true;
+            }
+
+            self.done[i] = false;
+            if completed {
+                self.remaining += 1;
+            }
+        }
+
+        false
+    }
+}
+
+// Drops operations while the history stays non-linearizable, until no single
+// operation can go. A put read by a remaining get is kept so every value read
+// still has a writer; puts that returned a dropped value stop being checked
+// against it, which only makes the rest of the history easier to linearize.
+fn shrink(mut operations: Vec<Operation>) -> Vec<Operation> {
+    let mut shrunk = true;
+    while shrunk {
+        shrunk = false;
+
+        let mut i = 0;
+        while i < operations.len() {
+            match without(&operations, i) {
+                Some(candidate) if !is_linearizable(&candidate) => {
+                    operations = candidate;
+                    shrunk = true;
+                }
+                _ => i += 1,
+            }
+        }
+    }
+
+    operations.sort_by_key(|operation| operation.call);
+    operations
+}
+
+fn without(operations: &[Operation], i: usize) -> Option<Vec<Operation>> {
+    let mut candidate = operations.to_vec();
+    let removed = candidate.remove(i);
+
+    if let KvInput::Put { value, .. } = &removed.input
+        && !value.is_empty()
+    {
+        for operation in &mut candidate {
+            if operation.output.as_ref() != Some(value) {
+                continue;
+            }
+            match operation.input {
+                KvInput::Get { .. } => return None,
+                KvInput::Put { .. } => operation.output = None,
+            }
+        }
+    }
+
+    Some(candidate)
+}
@@ -1,57 +1,288 @@
+use std::borrow::Cow;
+use std::fmt;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+
+use crate::trace::hex;
 use crate::types::{LogIndex, Term};
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct LogEntry {
     pub term: Term,
     pub index: LogIndex,
+    #[serde(with = "hex")]
     pub command: Vec<u8>,
 }
 
+// Index and term of the last entry compacted away; zero for a log that
+// still starts at index 1.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct LogOffset {
+    pub index: LogIndex,
+    pub term: Term,
+}
+
+// Reads back entries whose commands the log dropped from memory.
+pub trait LogReader: fmt::Debug + Send + Sync {
+    // Entries `from` through `to`, both included and all on disk, but only
+    // as many as `fitting` allows within `max_bytes`.
+    fn read(&self, from: LogIndex, to: LogIndex, max_bytes: u64) -> Vec<LogEntry>;
+}
+
+// How many of `entries`, from the first, have commands that add up to at
+// most `max_bytes`. The first counts even if it alone is larger.
+pub fn fitting(entries: &[LogEntry], max_bytes: u64) -> usize {
+    let mut bytes = 0u64;
+    let fit = entries
+        .iter()
+        .take_while(|entry| {
+            bytes = bytes.saturating_add(entry.command.len() as u64);
+            bytes <= max_bytes
+        })
+        .count();
+    fit.max(1).min(entries.len())
+}
+
+// Entries are held from `first_index` on, contiguously, so entry `i` sits at
+// position `i - first_index` and lookups never scan. The prefix before the
+// first entry may be compacted away; only its last index and term are kept.
+//
+// With a cache budget, the commands of the oldest entries that are already
+// on disk are dropped once the commands held add up to more than the budget.
+// Their terms stay, so only reads that need the commands go to the reader.
 #[derive(Debug, Clone, Default)]
 pub struct LogStore {
-    pub entries: Vec<LogEntry>,
+    entries: Vec<LogEntry>,
+    // Index and term of the entry just before the first one held.
+    offset: LogIndex,
+    offset_term: Term,
+    // Lowest index changed since the log was last persisted.
+    unstable_from: Option<LogIndex>,
+    // Lowest index changed since `take_changed_from` was last called.
+    changed_from: Option<LogIndex>,
+    reader: Option<Arc<dyn LogReader>>,
+    cache_budget: u64,
+    // Entries from here on have their commands in memory.
+    cached_from: LogIndex,
+    cached_bytes: u64,
 }
 
 impl LogStore {
     pub fn new() -> Self {
-        Self { entries: Vec::new() }
+        Self::default()
+    }
+
+    // `entries` start just after `offset`.
+    pub fn from_entries(entries: Vec<LogEntry>, offset: LogOffset) -> Self {
+        debug_assert!(
+            entries
+                .first()
+                .is_none_or(|entry| entry.index.get() == offset.index.get() + 1)
+        );
+        let cached_bytes = entries.iter().map(|entry| entry.command.len() as u64).sum();
+        Self {
+            entries,
+            offset: offset.index,
+            offset_term: offset.term,
+            cached_bytes,
+            ..Self::default()
+        }
+    }
+
+    // Keeps at most `budget` bytes of commands in memory, reading the rest
+    // back through `reader`. Takes effect on the next `evict`. Entries
+    // before `cached_from` were loaded without their commands.
+    pub fn set_cache(&mut self, reader: Arc<dyn LogReader>, budget: u64, cached_from: LogIndex) {
+        self.reader = Some(reader);
+        self.cache_budget = budget;
+        self.cached_from = cached_from;
+    }
+
+    fn position(&self, index: LogIndex) -> Option<usize> {
+        let position = index.get().checked_sub(self.offset.get() + 1)? as usize;
+        (position < self.entries.len()).then_some(position)
+    }
+
+    // Position of the first entry at or after `index`.
+    fn start(&self, index: LogIndex) -> usize {
+        let position = index.get().saturating_sub(self.offset.get() + 1);
+        (position as usize).min(self.entries.len())
     }
 
     pub fn append(&mut self, entry: LogEntry) {
+        debug_assert_eq!(entry.index.get(), self.last_log_index().get() + 1);
+        self.mark_unstable(entry.index);
+        self.cached_bytes += entry.command.len() as u64;
         self.entries.push(entry);
     }
 
-    // TODO: O(n) -> O(1)
-    pub fn get(&self, index: LogIndex) -> Option<&LogEntry> {
-        self.entries.iter().find(|&i| i.index == index)
+    pub fn get(&self, index: LogIndex) -> Option<Cow<'_, LogEntry>> {
+        let position = self.position(index)?;
+        if index >= self.cached_from {
+            return Some(Cow::Borrowed(&self.entries[position]));
+        }
+        self.read(index, index, u64::MAX).pop().map(Cow::Owned)
     }
 
-    pub fn last_log_index(&self) -> LogIndex {
-        if self.entries.is_empty() {
-            LogIndex::ZERO
-        } else {
-            self.entries.last().unwrap().index
+    // Also known for the last compacted entry, which AppendEntries still
+    // uses as the previous entry.
+    pub fn term_at(&self, index: LogIndex) -> Option<Term> {
+        if index == self.offset {
+            return Some(self.offset_term);
         }
+        self.position(index)
+            .map(|position| self.entries[position].term)
     }
 
-    pub fn last_log_term(&self) -> Term {
-        if self.entries.is_empty() {
-            Term::ZERO
-        } else {
-            self.entries.last().unwrap().term
+    pub fn offset(&self) -> LogOffset {
+        LogOffset {
+            index: self.offset,
+            term: self.offset_term,
         }
     }
 
-    pub fn entries_from(&self, from_index: LogIndex) -> Vec<LogEntry> {
+    pub fn first_index(&self) -> LogIndex {
+        LogIndex::new(self.offset.get() + 1)
+    }
+
+    pub fn last_log_index(&self) -> LogIndex {
+        LogIndex::new(self.offset.get() + self.entries.len() as u64)
+    }
+
+    pub fn last_log_term(&self) -> Term {
         self.entries
-            .iter()
-            .filter(|e| e.index >= from_index)
-            .cloned()
-            .collect()
+            .last()
+            .map_or(self.offset_term, |entry| entry.term)
+    }
+
+    pub fn entries(&self) -> Cow<'_, [LogEntry]> {
+        self.entries_from(self.first_index())
+    }
+
+    pub fn entries_from(&self, from_index: LogIndex) -> Cow<'_, [LogEntry]> {
+        self.entries_between(from_index, self.last_log_index())
+    }
+
+    // Entries from `from_index` through `to_index`, both included. Borrowed
+    // unless some of them have to be read back.
+    pub fn entries_between(&self, from_index: LogIndex, to_index: LogIndex) -> Cow<'_, [LogEntry]> {
+        self.entries_within(from_index, to_index, u64::MAX)
+    }
+
+    // As `entries_between`, but only as many as `fitting` allows within
+    // `max_bytes`. Entries past those are not read back.
+    pub fn entries_within(
+        &self,
+        from_index: LogIndex,
+        to_index: LogIndex,
+        max_bytes: u64,
+    ) -> Cow<'_, [LogEntry]> {
+        let end = self.start(LogIndex::new(to_index.get() + 1));
+        let start = self.start(from_index).min(end);
+        let cached = self.start(self.cached_from).clamp(start, end);
+        if cached == start {
+            let fit = fitting(&self.entries[start..end], max_bytes);
+            return Cow::Borrowed(&self.entries[start..start + fit]);
+        }
+
+        let from = self.entries[start].index;
+        let mut entries = self.read(from, self.entries[cached - 1].index, max_bytes);
+        if entries.len() == cached - start {
+            let mut bytes: u64 = entries.iter().map(|entry| entry.command.len() as u64).sum();
+            let rest = &self.entries[cached..end];
+            let fit = rest
+                .iter()
+                .take_while(|entry| {
+                    bytes = bytes.saturating_add(entry.command.len() as u64);
+                    bytes <= max_bytes
+                })
+                .count();
+            entries.extend_from_slice(&rest[..fit]);
+        }
+        Cow::Owned(entries)
+    }
+
+    fn read(&self, from: LogIndex, to: LogIndex, max_bytes: u64) -> Vec<LogEntry> {
+        let reader = self
+            .reader
+            .as_ref()
+            .expect("only a log with a reader evicts");
+        let entries = reader.read(from, to, max_bytes);
+        // Short only if cut by `max_bytes`, and never empty.
+        let count = (to.get() - from.get() + 1) as usize;
+        assert!(
+            entries.len() == count
+                || (max_bytes != u64::MAX && !entries.is_empty() && entries.len() < count),
+            "storage is missing evicted log entries"
+        );
+        entries
     }
 
     pub fn truncate(&mut self, from_index: LogIndex) {
-        self.entries.retain(|entry| entry.index < from_index);
+        self.mark_unstable(from_index);
+        let keep = self.start(from_index);
+        self.forget(keep..self.entries.len());
+        self.entries.truncate(keep);
+        self.cached_from = self.cached_from.min(from_index);
+    }
+
+    // Drops every entry through `index`, e.g. once a snapshot covers them.
+    pub fn compact(&mut self, index: LogIndex) {
+        let Some(position) = self.position(index) else {
+            return;
+        };
+        self.forget(0..position + 1);
+        self.offset_term = self.entries[position].term;
+        self.offset = index;
+        self.entries.drain(..=position);
+        self.cached_from = self.cached_from.max(self.first_index());
+    }
+
+    // Takes the commands of entries at `positions` off the cache's count.
+    fn forget(&mut self, positions: std::ops::Range<usize>) {
+        let cached = self.start(self.cached_from).max(positions.start);
+        let end = positions.end.max(cached);
+        let bytes: u64 = self.entries[cached..end]
+            .iter()
+            .map(|entry| entry.command.len() as u64)
+            .sum();
+        self.cached_bytes -= bytes;
+    }
+
+    // Drops the oldest commands over the cache budget, but none after
+    // `durable`: only what is on disk can be read back.
+    pub fn evict(&mut self, durable: LogIndex) {
+        if self.reader.is_none() {
+            return;
+        }
+
+        let durable = durable.min(self.last_log_index());
+        self.cached_from = self.cached_from.max(self.first_index());
+        while self.cached_bytes > self.cache_budget && self.cached_from <= durable {
+            let position = self.start(self.cached_from);
+            let command = std::mem::take(&mut self.entries[position].command);
+            self.cached_bytes -= command.len() as u64;
+            self.cached_from = LogIndex::new(self.cached_from.get() + 1);
+        }
+    }
+
+    // Bytes of commands held in memory.
+    pub fn cached_bytes(&self) -> u64 {
+        self.cached_bytes
+    }
+
+    pub fn take_unstable_from(&mut self) -> Option<LogIndex> {
+        self.unstable_from.take()
+    }
+
+    pub fn take_changed_from(&mut self) -> Option<LogIndex> {
+        self.changed_from.take()
+    }
+
+    fn mark_unstable(&mut self, index: LogIndex) {
+        self.unstable_from = Some(self.unstable_from.map_or(index, |from| from.min(index)));
+        self.changed_from = Some(self.changed_from.map_or(index, |from| from.min(index)));
     }
 
     pub fn len(&self) -> usize {
@@ -61,4 +292,4 @@ impl LogStore {
     pub fn is_empty(&self) -> bool {
         self.entries.is_empty()
     }
-}
\ No newline at end of file
+}
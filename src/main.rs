@@ -1,23 +1,150 @@
-use tonic::transport::Server;
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::{env, process};
 
+use mini_raft::config::RaftConfig;
+use mini_raft::grpc::{GrpcTransport, Listener, PeerAddr};
+use mini_raft::handshake::Handshake;
+use mini_raft::host::NodeHost;
 use mini_raft::node::RaftNode;
-use mini_raft::raft_proto::raft_server::RaftServer as RaftGrpcServer;
-use mini_raft::server::RaftServer;
+use mini_raft::raft::RaftRunner;
+use mini_raft::segment::SegmentStorage;
+use mini_raft::state_machine::KvStore;
+use mini_raft::tls::TlsConfig;
 use mini_raft::types::NodeId;
 
+const USAGE: &str = "usage: mini-raft [--cluster-id <id>]
+                 [--data-dir <dir> [--log-cache-bytes <n>]]
+                 [--tls-cert <pem> --tls-key <pem> --tls-ca <pem>]
+                 <id> <listen addr> [<peer id>=<addr> ...]
+addresses are host:port or unix:<path>; with TLS, each node's certificate
+must carry the DNS name node-<id>; without --data-dir, state is kept in memory";
+
+const DEFAULT_CLUSTER_ID: &str = "mini-raft";
+
+struct Args {
+    id: NodeId,
+    listen: PeerAddr,
+    peers: BTreeMap<NodeId, PeerAddr>,
+    cluster_id: String,
+    tls: Option<TlsConfig>,
+    data_dir: Option<PathBuf>,
+    log_cache_bytes: Option<u64>,
+}
+
+fn parse_args() -> Result<Args, String> {
+    let mut positional = Vec::new();
+    let mut cluster_id = None;
+    let (mut data_dir, mut log_cache_bytes) = (None, None);
+    let (mut cert, mut key, mut ca) = (None, None, None);
+    let mut args = env::args().skip(1);
+    while let Some(arg) = args.next() {
+        let slot = match arg.as_str() {
+            "--cluster-id" => &mut cluster_id,
+            "--tls-cert" => &mut cert,
+            "--tls-key" => &mut key,
+            "--tls-ca" => &mut ca,
+            "--data-dir" => &mut data_dir,
+            "--log-cache-bytes" => &mut log_cache_bytes,
+            _ => {
+                positional.push(arg);
+                continue;
+            }
+        };
+        *slot = Some(args.next().ok_or_else(|| format!("missing value after {}", arg))?);
+    }
+
+    let tls = match (cert, key, ca) {
+        (None, None, None) => None,
+        (Some(cert), Some(key), Some(ca)) => Some(
+            TlsConfig::from_files(cert.as_ref(), key.as_ref(), ca.as_ref())
+                .map_err(|e| format!("cannot read tls files: {}", e))?,
+        ),
+        _ => return Err("--tls-cert, --tls-key and --tls-ca go together".to_string()),
+    };
+
+    let cluster_id = cluster_id.unwrap_or_else(|| DEFAULT_CLUSTER_ID.to_string());
+    if cluster_id.is_empty() || !cluster_id.chars().all(|c| c.is_ascii_graphic()) {
+        return Err(format!("bad cluster id '{}'", cluster_id));
+    }
+
+    if log_cache_bytes.is_some() && data_dir.is_none() {
+        return Err("--log-cache-bytes needs --data-dir".to_string());
+    }
+    let log_cache_bytes = log_cache_bytes
+        .map(|bytes| {
+            bytes
+                .parse()
+                .map_err(|_| format!("bad log cache size '{}'", bytes))
+        })
+        .transpose()?;
+
+    let mut positional = positional.into_iter();
+    let id = positional.next().unwrap_or_else(|| "1".to_string());
+    let id = NodeId::new(id.parse().map_err(|_| format!("bad node id '{}'", id))?);
+    let listen = positional
+        .next()
+        .unwrap_or_else(|| "[::1]:50051".to_string())
+        .parse()?;
+
+    let mut peers = BTreeMap::new();
+    for arg in positional {
+        let (peer, addr) = arg
+            .split_once('=')
+            .ok_or_else(|| format!("expected <peer id>=<addr>, got '{}'", arg))?;
+        let peer = peer.parse().map_err(|_| format!("bad peer id '{}'", peer))?;
+        peers.insert(NodeId::new(peer), addr.parse()?);
+    }
+
+    Ok(Args {
+        id,
+        listen,
+        peers,
+        cluster_id,
+        tls,
+        data_dir: data_dir.map(PathBuf::from),
+        log_cache_bytes,
+    })
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let addr = "[::1]:50051".parse()?;
-
-    let node = RaftNode::new(NodeId::new(1), vec![]);
-    let raft_server = RaftServer::new(node);
+    let args = parse_args().unwrap_or_else(|e| {
+        eprintln!("{}\n{}", e, USAGE);
+        process::exit(2);
+    });
+    let secure = args.tls.is_some();
 
-    println!("Raft server listening on {}", addr);
+    let config = RaftConfig {
+        log_cache_bytes: args.log_cache_bytes,
+        ..RaftConfig::default()
+    };
+    let node = RaftNode::with_config(args.id, args.peers.keys().copied().collect(), config);
+    let runner = match &args.data_dir {
+        Some(dir) => RaftRunner::with_storage(
+            node,
+            Box::new(KvStore::new()),
+            Box::new(SegmentStorage::open(dir)?),
+        ),
+        None => RaftRunner::new(node),
+    };
+    let handshake = Handshake::new(args.cluster_id.clone());
+    let transport = match args.tls {
+        Some(tls) => GrpcTransport::with_tls(handshake, &args.peers, tls)?,
+        None => GrpcTransport::new(handshake, &args.peers),
+    };
+    let listener = Listener::bind(&args.listen).await?;
+    let server = tokio::spawn(transport.serve(listener));
 
-    Server::builder()
-        .add_service(RaftGrpcServer::new(raft_server))
-        .serve(addr)
-        .await?;
+    println!(
+        "Raft node {} of cluster '{}' listening on {}{}",
+        args.id.get(),
+        args.cluster_id,
+        args.listen,
+        if secure { " (mTLS)" } else { "" }
+    );
+    NodeHost::new(runner, transport).run().await;
+    server.await??;
 
     Ok(())
 }
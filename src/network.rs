@@ -0,0 +1,224 @@
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashSet};
+use std::time::Duration;
+
+use rand::Rng;
+use rand::rngs::StdRng;
+
+use crate::raft::RaftAction;
+use crate::types::NodeId;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Latency {
+    Fixed(Duration),
+    Uniform { min: Duration, max: Duration },
+    // `min` plus an exponentially distributed delay, for long tails.
+    Exponential { min: Duration, mean: Duration },
+}
+
+impl Latency {
+    fn sample(&self, rng: &mut StdRng) -> Duration {
+        match *self {
+            Latency::Fixed(latency) => latency,
+            Latency::Uniform { min, max } if max > min => rng.random_range(min..=max),
+            Latency::Uniform { min, .. } => min,
+            Latency::Exponential { min, mean } => {
+                let uniform: f64 = rng.random_range(f64::EPSILON..1.0);
+                min + mean.mul_f64(-uniform.ln())
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LinkConfig {
+    pub latency: Latency,
+    pub drop_rate: f64,
+    pub duplicate_rate: f64,
+    // Messages are delivered in send order per link unless picked for
+    // reordering, in which case they are held back by up to `reorder_window`.
+    pub reorder_rate: f64,
+    pub reorder_window: Duration,
+}
+
+impl Default for LinkConfig {
+    fn default() -> Self {
+        Self {
+            latency: Latency::Fixed(Duration::from_millis(1)),
+            drop_rate: 0.0,
+            duplicate_rate: 0.0,
+            reorder_rate: 0.0,
+            reorder_window: Duration::ZERO,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct InFlight {
+    pub deliver_at: Duration,
+    pub from: NodeId,
+    pub action: RaftAction,
+}
+
+// Simulated network on virtual time: every message is queued with a delivery
+// time drawn from its link's configuration.
+#[derive(Debug)]
+pub struct Network {
+    rng: StdRng,
+    default_link: LinkConfig,
+    links: BTreeMap<(NodeId, NodeId), LinkConfig>,
+    blocked: HashSet<(NodeId, NodeId)>,
+    last_delivery: BTreeMap<(NodeId, NodeId), Duration>,
+    queue: BinaryHeap<Reverse<(Duration, u64)>>,
+    in_flight: BTreeMap<u64, InFlight>,
+    next_seq: u64,
+}
+
+impl Network {
+    pub fn new(rng: StdRng) -> Self {
+        Self {
+            rng,
+            default_link: LinkConfig::default(),
+            links: BTreeMap::new(),
+            blocked: HashSet::new(),
+            last_delivery: BTreeMap::new(),
+            queue: BinaryHeap::new(),
+            in_flight: BTreeMap::new(),
+            next_seq: 0,
+        }
+    }
+
+    pub fn set_default_link(&mut self, config: LinkConfig) {
+        self.default_link = config;
+    }
+
+    pub fn set_link(&mut self, from: NodeId, to: NodeId, config: LinkConfig) {
+        self.links.insert((from, to), config);
+    }
+
+    pub fn link(&self, from: NodeId, to: NodeId) -> LinkConfig {
+        self.links
+            .get(&(from, to))
+            .copied()
+            .unwrap_or(self.default_link)
+    }
+
+    // Cuts every link between nodes of different groups, in both directions.
+    pub fn partition(&mut self, groups: &[Vec<NodeId>]) {
+        for (i, group) in groups.iter().enumerate() {
+            for other in &groups[i + 1..] {
+                for &a in group {
+                    for &b in other {
+                        self.blocked.insert((a, b));
+                        self.blocked.insert((b, a));
+                    }
+                }
+            }
+        }
+    }
+
+    pub fn isolate(&mut self, id: NodeId, all: &[NodeId]) {
+        for &other in all.iter().filter(|&&other| other != id) {
+            self.blocked.insert((id, other));
+            self.blocked.insert((other, id));
+        }
+    }
+
+    // One-way cut: `from` can no longer reach `to`, the reverse still works.
+    pub fn block(&mut self, from: NodeId, to: NodeId) {
+        self.blocked.insert((from, to));
+    }
+
+    pub fn unblock(&mut self, from: NodeId, to: NodeId) {
+        self.blocked.remove(&(from, to));
+    }
+
+    pub fn heal(&mut self) {
+        self.blocked.clear();
+    }
+
+    pub fn is_blocked(&self, from: NodeId, to: NodeId) -> bool {
+        self.blocked.contains(&(from, to))
+    }
+
+    pub fn send(&mut self, now: Duration, from: NodeId, action: RaftAction) {
+        let to = action.target();
+        if self.is_blocked(from, to) {
+            return;
+        }
+
+        let link = self.link(from, to);
+        if self.rng.random_bool(link.drop_rate.clamp(0.0, 1.0)) {
+            return;
+        }
+
+        if self.rng.random_bool(link.duplicate_rate.clamp(0.0, 1.0)) {
+            self.enqueue(now, from, action.clone(), &link);
+        }
+        self.enqueue(now, from, action, &link);
+    }
+
+    fn enqueue(&mut self, now: Duration, from: NodeId, action: RaftAction, link: &LinkConfig) {
+        let to = action.target();
+        let mut deliver_at = now + link.latency.sample(&mut self.rng);
+
+        if self.rng.random_bool(link.reorder_rate.clamp(0.0, 1.0)) {
+            deliver_at += self.rng.random_range(Duration::ZERO..=link.reorder_window);
+        } else {
+            let last = self.last_delivery.entry((from, to)).or_default();
+            deliver_at = deliver_at.max(*last);
+            *last = deliver_at;
+        }
+
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.queue.push(Reverse((deliver_at, seq)));
+        self.in_flight.insert(
+            seq,
+            InFlight {
+                deliver_at,
+                from,
+                action,
+            },
+        );
+    }
+
+    // Messages due by `now`, in delivery order. Links cut while a message
+    // was in flight lose it.
+    pub fn deliver(&mut self, now: Duration) -> Vec<(NodeId, RaftAction)> {
+        let mut delivered = Vec::new();
+
+        while let Some(&Reverse((deliver_at, seq))) = self.queue.peek() {
+            if deliver_at > now {
+                break;
+            }
+            self.queue.pop();
+
+            let message = self.in_flight.remove(&seq).unwrap();
+            if !self.is_blocked(message.from, message.action.target()) {
+                delivered.push((message.from, message.action));
+            }
+        }
+
+        delivered
+    }
+
+    // Drops everything still on its way to `to`, as when that node goes down.
+    pub fn discard_to(&mut self, to: NodeId) {
+        self.in_flight.retain(|_, message| message.action.target() != to);
+        let in_flight = &self.in_flight;
+        self.queue.retain(|&Reverse((_, seq))| in_flight.contains_key(&seq));
+    }
+
+    pub fn in_flight(&self) -> impl Iterator<Item = &InFlight> {
+        self.in_flight.values()
+    }
+
+    pub fn len(&self) -> usize {
+        self.in_flight.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.in_flight.is_empty()
+    }
+}
@@ -1,16 +1,36 @@
-use std::collections::HashMap;
-
-use crate::log::{LogEntry, LogStore};
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+
+use crate::clock::{SharedClock, SystemClock};
+use crate::config::RaftConfig;
+use crate::log::{LogEntry, LogOffset, LogStore};
+use crate::proposal::ProposalQueue;
+use crate::read_index::{ReadId, ReadIndexQueue};
 use crate::rpc::{
     AppendEntriesRequest, AppendEntriesResponse, RequestVoteRequest, RequestVoteResponse,
 };
-use crate::timer::{Timer, heartbeat_interval, random_election_timeout};
+use crate::session::Command;
+use crate::storage::HardState;
+use crate::timer::{Timer, heartbeat_interval, min_election_timeout, random_election_timeout};
 use crate::types::{LogIndex, NodeId, RaftState, Term};
 
 #[derive(Debug, Clone)]
 pub struct RaftNode {
     pub id: NodeId,
+    // The other voting members.
     pub peers: Vec<NodeId>,
+    // The voting members the node started with, and every membership change
+    // in its log since, oldest first. The newest one is in effect whether it
+    // is committed or not (Raft thesis 4.1).
+    pub initial_members: Vec<NodeId>,
+    pub membership_changes: Vec<(LogIndex, Vec<NodeId>)>,
+    pub config: RaftConfig,
+    pub clock: SharedClock,
+    pub rng: StdRng,
 
     pub state: RaftState,
 
@@ -18,14 +38,28 @@ pub struct RaftNode {
     pub current_term: Term,
     pub voted_for: Option<NodeId>,
     pub log: LogStore,
+    // Last index known to be on disk. A leader sends entries before it has
+    // synced them, and only counts itself towards a quorum up to here.
+    pub durable_index: LogIndex,
 
     // Volatile State
     pub commit_index: LogIndex,
     pub last_applied: LogIndex,
+    pub leader_id: Option<NodeId>,
+    pub last_leader_contact: Option<Instant>,
+
+    // Candidate State
+    pub votes_received: HashSet<NodeId>,
 
     // Leader State
     pub next_index: HashMap<NodeId, LogIndex>,
     pub match_index: HashMap<NodeId, LogIndex>,
+    pub last_ack: HashMap<NodeId, Instant>,
+    // Peers last sent entries that stopped short of the end of the log.
+    pub cut_short: HashSet<NodeId>,
+    pub read_queue: ReadIndexQueue,
+    pub proposals: ProposalQueue,
+    pub lease_expiry: Option<Instant>,
 
     // Timer
     pub election_timer: Timer,
@@ -34,20 +68,164 @@ pub struct RaftNode {
 
 impl RaftNode {
     pub fn new(id: NodeId, peers: Vec<NodeId>) -> Self {
+        Self::with_config(id, peers, RaftConfig::default())
+    }
+
+    pub fn with_config(id: NodeId, peers: Vec<NodeId>, config: RaftConfig) -> Self {
+        Self::with_clock(id, peers, config, Arc::new(SystemClock))
+    }
+
+    pub fn with_clock(
+        id: NodeId,
+        peers: Vec<NodeId>,
+        config: RaftConfig,
+        clock: SharedClock,
+    ) -> Self {
+        Self::with_clock_and_rng(id, peers, config, clock, StdRng::from_os_rng())
+    }
+
+    pub fn with_clock_and_rng(
+        id: NodeId,
+        peers: Vec<NodeId>,
+        config: RaftConfig,
+        clock: SharedClock,
+        mut rng: StdRng,
+    ) -> Self {
+        let election_timeout = random_election_timeout(&mut rng);
+        let mut initial_members = peers.clone();
+        initial_members.push(id);
+
         Self {
             id,
             peers,
+            initial_members,
+            membership_changes: Vec::new(),
+            config,
             state: RaftState::Follower,
             current_term: Term::ZERO,
             voted_for: None,
             log: LogStore::new(),
+            durable_index: LogIndex::ZERO,
             commit_index: LogIndex::ZERO,
             last_applied: LogIndex::ZERO,
+            leader_id: None,
+            last_leader_contact: None,
+            votes_received: HashSet::new(),
             next_index: HashMap::new(),
             match_index: HashMap::new(),
-            election_timer: Timer::new(random_election_timeout()),
-            heartbeat_timer: Timer::new(heartbeat_interval()),
+            last_ack: HashMap::new(),
+            cut_short: HashSet::new(),
+            read_queue: ReadIndexQueue::new(),
+            proposals: ProposalQueue::new(),
+            lease_expiry: None,
+            election_timer: Timer::with_clock(election_timeout, clock.clone()),
+            heartbeat_timer: Timer::with_clock(heartbeat_interval(), clock.clone()),
+            clock,
+            rng,
+        }
+    }
+
+    // A node added to a running cluster. It starts without any members and
+    // does not campaign until its log holds a membership that includes it.
+    pub fn joining(id: NodeId, config: RaftConfig, clock: SharedClock, rng: StdRng) -> Self {
+        let mut node = Self::with_clock_and_rng(id, Vec::new(), config, clock, rng);
+        node.initial_members.clear();
+        node
+    }
+
+    pub fn hard_state(&self) -> HardState {
+        HardState {
+            current_term: self.current_term,
+            voted_for: self.voted_for,
+        }
+    }
+
+    pub fn restore(&mut self, hard_state: HardState, offset: LogOffset, entries: Vec<LogEntry>) {
+        self.current_term = hard_state.current_term;
+        self.voted_for = hard_state.voted_for;
+        self.log = LogStore::from_entries(entries, offset);
+        self.durable_index = self.log.last_log_index();
+    }
+
+    // Picks up the membership changes in a restored log.
+    pub fn restore_membership(&mut self) {
+        self.reload_membership(LogIndex::ZERO);
+    }
+
+    pub fn members(&self) -> &[NodeId] {
+        self.membership_changes
+            .last()
+            .map_or(&self.initial_members, |(_, members)| members)
+    }
+
+    pub fn is_member(&self) -> bool {
+        self.members().contains(&self.id)
+    }
+
+    // Index of the newest membership change in the log, 0 if there is none.
+    pub fn membership_index(&self) -> LogIndex {
+        self.membership_changes
+            .last()
+            .map_or(LogIndex::ZERO, |&(index, _)| index)
+    }
+
+    // Drops the membership changes from `from` on and reads them back from
+    // the log, which changed there.
+    fn reload_membership(&mut self, from: LogIndex) {
+        self.membership_changes.retain(|&(index, _)| index < from);
+
+        let last = self.log.last_log_index();
+        let mut next = from.max(self.log.first_index());
+        while next <= last {
+            let entries = self
+                .log
+                .entries_within(next, last, self.config.max_append_bytes);
+            for entry in entries.iter() {
+                if let Some(members) = Command::membership(&entry.command) {
+                    self.membership_changes.push((entry.index, members));
+                }
+            }
+            next = LogIndex::new(next.get() + entries.len() as u64);
+        }
+
+        self.update_peers();
+    }
+
+    // A leader starts replicating to added members right away and forgets
+    // removed ones.
+    fn update_peers(&mut self) {
+        let id = self.id;
+        self.peers = self
+            .members()
+            .iter()
+            .copied()
+            .filter(|&member| member != id)
+            .collect();
+
+        if !self.is_leader() {
+            return;
+        }
+
+        let next_index = LogIndex::new(self.log.last_log_index().get() + 1);
+        let now = self.clock.now();
+        for &peer in &self.peers {
+            self.next_index.entry(peer).or_insert(next_index);
+            self.match_index.entry(peer).or_insert(LogIndex::ZERO);
+            self.last_ack.entry(peer).or_insert(now);
         }
+
+        let peers = &self.peers;
+        self.next_index.retain(|peer, _| peers.contains(peer));
+        self.match_index.retain(|peer, _| peers.contains(peer));
+        self.last_ack.retain(|peer, _| peers.contains(peer));
+        self.cut_short.retain(|peer| peers.contains(peer));
+    }
+
+    // The log is on disk through `index`.
+    pub fn persisted(&mut self, index: LogIndex) {
+        self.durable_index = index;
+        self.log.evict(index);
+        self.update_commit_index();
     }
 
     pub fn is_leader(&self) -> bool {
@@ -63,7 +241,7 @@ impl RaftNode {
     }
 
     pub fn cluster_size(&self) -> usize {
-        self.peers.len() + 1
+        self.members().len()
     }
 
     pub fn quorum(&self) -> usize {
@@ -72,28 +250,129 @@ impl RaftNode {
 
     pub fn become_follower(&mut self, term: Term) {
         self.state = RaftState::Follower;
-        self.current_term = term;
-        self.voted_for = None;
+        self.leader_id = None;
+        self.lease_expiry = None;
+        self.read_queue.abort_all();
+        self.proposals.clear();
+
+        // Stepping down within the same term (CheckQuorum) must keep the vote.
+        if term > self.current_term {
+            self.current_term = term;
+            self.voted_for = None;
+        }
     }
 
     pub fn become_candidate(&mut self) {
         self.state = RaftState::Candidate;
         self.current_term = Term::new(self.current_term.get() + 1);
         self.voted_for = Some(self.id);
+        self.leader_id = None;
+        self.votes_received = HashSet::from([self.id]);
+
+        if self.votes_received.len() >= self.quorum() {
+            self.become_leader();
+        }
     }
 
     pub fn become_leader(&mut self) {
         self.state = RaftState::Leader;
+        self.leader_id = Some(self.id);
+        self.lease_expiry = None;
 
         let next_index_value = LogIndex::new(self.log.last_log_index().get() + 1);
+        let now = self.clock.now();
 
+        // Peers of an earlier term may have been removed since.
+        self.next_index.clear();
+        self.match_index.clear();
+        self.last_ack.clear();
         for peer in &self.peers {
             self.next_index.insert(*peer, next_index_value);
             self.match_index.insert(*peer, LogIndex::ZERO);
+            // A fresh leader gets one full election timeout to reach its peers.
+            self.last_ack.insert(*peer, now);
+        }
+
+        // No-op entry so the leader learns the commit index of its own term,
+        // which ReadIndex needs before it can serve reads.
+        let noop_index = LogIndex::new(self.log.last_log_index().get() + 1);
+        self.log.append(LogEntry {
+            term: self.current_term,
+            index: noop_index,
+            command: Vec::new(),
+        });
+        self.update_commit_index();
+    }
+
+    // While a leader is known to be alive, vote requests from disrupting
+    // candidates (e.g. a node rejoining after a partition) are ignored.
+    pub fn has_recent_leader(&self) -> bool {
+        if !self.config.check_quorum {
+            return false;
+        }
+
+        if self.is_leader() {
+            return true;
         }
+
+        self.last_leader_contact
+            .is_some_and(|contact| self.since(contact) < min_election_timeout())
+    }
+
+    // Leader side of CheckQuorum: true if a quorum, itself included, has
+    // acknowledged us within the last election timeout.
+    pub fn check_quorum(&self) -> bool {
+        let active = self
+            .peers
+            .iter()
+            .filter(|peer| {
+                self.last_ack
+                    .get(peer)
+                    .is_some_and(|&ack| self.since(ack) < min_election_timeout())
+            })
+            .count();
+
+        active + 1 >= self.quorum()
+    }
+
+    pub fn has_lease(&self) -> bool {
+        self.config.lease_read
+            && self.config.check_quorum
+            && self.is_leader()
+            && self.lease_expiry.is_some_and(|expiry| self.clock.now() < expiry)
+    }
+
+    fn extend_lease(&mut self) {
+        if !self.config.lease_read {
+            return;
+        }
+
+        if let Some(sent_at) = self
+            .read_queue
+            .quorum_round_sent_at(&self.peers, self.quorum())
+        {
+            let expiry = sent_at + self.config.lease_duration();
+            self.lease_expiry = Some(self.lease_expiry.map_or(expiry, |e| e.max(expiry)));
+        }
+    }
+
+    pub fn reset_election_timer(&mut self) {
+        let timeout = random_election_timeout(&mut self.rng);
+        self.election_timer.reset_with(timeout);
+    }
+
+    fn since(&self, instant: Instant) -> Duration {
+        self.clock.now().saturating_duration_since(instant)
     }
 
     pub fn handle_request_vote(&mut self, request: RequestVoteRequest) -> RequestVoteResponse {
+        if request.term > self.current_term && self.has_recent_leader() {
+            return RequestVoteResponse {
+                term: self.current_term,
+                vote_granted: false,
+            };
+        }
+
         if request.term > self.current_term {
             self.become_follower(request.term);
         }
@@ -112,17 +391,26 @@ impl RaftNode {
         }
     }
 
-    pub fn handle_request_response(&mut self, response: RequestVoteResponse) -> Option<bool> {
-        if !self.is_candidate() {
-            return None;
-        }
-
+    pub fn handle_request_vote_response(&mut self, peer: NodeId, response: RequestVoteResponse) {
         if response.term > self.current_term {
             self.become_follower(response.term);
-            return Some(false);
+            return;
+        }
+
+        if !self.is_candidate() || response.term < self.current_term || !response.vote_granted {
+            return;
+        }
+
+        // Only votes from the candidate's own configuration count.
+        if !self.peers.contains(&peer) {
+            return;
         }
 
-        if response.vote_granted { None } else { None }
+        self.votes_received.insert(peer);
+
+        if self.votes_received.len() >= self.quorum() {
+            self.become_leader();
+        }
     }
 
     pub fn is_log_up_to_date(
@@ -153,57 +441,67 @@ impl RaftNode {
         }
 
         if request.term < self.current_term {
-            return AppendEntriesResponse {
-                term: self.current_term,
-                success: false,
-            };
+            return self.reject_append_entries(request.heartbeat_round);
         }
 
         if self.is_candidate() {
             self.state = RaftState::Follower;
         }
 
-        if request.prev_log_index.get() > 0 {
-            match self.log.get(request.prev_log_index) {
-                None => {
-                    return AppendEntriesResponse {
-                        term: self.current_term,
-                        success: false,
-                    };
-                }
-                Some(entry) if entry.term != request.prev_log_term => {
-                    return AppendEntriesResponse {
-                        term: self.current_term,
-                        success: false,
-                    };
-                }
-                _ => {}
-            }
+        self.leader_id = Some(request.leader_id);
+        self.last_leader_contact = Some(self.clock.now());
+
+        if self.log.term_at(request.prev_log_index) != Some(request.prev_log_term) {
+            return self.reject_append_entries(request.heartbeat_round);
         }
 
+        let Some(last_index) = request.prev_log_index.get().checked_add(request.entries.len() as u64) else {
+            return self.reject_append_entries(request.heartbeat_round);
+        };
+        let match_index = LogIndex::new(last_index);
+
+        let mut changed_from = None;
         for entry in request.entries {
-            if let Some(existing) = self.log.get(entry.index) {
-                if existing.term != entry.term {
-                    self.log.truncate(entry.index);
-                }
+            if let Some(existing) = self.log.term_at(entry.index)
+                && existing != entry.term
+            {
+                self.log.truncate(entry.index);
+                changed_from.get_or_insert(entry.index);
             }
-            if self.log.get(entry.index).is_none() {
+            if entry.index > self.log.last_log_index() {
+                changed_from.get_or_insert(entry.index);
                 self.log.append(entry);
             }
         }
-
-        if request.leader_commit > self.commit_index {
-            let last_new_entry = self.log.last_log_index();
-            self.commit_index = std::cmp::min(request.leader_commit, last_new_entry);
+        if let Some(from) = changed_from {
+            self.reload_membership(from);
         }
 
+        // Entries past the window may be stale ones from an older leader. A
+        // request delivered late may cover less than one already handled, so
+        // the commit index only moves forward.
+        self.commit_index = self
+            .commit_index
+            .max(std::cmp::min(request.leader_commit, match_index));
+
         AppendEntriesResponse {
             term: self.current_term,
             success: true,
+            match_index,
+            heartbeat_round: request.heartbeat_round,
         }
     }
 
-    pub fn create_append_entries(&self, peer: &NodeId) -> AppendEntriesRequest {
+    fn reject_append_entries(&self, heartbeat_round: u64) -> AppendEntriesResponse {
+        AppendEntriesResponse {
+            term: self.current_term,
+            success: false,
+            match_index: LogIndex::ZERO,
+            heartbeat_round,
+        }
+    }
+
+    pub fn create_append_entries(&mut self, peer: &NodeId) -> AppendEntriesRequest {
         let next_idx = self
             .next_index
             .get(peer)
@@ -211,16 +509,21 @@ impl RaftNode {
             .unwrap_or(LogIndex::new(1));
 
         let prev_log_index = LogIndex::new(next_idx.get().saturating_sub(1));
-        let prev_log_term = if prev_log_index.get() == 0 {
-            Term::ZERO
+        let prev_log_term = self.log.term_at(prev_log_index).unwrap_or(Term::ZERO);
+
+        let last = (self.config.max_append_entries.max(1) as u64)
+            .saturating_add(prev_log_index.get())
+            .min(self.log.last_log_index().get());
+        let entries = self
+            .log
+            .entries_within(next_idx, LogIndex::new(last), self.config.max_append_bytes)
+            .into_owned();
+        let end = LogIndex::new(prev_log_index.get() + entries.len() as u64);
+        if end < self.log.last_log_index() {
+            self.cut_short.insert(*peer);
         } else {
-            self.log
-                .get(prev_log_index)
-                .map(|e| e.term)
-                .unwrap_or(Term::ZERO)
-        };
-
-        let entries = self.log.entries_from(next_idx);
+            self.cut_short.remove(peer);
+        }
 
         AppendEntriesRequest {
             term: self.current_term,
@@ -229,6 +532,7 @@ impl RaftNode {
             prev_log_term,
             entries,
             leader_commit: self.commit_index,
+            heartbeat_round: self.read_queue.round(),
         }
     }
 
@@ -246,37 +550,177 @@ impl RaftNode {
             return;
         }
 
+        if response.term < self.current_term {
+            return;
+        }
+
+        // A node removed from the cluster no longer counts.
+        if !self.peers.contains(&peer) {
+            return;
+        }
+
+        self.last_ack.insert(peer, self.clock.now());
+        self.read_queue.acknowledge(peer, response.heartbeat_round);
+        self.extend_lease();
+
         if response.success {
-            if let Some(next_idx) = self.next_index.get(&peer).copied() {
-                let match_idx = LogIndex::new(next_idx.get().saturating_sub(1));
-                self.match_index.insert(peer, match_idx);
-                self.update_commit_index();
-            }
-        } else {
-            if let Some(next_idx) = self.next_index.get(&peer).copied() {
-                if next_idx.get() > 1 {
-                    self.next_index
-                        .insert(peer, LogIndex::new(next_idx.get() - 1));
-                }
-            }
+            let match_idx = self
+                .match_index
+                .get(&peer)
+                .copied()
+                .unwrap_or(LogIndex::ZERO)
+                .max(response.match_index);
+            self.match_index.insert(peer, match_idx);
+            self.next_index.insert(peer, LogIndex::new(match_idx.get() + 1));
+            self.update_commit_index();
+        } else if let Some(next_idx) = self.next_index.get(&peer).copied()
+            && next_idx.get() > 1
+        {
+            self.next_index
+                .insert(peer, LogIndex::new(next_idx.get() - 1));
         }
+
+        self.read_queue.confirm(&self.peers, self.quorum());
     }
 
-    pub fn client_request(&mut self, command: Vec<u8>) -> Option<LogIndex> {
+    // Queues a command for the next batch and returns the index it will be
+    // appended at, unless leadership is lost first.
+    // Queues `command` for the next batch; `flush_proposals` appends it.
+    pub fn propose(&mut self, command: Vec<u8>) -> bool {
         if !self.is_leader() {
+            return false;
+        }
+
+        let now = self.clock.now();
+        self.proposals.push(self.current_term, command, now);
+        true
+    }
+
+    // Appends a change to `members`, which must add or remove exactly one
+    // node other than the leader itself. Changes go one at a time, each once
+    // the last has committed and the leader has committed an entry of its
+    // own term (Raft thesis 4.1), so any two successive memberships share a
+    // majority.
+    pub fn change_membership(&mut self, members: Vec<NodeId>) -> Option<LogIndex> {
+        if !self.is_leader()
+            || !self.has_committed_in_current_term()
+            || self.membership_index() > self.commit_index
+            || !members.contains(&self.id)
+        {
+            return None;
+        }
+
+        let mut distinct = members.clone();
+        distinct.sort();
+        distinct.dedup();
+        let current = self.members();
+        let added = members.iter().filter(|id| !current.contains(id)).count();
+        let removed = current.iter().filter(|id| !members.contains(id)).count();
+        if distinct.len() != members.len() || added + removed != 1 {
             return None;
         }
 
-        let new_index = LogIndex::new(self.log.last_log_index().get() + 1);
-        let entry = LogEntry {
+        let index = LogIndex::new(self.log.last_log_index().get() + 1);
+        self.log.append(LogEntry {
             term: self.current_term,
-            index: new_index,
-            command,
-        };
+            index,
+            command: Command::Membership(members).encode(),
+        });
+        self.reload_membership(index);
+        self.update_commit_index();
+        Some(index)
+    }
+
+    pub fn proposals_due(&self) -> bool {
+        self.proposals.is_due(
+            self.clock.now(),
+            self.config.max_batch_size,
+            self.config.max_batch_delay,
+        )
+    }
 
-        self.log.append(entry);
+    // Appends the next batch of proposals if it is due, and returns the
+    // indices it was appended at.
+    pub fn flush_proposals(&mut self) -> Vec<LogIndex> {
+        if !self.proposals_due() {
+            return Vec::new();
+        }
+        if !self.is_leader() || self.proposals.term() != self.current_term {
+            self.proposals.clear();
+            return Vec::new();
+        }
+
+        let batch = self.proposals.take_batch(self.config.max_batch_size);
+        let mut indices = Vec::with_capacity(batch.len());
+        for command in batch {
+            let index = LogIndex::new(self.log.last_log_index().get() + 1);
+            self.log.append(LogEntry {
+                term: self.current_term,
+                index,
+                command,
+            });
+            indices.push(index);
+        }
+        if let Some(&first) = indices.first() {
+            self.reload_membership(first);
+        }
+        self.update_commit_index();
 
-        Some(self.log.last_log_index())
+        indices
+    }
+
+    pub fn read_index(&mut self) -> Option<ReadId> {
+        if !self.is_leader() || !self.has_committed_in_current_term() {
+            return None;
+        }
+
+        if self.has_lease() {
+            return Some(self.read_queue.register_confirmed(self.commit_index));
+        }
+
+        let id = self.read_queue.register(self.commit_index);
+        self.read_queue.confirm(&self.peers, self.quorum());
+
+        Some(id)
+    }
+
+    // Read index that can be handed out without a heartbeat round, i.e. when
+    // the lease holds or there are no peers to confirm leadership with.
+    pub fn local_read_index(&self) -> Option<LogIndex> {
+        let confirmed = self.has_lease() || self.quorum() == 1;
+
+        (self.is_leader() && self.has_committed_in_current_term() && confirmed)
+            .then_some(self.commit_index)
+    }
+
+    pub fn start_heartbeat_round(&mut self) {
+        self.read_queue.start_round(self.clock.now());
+    }
+
+    pub fn has_committed_in_current_term(&self) -> bool {
+        self.commit_index >= self.log.first_index()
+            && self.log.term_at(self.commit_index) == Some(self.current_term)
+    }
+
+    // The next committed entries to apply, as many as fit in the log cache
+    // budget and at least one, so applying never reads back more than that.
+    pub fn take_committed_entries(&mut self) -> Vec<LogEntry> {
+        if self.commit_index <= self.last_applied {
+            return Vec::new();
+        }
+
+        let from = LogIndex::new(self.last_applied.get() + 1);
+        let max_bytes = self.config.log_cache_bytes.unwrap_or(u64::MAX);
+        let entries = self
+            .log
+            .entries_within(from, self.commit_index, max_bytes)
+            .into_owned();
+
+        if let Some(last) = entries.last() {
+            self.last_applied = last.index;
+        }
+
+        entries
     }
 
     pub fn update_commit_index(&mut self) {
@@ -289,17 +733,17 @@ impl RaftNode {
             .map(|idx| idx.get())
             .collect();
 
-        match_indices.push(self.log.last_log_index().get());
+        match_indices.push(self.durable_index.min(self.log.last_log_index()).get());
 
         match_indices.sort();
 
         let quorum_idx = match_indices.len() - self.quorum();
         let new_commit = match_indices[quorum_idx];
 
-        if let Some(entry) = self.log.get(LogIndex::new(new_commit)) {
-            if entry.term == self.current_term && new_commit > self.commit_index.get() {
-                self.commit_index = LogIndex::new(new_commit);
-            }
+        if self.log.term_at(LogIndex::new(new_commit)) == Some(self.current_term)
+            && new_commit > self.commit_index.get()
+        {
+            self.commit_index = LogIndex::new(new_commit);
         }
     }
 }
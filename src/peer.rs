@@ -0,0 +1,187 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rand::Rng;
+use tokio::time::Instant;
+use tonic::transport::Channel;
+
+use crate::raft_proto::raft_client::RaftClient;
+use crate::timer::heartbeat_interval;
+use crate::types::NodeId;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct PeerConfig {
+    // Deadline of one RPC. A reply later than the heartbeat after next is
+    // stale: the leader has moved on and will ask again.
+    pub rpc_timeout: Duration,
+    // Calls to one peer that may be outstanding at once. Further messages to
+    // the peer are dropped until one completes.
+    pub max_in_flight: usize,
+    // After a failed call the peer gets no messages for a backoff that
+    // doubles with each further failure, between these bounds, scaled by a
+    // random factor in [0.5, 1] so peers do not retry in lockstep.
+    pub backoff_min: Duration,
+    pub backoff_max: Duration,
+}
+
+impl Default for PeerConfig {
+    fn default() -> Self {
+        Self {
+            rpc_timeout: heartbeat_interval() * 2,
+            max_in_flight: 8,
+            backoff_min: heartbeat_interval(),
+            backoff_max: Duration::from_secs(2),
+        }
+    }
+}
+
+impl PeerConfig {
+    fn backoff(&self, failures: u32, rng: &mut impl Rng) -> Duration {
+        let doublings = failures.saturating_sub(1).min(16);
+        let delay = self
+            .backoff_min
+            .saturating_mul(1 << doublings)
+            .min(self.backoff_max);
+        delay.mul_f64(rng.random_range(0.5..=1.0))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerStatus {
+    // False from a failed call until the next successful one.
+    pub reachable: bool,
+    pub failures: u32,
+    pub in_flight: usize,
+}
+
+#[derive(Default)]
+struct PeerState {
+    failures: u32,
+    retry_at: Option<Instant>,
+    in_flight: usize,
+}
+
+struct Peer {
+    client: RaftClient<Channel>,
+    state: Mutex<PeerState>,
+}
+
+// Connections to a node's peers and each peer's health. Channels connect
+// lazily and reconnect by themselves; the manager decides when a peer is
+// worth a call. A peer that failed is left alone while it backs off, then
+// probed with a single call at a time until one succeeds, so a dead peer
+// costs nothing but its own slots.
+#[derive(Clone)]
+pub struct PeerManager {
+    config: Arc<PeerConfig>,
+    peers: Arc<HashMap<NodeId, Peer>>,
+}
+
+impl PeerManager {
+    pub(crate) fn new(config: PeerConfig, clients: HashMap<NodeId, RaftClient<Channel>>) -> Self {
+        let peers = clients
+            .into_iter()
+            .map(|(id, client)| {
+                let peer = Peer {
+                    client,
+                    state: Mutex::new(PeerState::default()),
+                };
+                (id, peer)
+            })
+            .collect();
+
+        Self {
+            config: Arc::new(config),
+            peers: Arc::new(peers),
+        }
+    }
+
+    // The same connections under a new configuration.
+    pub(crate) fn reconfigured(&self, config: PeerConfig) -> Self {
+        let clients = self
+            .peers
+            .iter()
+            .map(|(&id, peer)| (id, peer.client.clone()))
+            .collect();
+        Self::new(config, clients)
+    }
+
+    pub fn config(&self) -> &PeerConfig {
+        &self.config
+    }
+
+    pub fn status(&self, peer: NodeId) -> Option<PeerStatus> {
+        let state = self.peers.get(&peer)?.state.lock().unwrap();
+        Some(PeerStatus {
+            reachable: state.failures == 0,
+            failures: state.failures,
+            in_flight: state.in_flight,
+        })
+    }
+
+    pub fn reachable(&self, peer: NodeId) -> bool {
+        self.status(peer).is_some_and(|status| status.reachable)
+    }
+
+    // Takes a call slot for `peer`, or `None` if the peer is unknown, backing
+    // off, or has no free slot.
+    pub(crate) fn start(&self, peer: NodeId) -> Option<(RaftClient<Channel>, Call)> {
+        let entry = self.peers.get(&peer)?;
+        let mut state = entry.state.lock().unwrap();
+
+        if state.retry_at.is_some_and(|at| Instant::now() < at) {
+            return None;
+        }
+        let limit = if state.failures > 0 {
+            1
+        } else {
+            self.config.max_in_flight
+        };
+        if state.in_flight >= limit {
+            return None;
+        }
+
+        state.in_flight += 1;
+        let call = Call {
+            manager: self.clone(),
+            peer,
+        };
+        Some((entry.client.clone(), call))
+    }
+}
+
+// A call slot, given back when dropped. `succeeded` and `failed` record the
+// outcome; a call dropped without one leaves the peer's health unchanged.
+pub(crate) struct Call {
+    manager: PeerManager,
+    peer: NodeId,
+}
+
+impl Call {
+    pub(crate) fn succeeded(self) {
+        let mut state = self.state();
+        state.failures = 0;
+        state.retry_at = None;
+    }
+
+    pub(crate) fn failed(self) {
+        let mut state = self.state();
+        state.failures += 1;
+        let backoff = self
+            .manager
+            .config
+            .backoff(state.failures, &mut rand::rng());
+        state.retry_at = Some(Instant::now() + backoff);
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, PeerState> {
+        self.manager.peers[&self.peer].state.lock().unwrap()
+    }
+}
+
+impl Drop for Call {
+    fn drop(&mut self) {
+        self.state().in_flight -= 1;
+    }
+}
@@ -0,0 +1,60 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use crate::types::Term;
+
+// Commands proposed to the leader that are not in its log yet. They go in
+// as batches: one log append, one sync and one AppendEntries round for all
+// proposals that arrived in the meantime, instead of one of each per write.
+#[derive(Debug, Clone, Default)]
+pub struct ProposalQueue {
+    // The term the queued proposals were accepted in.
+    term: Term,
+    pending: VecDeque<(Instant, Vec<u8>)>,
+}
+
+impl ProposalQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, term: Term, command: Vec<u8>, now: Instant) {
+        if term != self.term {
+            self.pending.clear();
+            self.term = term;
+        }
+        self.pending.push_back((now, command));
+    }
+
+    pub fn term(&self) -> Term {
+        self.term
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+
+    // A batch is cut once it is full or its oldest proposal has waited long
+    // enough.
+    pub fn is_due(&self, now: Instant, max_size: usize, max_delay: Duration) -> bool {
+        self.pending.front().is_some_and(|&(queued_at, _)| {
+            self.pending.len() >= max_size || now.saturating_duration_since(queued_at) >= max_delay
+        })
+    }
+
+    pub fn take_batch(&mut self, max_size: usize) -> Vec<Vec<u8>> {
+        let size = self.pending.len().min(max_size.max(1));
+        self.pending
+            .drain(..size)
+            .map(|(_, command)| command)
+            .collect()
+    }
+
+    pub fn clear(&mut self) {
+        self.pending.clear();
+    }
+}
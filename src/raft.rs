@@ -1,30 +1,195 @@
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
+use std::time::{Duration, Instant};
 
 use crate::{
     event::RaftEvent,
+    log::LogEntry,
     node::RaftNode,
-    rpc::{AppendEntriesRequest, RequestVoteRequest},
-    timer::random_election_timeout,
-    types::NodeId,
+    read_index::{ReadConsistency, ReadError, ReadId},
+    rpc::{
+        AppendEntriesRequest, AppendEntriesResponse, ReadIndexRequest, ReadIndexResponse,
+        RequestVoteRequest, RequestVoteResponse,
+    },
+    session::{
+        ClientId, Command, CommandResult, ProposeOutcome, SessionCommand, SessionExpired,
+        SessionStatus, SessionTable,
+    },
+    state_machine::{InvalidSnapshot, KvStore, SnapshotReader, StateMachine, put_bytes},
+    storage::{HardState, MemStorage, Storage},
+    trace::TraceEvent,
+    types::{LogIndex, NodeId, RaftState, Term},
 };
 
 pub struct RaftRunner {
     node: RaftNode,
     event_queue: VecDeque<RaftEvent>,
+    outbox: Vec<RaftAction>,
+    state_machine: Box<dyn StateMachine>,
+    storage: Box<dyn Storage>,
+    persisted_hard_state: HardState,
+    // Leader entries written and sent to followers, but not synced yet.
+    unsynced_through: Option<LogIndex>,
+    sessions: SessionTable,
+    command_results: Vec<CommandResult>,
+    pending_reads: HashMap<ReadId, Vec<u8>>,
+    confirmed_reads: Vec<(ReadId, LogIndex)>,
+    // Follower side: reads waiting for a read index from this leader.
+    forwarded_reads: HashMap<ReadId, NodeId>,
+    // Leader side: reads registered on behalf of (follower, request id).
+    remote_reads: HashMap<ReadId, (NodeId, u64)>,
+    read_results: Vec<(ReadId, Result<Vec<u8>, ReadError>)>,
+    // Set by client calls so the next tick is not skipped as idle.
+    has_input: bool,
+    trace: Option<Vec<TraceEvent>>,
+    trace_epoch: Instant,
+    // State, term and commit index as of the last trace event.
+    observed: (RaftState, Term, LogIndex),
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub enum RaftAction {
     SendRequestVote(NodeId, RequestVoteRequest),
+    SendRequestVoteResponse(NodeId, RequestVoteResponse),
     SendAppendEntries(NodeId, AppendEntriesRequest),
+    SendAppendEntriesResponse(NodeId, AppendEntriesResponse),
+    SendReadIndex(NodeId, ReadIndexRequest),
+    SendReadIndexResponse(NodeId, ReadIndexResponse),
+}
+
+impl RaftAction {
+    pub fn target(&self) -> NodeId {
+        match self {
+            RaftAction::SendRequestVote(to, _)
+            | RaftAction::SendRequestVoteResponse(to, _)
+            | RaftAction::SendAppendEntries(to, _)
+            | RaftAction::SendAppendEntriesResponse(to, _)
+            | RaftAction::SendReadIndex(to, _)
+            | RaftAction::SendReadIndexResponse(to, _) => *to,
+        }
+    }
+
+    // The event the target sees when this message, sent by `from`, arrives.
+    pub fn into_event(self, from: NodeId) -> RaftEvent {
+        match self {
+            RaftAction::SendRequestVote(_, request) => RaftEvent::ReceivedRequestVote(request),
+            RaftAction::SendRequestVoteResponse(_, response) => {
+                RaftEvent::ReceivedRequestVoteResponse(from, response)
+            }
+            RaftAction::SendAppendEntries(_, request) => RaftEvent::ReceivedAppendEntries(request),
+            RaftAction::SendAppendEntriesResponse(_, response) => {
+                RaftEvent::ReceivedAppendEntriesResponse(from, response)
+            }
+            RaftAction::SendReadIndex(_, request) => RaftEvent::ReceivedReadIndex(request),
+            RaftAction::SendReadIndexResponse(_, response) => {
+                RaftEvent::ReceivedReadIndexResponse(response)
+            }
+        }
+    }
 }
 
 impl RaftRunner {
     pub fn new(node: RaftNode) -> Self {
+        Self::with_state_machine(node, Box::new(KvStore::new()))
+    }
+
+    pub fn with_state_machine(node: RaftNode, state_machine: Box<dyn StateMachine>) -> Self {
+        Self::with_storage(node, state_machine, Box::new(MemStorage::new()))
+    }
+
+    // Restores the node's term, vote and log from `storage` before it runs.
+    pub fn with_storage(
+        mut node: RaftNode,
+        state_machine: Box<dyn StateMachine>,
+        storage: Box<dyn Storage>,
+    ) -> Self {
+        // With a cache budget only the newest commands are loaded; the rest
+        // are read back when needed.
+        let hard_state = match node.config.log_cache_bytes.zip(storage.log_reader()) {
+            Some((budget, reader)) => {
+                let (hard_state, offset, entries, cached_from) = storage.load_tail(budget);
+                node.restore(hard_state, offset, entries);
+                node.log.set_cache(reader, budget, cached_from);
+                node.log.evict(node.durable_index);
+                hard_state
+            }
+            None => {
+                let (hard_state, offset, entries) = storage.load();
+                node.restore(hard_state, offset, entries);
+                hard_state
+            }
+        };
+        node.restore_membership();
+        let sessions = SessionTable::new(node.config.session_timeout);
+        let trace_epoch = node.clock.now();
+        let observed = (node.state, node.current_term, node.commit_index);
+
         Self {
             node,
             event_queue: VecDeque::new(),
+            outbox: Vec::new(),
+            state_machine,
+            storage,
+            persisted_hard_state: hard_state,
+            unsynced_through: None,
+            sessions,
+            command_results: Vec::new(),
+            pending_reads: HashMap::new(),
+            confirmed_reads: Vec::new(),
+            forwarded_reads: HashMap::new(),
+            remote_reads: HashMap::new(),
+            read_results: Vec::new(),
+            has_input: false,
+            trace: None,
+            trace_epoch,
+            observed,
+        }
+    }
+
+    // Starts recording a trace. Call it before the first tick: replay starts
+    // from the persistent state recorded here.
+    pub fn enable_trace(&mut self) {
+        self.trace_epoch = self.node.clock.now();
+        self.observed = (self.node.state, self.node.current_term, self.node.commit_index);
+        self.trace = Some(vec![TraceEvent::Started {
+            at: Duration::ZERO,
+            id: self.node.id,
+            members: self.node.initial_members.clone(),
+            config: self.node.config.clone(),
+            hard_state: self.node.hard_state(),
+            entries: self.node.log.entries().into_owned(),
+        }]);
+    }
+
+    pub fn take_trace(&mut self) -> Vec<TraceEvent> {
+        self.trace.as_mut().map(std::mem::take).unwrap_or_default()
+    }
+
+    fn record(&mut self, event: impl FnOnce(Duration) -> TraceEvent) {
+        if let Some(trace) = &mut self.trace {
+            let at = self.node.clock.now().saturating_duration_since(self.trace_epoch);
+            trace.push(event(at));
+        }
+    }
+
+    fn record_changes(&mut self) {
+        let (state, term, commit_index) = self.observed;
+        let node = &self.node;
+
+        if (state, term) != (node.state, node.current_term) {
+            let (to, term) = (node.state, node.current_term);
+            self.record(|at| TraceEvent::StateChanged {
+                at,
+                from: state,
+                to,
+                term,
+            });
+        }
+        if commit_index != self.node.commit_index {
+            let index = self.node.commit_index;
+            self.record(|at| TraceEvent::Committed { at, index });
         }
+
+        self.observed = (self.node.state, self.node.current_term, self.node.commit_index);
     }
 
     pub fn node_mut(&mut self) -> &mut RaftNode {
@@ -32,20 +197,61 @@ impl RaftRunner {
     }
 
     pub fn tick(&mut self) -> Vec<RaftAction> {
-        let mut actions = Vec::new();
+        let election_timeout = self.node.election_timer.is_elapsed();
+        let heartbeat_timeout = self.node.is_leader() && self.node.heartbeat_timer.is_elapsed();
+
+        self.step(election_timeout, heartbeat_timeout)
+    }
+
+    // One pass of the event loop with the timers' state passed in, so a trace
+    // can be replayed with timers firing exactly where they did. A pass with
+    // no timer, message or client call to handle does nothing and is skipped.
+    pub fn step(&mut self, election_timeout: bool, heartbeat_timeout: bool) -> Vec<RaftAction> {
+        let idle = !election_timeout
+            && !heartbeat_timeout
+            && !self.has_input
+            && self.unsynced_through.is_none()
+            && !self.node.proposals_due()
+            && self.event_queue.is_empty()
+            && self.outbox.is_empty()
+            && !self.node.read_queue.needs_round();
+        if idle {
+            return Vec::new();
+        }
+
+        self.has_input = false;
+        self.record(|at| TraceEvent::Tick {
+            at,
+            election_timeout,
+            heartbeat_timeout,
+        });
+        let mut actions = std::mem::take(&mut self.outbox);
+        self.sync();
 
-        if self.node.election_timer.is_elapsed() {
-            if self.node.is_follower() || self.node.is_candidate() {
-                let old_term = self.node.current_term;
+        // A batch of proposals goes into the log as one append, is synced
+        // with everything else at the end of this pass, and is sent right away.
+        let batch = self.node.flush_proposals();
+        for &index in &batch {
+            self.record(|at| TraceEvent::Appended { at, index });
+        }
+
+        if election_timeout {
+            if self.node.is_leader() {
+                self.node.reset_election_timer();
+
+                if self.node.config.check_quorum && !self.node.check_quorum() {
+                    self.node.become_follower(self.node.current_term);
+                }
+            } else if !self.node.is_member() {
+                // Outside the cluster, e.g. not added yet or removed.
+                self.node.reset_election_timer();
+            } else {
                 self.node.become_candidate();
-                self.node
-                    .election_timer
-                    .reset_with(random_election_timeout());
+                self.node.reset_election_timer();
 
-                println!(
-                    "  [ELECTION] Node {:?}: Started election (Term {:?} -> {:?})",
-                    self.node.id, old_term, self.node.current_term
-                );
+                if self.node.is_leader() {
+                    self.broadcast_append_entries(&mut actions);
+                }
 
                 for peer in &self.node.peers.clone() {
                     let request = RequestVoteRequest {
@@ -59,48 +265,430 @@ impl RaftRunner {
             }
         }
 
-        if self.node.heartbeat_timer.is_elapsed() {
-            if self.node.is_leader() {
-                self.node.heartbeat_timer.reset();
-
-                for peer in &self.node.peers.clone() {
-                    let request = self.node.create_append_entries(peer);
-                    actions.push(RaftAction::SendAppendEntries(*peer, request));
-                }
-            }
+        // Pending reads don't wait for the heartbeat timer: the next round is
+        // sent right away and confirms every read registered since the last one.
+        if self.node.is_leader()
+            && (heartbeat_timeout || self.node.read_queue.needs_round() || !batch.is_empty())
+        {
+            self.broadcast_append_entries(&mut actions);
         }
+        self.record_changes();
 
         while let Some(event) = self.event_queue.pop_front() {
             self.handle_event(event, &mut actions);
+            self.record_changes();
+        }
+
+        self.apply_committed();
+        self.serve_reads(&mut actions);
+        self.persist();
+
+        if self.trace.is_some() {
+            for action in &actions {
+                self.record(|at| TraceEvent::Sent {
+                    at,
+                    action: action.clone(),
+                });
+            }
         }
 
         actions
     }
 
+    // Term, vote and new log entries must be durable before any message that
+    // depends on them leaves the node. The exception is a leader's own new
+    // entries (Raft thesis 10.2.1): they go to followers while they are
+    // still being written locally, and `sync` finishes the write.
+    fn persist(&mut self) {
+        let mut written = false;
+
+        let hard_state = self.node.hard_state();
+        if hard_state != self.persisted_hard_state {
+            self.storage.save_hard_state(hard_state);
+            self.persisted_hard_state = hard_state;
+            written = true;
+        }
+
+        let appended = match self.node.log.take_unstable_from() {
+            Some(from) => {
+                self.storage.truncate(from);
+                self.storage.append(&self.node.log.entries_from(from));
+                true
+            }
+            None => false,
+        };
+
+        let last = self.node.log.last_log_index();
+        if written || (appended && !self.node.is_leader()) {
+            self.storage.sync();
+            self.unsynced_through = None;
+            self.node.persisted(last);
+        } else if appended {
+            self.unsynced_through = Some(last);
+        }
+    }
+
+    // Syncs the entries the leader wrote and sent in the last pass, which
+    // lets it count itself towards committing them. Hosts call this once
+    // the pass's messages are on their way; otherwise the next pass does.
+    pub fn sync(&mut self) {
+        if let Some(index) = self.unsynced_through.take() {
+            self.storage.sync();
+            self.node.persisted(index);
+            self.has_input = true;
+        }
+    }
+
     pub fn push_event(&mut self, event: RaftEvent) {
+        if self.trace.is_some() {
+            self.record(|at| TraceEvent::Received {
+                at,
+                message: event.clone(),
+            });
+        }
         self.event_queue.push_back(event);
     }
 
-    pub fn handle_event(&mut self, event: RaftEvent, _actions: &mut Vec<RaftAction>) {
+    pub fn handle_event(&mut self, event: RaftEvent, actions: &mut Vec<RaftAction>) {
         match event {
             RaftEvent::ReceivedRequestVote(request) => {
-                let _response = self.node.handle_request_vote(request);
+                let candidate_id = request.candidate_id;
+                let response = self.node.handle_request_vote(request);
+                if response.vote_granted {
+                    self.node.reset_election_timer();
+                }
+                actions.push(RaftAction::SendRequestVoteResponse(candidate_id, response));
             }
-            RaftEvent::ReceivedRequestVoteResponse(response) => {
-                self.node.handle_request_response(response);
+            RaftEvent::ReceivedRequestVoteResponse(peer, response) => {
+                let was_leader = self.node.is_leader();
+                self.node.handle_request_vote_response(peer, response);
+                if !was_leader && self.node.is_leader() {
+                    self.broadcast_append_entries(actions);
+                }
             }
             RaftEvent::ReceivedAppendEntries(request) => {
-                let _response = self.node.handle_append_entries(request);
-                self.node
-                    .election_timer
-                    .reset_with(random_election_timeout());
+                let leader_id = request.leader_id;
+                let response = self.node.handle_append_entries(request);
+                self.node.reset_election_timer();
+                actions.push(RaftAction::SendAppendEntriesResponse(leader_id, response));
+            }
+            RaftEvent::ReceivedAppendEntriesResponse(peer, response) => {
+                let success = response.success;
+                self.node.handle_append_entries_response(peer, response);
+                // A follower last sent a window short of the end of the log
+                // gets the next one right away instead of at the next
+                // heartbeat.
+                if success && self.node.is_leader() && self.node.cut_short.contains(&peer) {
+                    let request = self.node.create_append_entries(&peer);
+                    actions.push(RaftAction::SendAppendEntries(peer, request));
+                }
+            }
+            RaftEvent::ReceivedReadIndex(request) => {
+                // A read index is only good for a follower of this term.
+                let id = if request.term == self.node.current_term {
+                    self.node.read_index()
+                } else {
+                    None
+                };
+                match id {
+                    Some(id) => {
+                        self.remote_reads
+                            .insert(id, (request.follower_id, request.request_id));
+                    }
+                    None => {
+                        let response = self.read_index_response(request.request_id, None);
+                        actions.push(RaftAction::SendReadIndexResponse(request.follower_id, response));
+                    }
+                }
+            }
+            RaftEvent::ReceivedReadIndexResponse(response) => {
+                let id = response.request_id;
+                if self.forwarded_reads.remove(&id).is_none() {
+                    return;
+                }
+
+                // From a leader of another term, the index may be stale.
+                if response.success && response.term == self.node.current_term {
+                    self.confirmed_reads.push((id, response.read_index));
+                } else if self.pending_reads.remove(&id).is_some() {
+                    self.read_results.push((id, Err(ReadError::NotLeader)));
+                }
             }
-            RaftEvent::ReceivedAppendEntriesResponse(_response) => {}
-            
             _ => {}
         }
     }
 
+    fn broadcast_append_entries(&mut self, actions: &mut Vec<RaftAction>) {
+        self.node.heartbeat_timer.reset();
+        self.node.start_heartbeat_round();
+
+        for peer in &self.node.peers.clone() {
+            let request = self.node.create_append_entries(peer);
+            actions.push(RaftAction::SendAppendEntries(*peer, request));
+        }
+    }
+
+    // Exactly-once proposal: a retried (client, sequence) pair that was
+    // already applied gets the cached response instead of a new log entry.
+    // A new command is queued and appended with the next batch.
+    pub fn propose(
+        &mut self,
+        client_id: ClientId,
+        sequence: u64,
+        command: Vec<u8>,
+    ) -> Option<ProposeOutcome> {
+        if self.trace.is_some() {
+            self.record(|at| TraceEvent::Proposed {
+                at,
+                client_id,
+                sequence,
+                command: command.clone(),
+            });
+        }
+        self.has_input = true;
+
+        if !self.node.is_leader() {
+            return None;
+        }
+
+        // A client without a session may only look expired here because its
+        // first command has not been applied yet; applying decides.
+        let outcome = match self.sessions.status(client_id, sequence, self.node.last_applied) {
+            SessionStatus::Duplicate(response) => Some(ProposeOutcome::Cached(response)),
+            SessionStatus::Stale => Some(ProposeOutcome::Stale),
+            SessionStatus::New | SessionStatus::Expired => {
+                let command = SessionCommand {
+                    client_id,
+                    sequence,
+                    command,
+                };
+                self.node
+                    .propose(command.encode())
+                    .then_some(ProposeOutcome::Queued)
+            }
+        };
+        self.record_changes();
+        outcome
+    }
+
+    // Adds or removes one voting member; see `RaftNode::change_membership`.
+    // Returns the index of the change, or `None` if it was refused.
+    pub fn change_membership(&mut self, members: Vec<NodeId>) -> Option<LogIndex> {
+        if self.trace.is_some() {
+            self.record(|at| TraceEvent::MembershipRequested {
+                at,
+                members: members.clone(),
+            });
+        }
+        self.has_input = true;
+
+        let index = self.node.change_membership(members)?;
+        self.record(|at| TraceEvent::Appended { at, index });
+        self.record_changes();
+
+        let mut actions = Vec::new();
+        self.broadcast_append_entries(&mut actions);
+        self.outbox.extend(actions);
+        Some(index)
+    }
+
+    pub fn take_command_results(&mut self) -> Vec<CommandResult> {
+        std::mem::take(&mut self.command_results)
+    }
+
+    pub fn read(&mut self, query: Vec<u8>, consistency: ReadConsistency) -> Option<ReadId> {
+        if self.trace.is_some() {
+            self.record(|at| TraceEvent::ReadRequested {
+                at,
+                query: query.clone(),
+                consistency,
+            });
+        }
+        self.has_input = true;
+
+        let id = match consistency {
+            ReadConsistency::Stale => {
+                let id = self.node.read_queue.allocate_id();
+                let value = self.state_machine.query(&query);
+                self.read_results.push((id, Ok(value)));
+                return Some(id);
+            }
+            ReadConsistency::LinearizableFollower if !self.node.is_leader() => {
+                let leader_id = self.node.leader_id?;
+                let id = self.node.read_queue.allocate_id();
+                let request = ReadIndexRequest {
+                    term: self.node.current_term,
+                    follower_id: self.node.id,
+                    request_id: id,
+                };
+                self.forwarded_reads.insert(id, leader_id);
+                self.outbox.push(RaftAction::SendReadIndex(leader_id, request));
+                id
+            }
+            _ => self.node.read_index()?,
+        };
+
+        self.pending_reads.insert(id, query);
+        Some(id)
+    }
+
+    pub fn take_read_results(&mut self) -> Vec<(ReadId, Result<Vec<u8>, ReadError>)> {
+        std::mem::take(&mut self.read_results)
+    }
+
+    // Replicated state: the session table followed by the state machine.
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mut out = Vec::new();
+        put_bytes(&mut out, &self.sessions.snapshot());
+        put_bytes(&mut out, &self.state_machine.snapshot());
+        out
+    }
+
+    pub fn restore_snapshot(&mut self, snapshot: &[u8]) -> Result<(), InvalidSnapshot> {
+        let mut reader = SnapshotReader::new(snapshot);
+        let sessions = reader.bytes()?;
+        let state = reader.bytes()?;
+        reader.finish()?;
+
+        let mut table = SessionTable::new(self.node.config.session_timeout);
+        table.restore(sessions)?;
+        self.state_machine.restore(state)?;
+        self.sessions = table;
+        Ok(())
+    }
+
+    // Takes the committed entries a batch at a time, so a long backlog is
+    // never read back from disk all at once.
+    fn apply_committed(&mut self) {
+        let mut last = None;
+        loop {
+            let entries = self.node.take_committed_entries();
+            let Some(entry) = entries.last() else {
+                break;
+            };
+            last = Some(entry.index);
+            self.apply_entries(entries);
+        }
+        let Some(last) = last else {
+            return;
+        };
+        self.record(|at| TraceEvent::Applied { at, index: last });
+
+        // Dropping idle sessions is only housekeeping, since `status` treats
+        // one past its timeout as gone already; once per call is enough.
+        self.sessions.expire(last);
+    }
+
+    fn apply_entries(&mut self, entries: Vec<LogEntry>) {
+        for entry in entries {
+            if entry.command.is_empty() {
+                continue;
+            }
+
+            let command = match Command::decode(&entry.command) {
+                Some(Command::Session(command)) => command,
+                Some(Command::Plain(command)) => {
+                    self.state_machine.apply(&command);
+                    continue;
+                }
+                // Took effect when it was appended.
+                Some(Command::Membership(_)) => continue,
+                // Not written by this code; every replica skips it alike.
+                None => continue,
+            };
+
+            // The same command can be in the log twice if the client retried
+            // before the first copy committed; only the first one is applied.
+            // Neither is once the session is gone, as it may have been before.
+            let response = match self.sessions.status(command.client_id, command.sequence, entry.index) {
+                SessionStatus::New => {
+                    let response = self.state_machine.apply(&command.command);
+                    self.sessions.record(
+                        command.client_id,
+                        command.sequence,
+                        response.clone(),
+                        entry.index,
+                    );
+                    Ok(response)
+                }
+                SessionStatus::Duplicate(response) => {
+                    self.sessions.touch(command.client_id, entry.index);
+                    Ok(response)
+                }
+                SessionStatus::Expired => Err(SessionExpired),
+                SessionStatus::Stale => continue,
+            };
+
+            if self.node.is_leader() {
+                self.command_results.push(CommandResult {
+                    client_id: command.client_id,
+                    sequence: command.sequence,
+                    response,
+                });
+            }
+        }
+    }
+
+    fn serve_reads(&mut self, actions: &mut Vec<RaftAction>) {
+        for id in self.node.read_queue.take_aborted() {
+            if let Some((follower_id, request_id)) = self.remote_reads.remove(&id) {
+                let response = self.read_index_response(request_id, None);
+                actions.push(RaftAction::SendReadIndexResponse(follower_id, response));
+            } else if self.pending_reads.remove(&id).is_some() {
+                self.read_results.push((id, Err(ReadError::NotLeader)));
+            }
+        }
+
+        // A forwarded read is given up once the leader it was sent to is no
+        // longer the one this node follows; its response may never arrive.
+        let leader_id = self.node.leader_id;
+        let mut abandoned: Vec<ReadId> = self
+            .forwarded_reads
+            .iter()
+            .filter(|&(_, &target)| Some(target) != leader_id)
+            .map(|(&id, _)| id)
+            .collect();
+        abandoned.sort_unstable();
+        for id in abandoned {
+            self.forwarded_reads.remove(&id);
+            if self.pending_reads.remove(&id).is_some() {
+                self.read_results.push((id, Err(ReadError::NotLeader)));
+            }
+        }
+
+        for (id, index) in self.node.read_queue.take_confirmed() {
+            if let Some((follower_id, request_id)) = self.remote_reads.remove(&id) {
+                let response = self.read_index_response(request_id, Some(index));
+                actions.push(RaftAction::SendReadIndexResponse(follower_id, response));
+            } else {
+                self.confirmed_reads.push((id, index));
+            }
+        }
+
+        let last_applied = self.node.last_applied;
+        let (ready, waiting): (Vec<_>, Vec<_>) = self
+            .confirmed_reads
+            .drain(..)
+            .partition(|&(_, index)| index <= last_applied);
+        self.confirmed_reads = waiting;
+
+        for (id, _) in ready {
+            if let Some(query) = self.pending_reads.remove(&id) {
+                let value = self.state_machine.query(&query);
+                self.read_results.push((id, Ok(value)));
+            }
+        }
+    }
+
+    fn read_index_response(&self, request_id: u64, read_index: Option<LogIndex>) -> ReadIndexResponse {
+        ReadIndexResponse {
+            term: self.node.current_term,
+            request_id,
+            success: read_index.is_some(),
+            read_index: read_index.unwrap_or(LogIndex::ZERO),
+        }
+    }
+
     pub fn node(&self) -> &RaftNode {
         &self.node
     }
@@ -0,0 +1,154 @@
+use std::collections::{HashMap, VecDeque};
+use std::time::Instant;
+
+use serde::{Deserialize, Serialize};
+
+use crate::types::{LogIndex, NodeId};
+
+pub type ReadId = u64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadError {
+    NotLeader,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReadConsistency {
+    // ReadIndex (or lease) on the leader.
+    #[default]
+    LinearizableLeader,
+    // A follower asks the leader for its read index and waits until it has
+    // applied up to it locally.
+    LinearizableFollower,
+    // Whatever the local state machine holds, possibly behind the leader.
+    Stale,
+}
+
+#[derive(Debug, Clone)]
+struct PendingRead {
+    id: ReadId,
+    index: LogIndex,
+    round: u64,
+}
+
+// Reads registered between two heartbeat rounds wait for the same round, so a
+// burst of concurrent reads costs a single round trip to a quorum.
+#[derive(Debug, Clone, Default)]
+pub struct ReadIndexQueue {
+    next_id: ReadId,
+    round: u64,
+    acked_round: HashMap<NodeId, u64>,
+    round_sent_at: VecDeque<(u64, Instant)>,
+    pending: VecDeque<PendingRead>,
+    confirmed: Vec<(ReadId, LogIndex)>,
+    aborted: Vec<ReadId>,
+}
+
+impl ReadIndexQueue {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn round(&self) -> u64 {
+        self.round
+    }
+
+    pub fn start_round(&mut self, now: Instant) -> u64 {
+        self.round += 1;
+        self.round_sent_at.push_back((self.round, now));
+        self.round
+    }
+
+    pub fn needs_round(&self) -> bool {
+        self.pending.back().is_some_and(|read| read.round > self.round)
+    }
+
+    pub fn allocate_id(&mut self) -> ReadId {
+        self.next_id += 1;
+        self.next_id
+    }
+
+    pub fn register(&mut self, index: LogIndex) -> ReadId {
+        let id = self.allocate_id();
+        self.pending.push_back(PendingRead {
+            id,
+            index,
+            round: self.round + 1,
+        });
+        id
+    }
+
+    pub fn register_confirmed(&mut self, index: LogIndex) -> ReadId {
+        let id = self.allocate_id();
+        self.confirmed.push((id, index));
+        id
+    }
+
+    pub fn acknowledge(&mut self, peer: NodeId, round: u64) {
+        let acked = self.acked_round.entry(peer).or_insert(0);
+        *acked = (*acked).max(round);
+    }
+
+    pub fn confirm(&mut self, peers: &[NodeId], quorum: usize) {
+        while let Some(read) = self.pending.front() {
+            let acks = peers
+                .iter()
+                .filter(|peer| self.acked_round.get(peer).is_some_and(|&r| r >= read.round))
+                .count();
+
+            if acks + 1 < quorum {
+                break;
+            }
+
+            let read = self.pending.pop_front().unwrap();
+            self.confirmed.push((read.id, read.index));
+        }
+    }
+
+    // Send time of the newest round acknowledged by a quorum. Followers that
+    // acked it heard from us no earlier than this, which is where a lease starts.
+    pub fn quorum_round_sent_at(&mut self, peers: &[NodeId], quorum: usize) -> Option<Instant> {
+        let mut rounds: Vec<u64> = peers
+            .iter()
+            .map(|peer| self.acked_round.get(peer).copied().unwrap_or(0))
+            .collect();
+        rounds.push(self.round);
+        rounds.sort_unstable_by(|a, b| b.cmp(a));
+
+        let quorum_round = *rounds.get(quorum.checked_sub(1)?)?;
+
+        while self
+            .round_sent_at
+            .front()
+            .is_some_and(|&(round, _)| round < quorum_round)
+        {
+            self.round_sent_at.pop_front();
+        }
+
+        self.round_sent_at
+            .front()
+            .filter(|&&(round, _)| round == quorum_round)
+            .map(|&(_, sent_at)| sent_at)
+    }
+
+    pub fn abort_all(&mut self) {
+        self.aborted.extend(self.pending.drain(..).map(|read| read.id));
+    }
+
+    pub fn take_confirmed(&mut self) -> Vec<(ReadId, LogIndex)> {
+        std::mem::take(&mut self.confirmed)
+    }
+
+    pub fn take_aborted(&mut self) -> Vec<ReadId> {
+        std::mem::take(&mut self.aborted)
+    }
+
+    pub fn len(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
@@ -1,6 +1,8 @@
+use serde::{Deserialize, Serialize};
+
 use crate::{log::LogEntry, types::{LogIndex, NodeId, Term}};
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct RequestVoteRequest {
     pub term: Term,
     pub candidate_id: NodeId,
@@ -8,13 +10,13 @@ pub struct RequestVoteRequest {
     pub last_log_term: Term,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct RequestVoteResponse {
     pub term: Term,
     pub vote_granted: bool,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct AppendEntriesRequest {
     pub term: Term,
     pub leader_id: NodeId,
@@ -22,10 +24,28 @@ pub struct AppendEntriesRequest {
     pub prev_log_term: Term,
     pub entries: Vec<LogEntry>,
     pub leader_commit: LogIndex,
+    pub heartbeat_round: u64,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct AppendEntriesResponse {
     pub term: Term,
     pub success: bool,
-}
\ No newline at end of file
+    pub match_index: LogIndex,
+    pub heartbeat_round: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReadIndexRequest {
+    pub term: Term,
+    pub follower_id: NodeId,
+    pub request_id: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReadIndexResponse {
+    pub term: Term,
+    pub request_id: u64,
+    pub success: bool,
+    pub read_index: LogIndex,
+}
@@ -0,0 +1,359 @@
+use std::fmt;
+use std::time::Duration;
+
+use crate::fuzz::{
+    self, Driver, Failure, ParseError, Step, parse_duration, parse_max_append_entries, parse_node,
+};
+use crate::node::RaftNode;
+use crate::read_index::ReadConsistency;
+use crate::session;
+use crate::types::{LogIndex, NodeId};
+
+// How long `wait` polls when the line gives no `within`.
+const DEFAULT_WAIT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Target {
+    All,
+    Leader,
+    Nodes(Vec<NodeId>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Condition {
+    // The leader of the newest term any leader has is one of `on`, or is the
+    // given node. Stale leaders cut off from the rest don't count.
+    Leader { id: Option<NodeId>, on: Target },
+    // None of `on` is leader, in any term.
+    NoLeader { on: Target },
+    // Number of committed client commands, not counting leader no-ops.
+    Committed { count: usize, on: Target },
+    Value { key: Vec<u8>, value: Vec<u8>, on: Target },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    Step(Step),
+    Wait { condition: Condition, within: Duration },
+    Assert(Condition),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Line {
+    pub number: usize,
+    pub text: String,
+    pub command: Command,
+}
+
+// A simulator test written as one command per line, e.g.
+//
+//   start 5 nodes
+//   wait leader
+//   propose "x=1"
+//   partition {1,2} {3,4,5}
+//   advance 2s
+//   assert committed 1 on all
+//
+// Steps are the ones fuzz schedules are made of, so a shrunk schedule is a
+// scenario too.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Scenario {
+    pub seed: u64,
+    pub nodes: u64,
+    pub max_append_entries: Option<usize>,
+    pub lines: Vec<Line>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ScenarioError {
+    // 0 when the failure is only detected at the end of the scenario.
+    pub line: usize,
+    pub text: String,
+    pub message: String,
+}
+
+impl fmt::Display for ScenarioError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.line == 0 {
+            write!(f, "at end of scenario: {}", self.message)
+        } else {
+            write!(f, "line {} `{}`: {}", self.line, self.text, self.message)
+        }
+    }
+}
+
+impl Scenario {
+    pub fn parse(text: &str) -> Result<Self, ParseError> {
+        let mut seed = 0;
+        let mut nodes = None;
+        let mut max_append_entries = None;
+        let mut lines = Vec::new();
+
+        for (i, line) in text.lines().enumerate() {
+            let error = |message: String| ParseError {
+                line: i + 1,
+                message,
+            };
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            if let Some(value) = line.strip_prefix("seed ") {
+                seed = value
+                    .trim()
+                    .parse()
+                    .map_err(|_| error(format!("invalid seed `{}`", value.trim())))?;
+            } else if let Some(value) = line
+                .strip_prefix("start ")
+                .and_then(|rest| rest.strip_suffix(" nodes"))
+            {
+                if nodes.is_some() {
+                    return Err(error("the cluster is already started".to_string()));
+                }
+                nodes = Some(
+                    value
+                        .trim()
+                        .parse()
+                        .map_err(|_| error(format!("invalid node count `{}`", value.trim())))?,
+                );
+            } else if let Some(value) = line.strip_prefix("max-append-entries ") {
+                if nodes.is_some() {
+                    return Err(error("the cluster is already started".to_string()));
+                }
+                max_append_entries = Some(parse_max_append_entries(value).map_err(error)?);
+            } else if nodes.is_none() {
+                return Err(error("expected `start <n> nodes` first".to_string()));
+            } else {
+                lines.push(Line {
+                    number: i + 1,
+                    text: line.to_string(),
+                    command: parse_command(line).map_err(error)?,
+                });
+            }
+        }
+
+        Ok(Self {
+            seed,
+            nodes: nodes.ok_or(ParseError {
+                line: 0,
+                message: "missing `start <n> nodes` line".to_string(),
+            })?,
+            max_append_entries,
+            lines,
+        })
+    }
+
+    pub fn run(&self) -> Result<(), ScenarioError> {
+        self.run_on(&mut self.driver())
+    }
+
+    // A driver for the cluster the scenario starts.
+    pub fn driver(&self) -> Driver {
+        let config = fuzz::config(self.max_append_entries);
+        Driver::with_config(self.nodes, config, self.seed)
+    }
+
+    // Runs on a driver the caller keeps, e.g. to export its trace afterwards.
+    pub fn run_on(&self, driver: &mut Driver) -> Result<(), ScenarioError> {
+        for line in &self.lines {
+            let error = |message: String| ScenarioError {
+                line: line.number,
+                text: line.text.clone(),
+                message,
+            };
+
+            match &line.command {
+                Command::Step(Step::Propose(command)) => {
+                    if driver.propose(command).is_none() {
+                        return Err(error("no leader accepted the proposal".to_string()));
+                    }
+                }
+                Command::Step(step) => driver.apply(step).map_err(|failure| error(failure.to_string()))?,
+                Command::Wait { condition, within } => {
+                    let until = driver.sim().elapsed() + *within;
+                    while let Err(message) = check(driver, condition) {
+                        if driver.sim().elapsed() >= until {
+                            return Err(error(format!("still not true after {:?}: {}", within, message)));
+                        }
+                        driver.tick().map_err(|failure| error(failure.to_string()))?;
+                    }
+                }
+                Command::Assert(condition) => check(driver, condition).map_err(error)?,
+            }
+        }
+
+        driver
+            .check_history()
+            .map_err(|failure: Failure| ScenarioError {
+                line: 0,
+                text: String::new(),
+                message: failure.to_string(),
+            })
+    }
+}
+
+fn parse_command(line: &str) -> Result<Command, String> {
+    if let Some(rest) = line.strip_prefix("wait ") {
+        let (condition, within) = match rest.rsplit_once(" within ") {
+            Some((condition, within)) => (condition, parse_duration(within.trim())?),
+            None => (rest, DEFAULT_WAIT),
+        };
+        return Ok(Command::Wait {
+            condition: parse_condition(condition)?,
+            within,
+        });
+    }
+    if let Some(rest) = line.strip_prefix("assert ") {
+        return parse_condition(rest).map(Command::Assert);
+    }
+    line.parse().map(Command::Step)
+}
+
+fn parse_condition(text: &str) -> Result<Condition, String> {
+    let (text, target) = match text.rsplit_once(" on ") {
+        Some((text, target)) => (text, Some(parse_target(target.trim())?)),
+        None => (text, None),
+    };
+    let on = target.clone().unwrap_or(Target::All);
+    let words: Vec<&str> = text.split_whitespace().collect();
+
+    match words.as_slice() {
+        ["leader"] => Ok(Condition::Leader { id: None, on }),
+        ["leader", _] if target.is_some() => Err("`leader <id>` takes no `on`".to_string()),
+        ["leader", id] => Ok(Condition::Leader {
+            id: Some(parse_node(id)?),
+            on,
+        }),
+        ["no", "leader"] => Ok(Condition::NoLeader { on }),
+        ["committed", count] => Ok(Condition::Committed {
+            count: count
+                .parse()
+                .map_err(|_| format!("invalid count `{}`", count))?,
+            on,
+        }),
+        ["value", key, "=", value] => Ok(Condition::Value {
+            key: unquote(key).as_bytes().to_vec(),
+            value: unquote(value).as_bytes().to_vec(),
+            on,
+        }),
+        _ => Err(format!("unknown condition `{}`", text)),
+    }
+}
+
+fn parse_target(text: &str) -> Result<Target, String> {
+    match text {
+        "all" => Ok(Target::All),
+        "leader" => Ok(Target::Leader),
+        _ => text
+            .split(',')
+            .map(|id| parse_node(id.trim()))
+            .collect::<Result<_, _>>()
+            .map(Target::Nodes),
+    }
+}
+
+fn unquote(text: &str) -> &str {
+    text.strip_prefix('"')
+        .and_then(|text| text.strip_suffix('"'))
+        .unwrap_or(text)
+}
+
+fn check(driver: &mut Driver, condition: &Condition) -> Result<(), String> {
+    match condition {
+        Condition::Leader { id, on } => {
+            let ids = targets(driver, on)?;
+            match newest_leader(driver) {
+                Some(leader) if ids.contains(&leader) && id.is_none_or(|id| id == leader) => Ok(()),
+                Some(leader) => match id {
+                    Some(id) => Err(format!("node {} is not leader (leader: {})", id.get(), leader.get())),
+                    None => Err(format!("node {} is leader", leader.get())),
+                },
+                None => Err("no leader".to_string()),
+            }
+        }
+        Condition::NoLeader { on } => {
+            let sim = driver.sim();
+            match targets(driver, on)?.into_iter().find(|&id| sim.node(id).unwrap().is_leader()) {
+                Some(leader) => Err(format!("node {} is leader", leader.get())),
+                None => Ok(()),
+            }
+        }
+        Condition::Committed { count, on } => {
+            for id in targets(driver, on)? {
+                let committed = committed_commands(driver.sim().node(id).unwrap());
+                if committed != *count {
+                    return Err(format!(
+                        "node {} has {} committed commands, expected {}",
+                        id.get(),
+                        committed,
+                        count
+                    ));
+                }
+            }
+            Ok(())
+        }
+        Condition::Value { key, value, on } => {
+            for id in targets(driver, on)? {
+                let runner = driver.sim_mut().runner_mut(id).unwrap();
+                let read_id = runner.read(key.clone(), ReadConsistency::Stale).unwrap();
+                let actual = runner
+                    .take_read_results()
+                    .into_iter()
+                    .find(|(id, _)| *id == read_id)
+                    .and_then(|(_, result)| result.ok())
+                    .unwrap_or_default();
+
+                if actual != *value {
+                    return Err(format!(
+                        "node {} has {} = {:?}, expected {:?}",
+                        id.get(),
+                        String::from_utf8_lossy(key),
+                        String::from_utf8_lossy(&actual),
+                        String::from_utf8_lossy(value)
+                    ));
+                }
+            }
+            Ok(())
+        }
+    }
+}
+
+fn targets(driver: &Driver, on: &Target) -> Result<Vec<NodeId>, String> {
+    let sim = driver.sim();
+    let ids = match on {
+        Target::All => sim
+            .node_ids()
+            .into_iter()
+            .filter(|&id| !sim.is_crashed(id))
+            .collect(),
+        Target::Leader => vec![newest_leader(driver).ok_or("no leader")?],
+        Target::Nodes(ids) => ids.clone(),
+    };
+
+    match ids.iter().find(|&&id| sim.node(id).is_none()) {
+        Some(id) => Err(format!("node {} is not running", id.get())),
+        None => Ok(ids),
+    }
+}
+
+// The running leader with the highest term, as an old leader that has not
+// heard of the new one yet may still think it leads.
+fn newest_leader(driver: &Driver) -> Option<NodeId> {
+    let sim = driver.sim();
+    sim.node_ids()
+        .into_iter()
+        .filter_map(|id| sim.node(id))
+        .filter(|node| node.is_leader())
+        .max_by_key(|node| node.current_term)
+        .map(|node| node.id)
+}
+
+fn committed_commands(node: &RaftNode) -> usize {
+    node.log
+        .entries_between(LogIndex::new(1), node.commit_index)
+        .iter()
+        .filter(|entry| !entry.command.is_empty())
+        .filter(|entry| session::Command::membership(&entry.command).is_none())
+        .count()
+}
@@ -0,0 +1,529 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Read};
+use std::os::unix::fs::FileExt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use crate::log::{LogEntry, LogOffset, LogReader};
+use crate::storage::{HardState, Storage};
+use crate::types::{LogIndex, NodeId, Term};
+
+const HARD_STATE: &str = "hard_state";
+const HARD_STATE_TMP: &str = "hard_state.tmp";
+const SEGMENT_EXTENSION: &str = "log";
+const DEFAULT_MAX_SEGMENT_BYTES: u64 = 64 << 20;
+
+// Term, index and command length ahead of each command, then a CRC-32 of
+// those fields and the command.
+const RECORD_FIELDS: usize = 24;
+const RECORD_HEADER: usize = RECORD_FIELDS + 4;
+
+// A run of consecutive entries in one file, named after the first of them.
+#[derive(Debug)]
+struct Segment {
+    first: LogIndex,
+    path: PathBuf,
+    file: File,
+    // Where each entry's record starts, followed by the end of the file.
+    offsets: Vec<u64>,
+    terms: Vec<Term>,
+    unsynced: bool,
+}
+
+impl Segment {
+    fn create(dir: &Path, first: LogIndex) -> io::Result<Self> {
+        let path = dir.join(format!("{:020}.{}", first.get(), SEGMENT_EXTENSION));
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)?;
+        Ok(Self {
+            first,
+            path,
+            file,
+            offsets: vec![0],
+            terms: Vec::new(),
+            unsynced: true,
+        })
+    }
+
+    // Only the record headers are read, skipping the commands. A record torn
+    // by a crash can only be at the end of the last segment, so that one is
+    // checksummed and cut off at the first torn record; a short record is an
+    // error anywhere else, and other checksums are checked when read.
+    fn open(path: PathBuf, first: LogIndex, last: bool) -> io::Result<Self> {
+        let file = OpenOptions::new().read(true).write(true).open(&path)?;
+        let size = file.metadata()?.len();
+        let mut reader = BufReader::new(&file);
+
+        let (mut offsets, mut terms) = (vec![0], Vec::new());
+        let mut at = 0;
+        let mut header = [0; RECORD_HEADER];
+        let mut command = Vec::new();
+        while size - at >= RECORD_HEADER as u64 {
+            reader.read_exact(&mut header)?;
+            let (term, index, len) = fields(&header);
+            let Some(end) = len
+                .checked_add(at + RECORD_HEADER as u64)
+                .filter(|&end| end <= size)
+            else {
+                break;
+            };
+            if last {
+                command.resize(len as usize, 0);
+                reader.read_exact(&mut command)?;
+                let crc = u32::from_be_bytes(header[RECORD_FIELDS..].try_into().unwrap());
+                if checksum(&header[..RECORD_FIELDS], &command) != crc {
+                    break;
+                }
+            } else {
+                reader.seek_relative(len as i64)?;
+            }
+            if index.get() != first.get() + terms.len() as u64 {
+                return Err(corrupt(&path));
+            }
+            at = end;
+            offsets.push(at);
+            terms.push(term);
+        }
+        if at < size {
+            if !last {
+                return Err(corrupt(&path));
+            }
+            file.set_len(at)?;
+        }
+
+        Ok(Self {
+            first,
+            path,
+            file,
+            offsets,
+            terms,
+            unsynced: false,
+        })
+    }
+
+    fn len(&self) -> usize {
+        self.offsets.len() - 1
+    }
+
+    fn bytes(&self) -> u64 {
+        self.offsets[self.len()]
+    }
+
+    fn next_index(&self) -> LogIndex {
+        LogIndex::new(self.first.get() + self.len() as u64)
+    }
+
+    fn command_len(&self, position: usize) -> u64 {
+        self.offsets[position + 1] - self.offsets[position] - RECORD_HEADER as u64
+    }
+
+    // Entries at positions `from..to` within the segment.
+    fn read(&self, from: usize, to: usize) -> io::Result<Vec<LogEntry>> {
+        let start = self.offsets[from];
+        let mut bytes = vec![0; (self.offsets[to] - start) as usize];
+        self.file.read_exact_at(&mut bytes, start)?;
+
+        let mut entries = Vec::with_capacity(to - from);
+        let mut at = 0;
+        while let Some((term, index, command)) = decode(&bytes[at..]) {
+            at += RECORD_HEADER + command.len();
+            entries.push(LogEntry {
+                term,
+                index,
+                command: command.to_vec(),
+            });
+        }
+        if entries.len() != to - from {
+            return Err(corrupt(&self.path));
+        }
+        Ok(entries)
+    }
+}
+
+fn checksum(fields: &[u8], command: &[u8]) -> u32 {
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(fields);
+    hasher.update(command);
+    hasher.finalize()
+}
+
+fn encode(out: &mut Vec<u8>, entry: &LogEntry) {
+    let start = out.len();
+    out.extend_from_slice(&entry.term.get().to_be_bytes());
+    out.extend_from_slice(&entry.index.get().to_be_bytes());
+    out.extend_from_slice(&(entry.command.len() as u64).to_be_bytes());
+    let crc = checksum(&out[start..], &entry.command);
+    out.extend_from_slice(&crc.to_be_bytes());
+    out.extend_from_slice(&entry.command);
+}
+
+// The term, index and command length in a record header.
+fn fields(header: &[u8]) -> (Term, LogIndex, u64) {
+    let field = |i: usize| u64::from_be_bytes(header[i * 8..i * 8 + 8].try_into().unwrap());
+    (Term::new(field(0)), LogIndex::new(field(1)), field(2))
+}
+
+// The term, index and command of the record `bytes` start with, unless it
+// is cut short or fails its checksum.
+fn decode(bytes: &[u8]) -> Option<(Term, LogIndex, &[u8])> {
+    let header = bytes.get(..RECORD_HEADER)?;
+    let (term, index, len) = fields(header);
+    let len = usize::try_from(len).ok()?;
+    let command = bytes.get(RECORD_HEADER..RECORD_HEADER.checked_add(len)?)?;
+    let crc = u32::from_be_bytes(header[RECORD_FIELDS..].try_into().unwrap());
+    (checksum(&header[..RECORD_FIELDS], command) == crc).then_some((term, index, command))
+}
+
+fn corrupt(path: &Path) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("corrupt log segment {}", path.display()),
+    )
+}
+
+// A node cannot go on once its disk fails under it.
+fn check<T>(result: io::Result<T>, what: &str) -> T {
+    result.unwrap_or_else(|e| panic!("log storage failed to {}: {}", what, e))
+}
+
+#[derive(Debug)]
+struct Disk {
+    dir: PathBuf,
+    max_segment_bytes: u64,
+    segments: Vec<Segment>,
+    hard_state: HardState,
+    offset: LogOffset,
+    // Files were created, renamed or removed since the last sync.
+    dir_changed: bool,
+    hard_state_changed: bool,
+}
+
+impl Disk {
+    fn truncate(&mut self, from: LogIndex) -> io::Result<()> {
+        while let Some(segment) = self.segments.last()
+            && segment.first >= from
+        {
+            fs::remove_file(&segment.path)?;
+            self.segments.pop();
+            self.dir_changed = true;
+        }
+
+        if let Some(segment) = self.segments.last_mut()
+            && from < segment.next_index()
+        {
+            let keep = (from.get() - segment.first.get()) as usize;
+            segment.offsets.truncate(keep + 1);
+            segment.terms.truncate(keep);
+            segment.file.set_len(segment.bytes())?;
+            segment.unsynced = true;
+        }
+        Ok(())
+    }
+
+    // Segments wholly at or before the offset are only removed once the
+    // offset is synced, so a crash never leaves a gap before the log.
+    fn compact(&mut self, offset: LogOffset) {
+        self.offset = offset;
+        self.hard_state_changed = true;
+    }
+
+    fn remove_compacted(&mut self) -> io::Result<()> {
+        let first = LogIndex::new(self.offset.index.get() + 1);
+        while let Some(segment) = self.segments.first()
+            && segment.next_index() <= first
+            && segment.first < first
+        {
+            fs::remove_file(&segment.path)?;
+            self.segments.remove(0);
+            self.dir_changed = true;
+        }
+        Ok(())
+    }
+
+    // Fills the last segment up to the size limit, then starts new ones.
+    fn append(&mut self, mut entries: &[LogEntry]) -> io::Result<()> {
+        let max = self.max_segment_bytes;
+        while let Some(first) = entries.first() {
+            if self
+                .segments
+                .last()
+                .is_none_or(|segment| segment.bytes() >= max)
+            {
+                self.segments.push(Segment::create(&self.dir, first.index)?);
+                self.dir_changed = true;
+            }
+
+            let segment = self.segments.last_mut().unwrap();
+            debug_assert_eq!(first.index, segment.next_index());
+            let start = segment.bytes();
+            let mut buf = Vec::new();
+            let mut written = 0;
+            for entry in entries {
+                if written > 0 && start + buf.len() as u64 >= max {
+                    break;
+                }
+                encode(&mut buf, entry);
+                segment.offsets.push(start + buf.len() as u64);
+                segment.terms.push(entry.term);
+                written += 1;
+            }
+            segment.file.write_all_at(&buf, start)?;
+            segment.unsynced = true;
+            entries = &entries[written..];
+        }
+        Ok(())
+    }
+
+    fn sync(&mut self) -> io::Result<()> {
+        for segment in &mut self.segments {
+            if segment.unsynced {
+                segment.file.sync_data()?;
+                segment.unsynced = false;
+            }
+        }
+
+        // Written aside and renamed into place, so a crash leaves either the
+        // old hard state or the new one.
+        if self.hard_state_changed {
+            let tmp = self.dir.join(HARD_STATE_TMP);
+            let state = &self.hard_state;
+            let mut bytes = state.current_term.get().to_be_bytes().to_vec();
+            bytes.extend_from_slice(&state.voted_for.map_or(0, |id| id.get()).to_be_bytes());
+            bytes.extend_from_slice(&self.offset.index.get().to_be_bytes());
+            bytes.extend_from_slice(&self.offset.term.get().to_be_bytes());
+            let file = File::create(&tmp)?;
+            file.write_all_at(&bytes, 0)?;
+            file.sync_data()?;
+            fs::rename(&tmp, self.dir.join(HARD_STATE))?;
+            self.hard_state_changed = false;
+            self.dir_changed = true;
+            self.remove_compacted()?;
+        }
+
+        if self.dir_changed {
+            File::open(&self.dir)?.sync_all()?;
+            self.dir_changed = false;
+        }
+        Ok(())
+    }
+
+    fn last_index(&self) -> LogIndex {
+        self.segments.last().map_or(self.offset.index, |segment| {
+            LogIndex::new(segment.next_index().get() - 1)
+        })
+    }
+
+    // Stops ahead of the first entry whose command takes the total past
+    // `max_bytes`, unless it is the first entry; the rest is not read.
+    fn read(&self, from: LogIndex, to: LogIndex, max_bytes: u64) -> io::Result<Vec<LogEntry>> {
+        let from = from.max(LogIndex::new(self.offset.index.get() + 1));
+        let mut entries = Vec::new();
+        let mut bytes = 0u64;
+        for segment in &self.segments {
+            if segment.next_index() <= from || segment.first > to {
+                continue;
+            }
+            let start = from.get().saturating_sub(segment.first.get()) as usize;
+            let last = ((to.get() + 1 - segment.first.get()) as usize).min(segment.len());
+            let mut end = start;
+            while end < last {
+                bytes = bytes.saturating_add(segment.command_len(end));
+                if bytes > max_bytes && (end > start || !entries.is_empty()) {
+                    break;
+                }
+                end += 1;
+            }
+            entries.extend(segment.read(start, end)?);
+            if end < last {
+                break;
+            }
+        }
+        Ok(entries)
+    }
+
+    // Every entry's term and index, with the commands of the newest entries
+    // that fit in `budget` bytes and the older ones left empty. Only the
+    // commands kept are read.
+    fn load_tail(&self, budget: u64) -> io::Result<(Vec<LogEntry>, LogIndex)> {
+        let mut cached_from = LogIndex::new(self.last_index().get() + 1);
+        let mut bytes = 0u64;
+        'segments: for segment in self.segments.iter().rev() {
+            for position in (0..segment.len()).rev() {
+                if segment.first.get() + position as u64 <= self.offset.index.get() {
+                    break 'segments;
+                }
+                bytes += segment.command_len(position);
+                if bytes > budget {
+                    break 'segments;
+                }
+                cached_from = LogIndex::new(segment.first.get() + position as u64);
+            }
+        }
+
+        let mut entries = Vec::new();
+        for segment in &self.segments {
+            let evicted = cached_from.get().saturating_sub(segment.first.get()) as usize;
+            for (position, &term) in segment.terms.iter().take(evicted).enumerate() {
+                let index = LogIndex::new(segment.first.get() + position as u64);
+                if index > self.offset.index {
+                    entries.push(LogEntry {
+                        term,
+                        index,
+                        command: Vec::new(),
+                    });
+                }
+            }
+        }
+        entries.extend(self.read(cached_from, self.last_index(), u64::MAX)?);
+        Ok((entries, cached_from))
+    }
+}
+
+// Storage on local disk: the hard state in one small file, the log in
+// segment files of consecutive entries, a new one started whenever the last
+// reaches its size limit. Clones share the same files.
+#[derive(Debug, Clone)]
+pub struct SegmentStorage {
+    disk: Arc<Mutex<Disk>>,
+}
+
+impl SegmentStorage {
+    // Opens the storage in `dir`, creating it if needed.
+    pub fn open(dir: &Path) -> io::Result<Self> {
+        Self::with_max_segment_bytes(dir, DEFAULT_MAX_SEGMENT_BYTES)
+    }
+
+    pub fn with_max_segment_bytes(dir: &Path, max_segment_bytes: u64) -> io::Result<Self> {
+        fs::create_dir_all(dir)?;
+        let _ = fs::remove_file(dir.join(HARD_STATE_TMP));
+
+        let (hard_state, offset) = match fs::read(dir.join(HARD_STATE)) {
+            Ok(bytes) if bytes.len() == 32 => {
+                let field = |i: usize| u64::from_be_bytes(bytes[i..i + 8].try_into().unwrap());
+                let hard_state = HardState {
+                    current_term: Term::new(field(0)),
+                    voted_for: Some(field(8)).filter(|&id| id != 0).map(NodeId::new),
+                };
+                let offset = LogOffset {
+                    index: LogIndex::new(field(16)),
+                    term: Term::new(field(24)),
+                };
+                (hard_state, offset)
+            }
+            Ok(_) => return Err(corrupt(&dir.join(HARD_STATE))),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Default::default(),
+            Err(e) => return Err(e),
+        };
+
+        let mut paths = Vec::new();
+        for file in fs::read_dir(dir)? {
+            let path = file?.path();
+            if path.extension().is_some_and(|ext| ext == SEGMENT_EXTENSION) {
+                let first = path
+                    .file_stem()
+                    .and_then(|stem| stem.to_str()?.parse().ok())
+                    .ok_or_else(|| corrupt(&path))?;
+                paths.push((LogIndex::new(first), path));
+            }
+        }
+        paths.sort();
+
+        let mut segments: Vec<Segment> = Vec::new();
+        let count = paths.len();
+        for (i, (first, path)) in paths.into_iter().enumerate() {
+            if segments
+                .last()
+                .is_some_and(|last| last.next_index() != first)
+            {
+                return Err(corrupt(&path));
+            }
+            segments.push(Segment::open(path, first, i + 1 == count)?);
+        }
+
+        // A crash after the offset was synced may have left segments it
+        // covers behind.
+        let mut disk = Disk {
+            dir: dir.to_path_buf(),
+            max_segment_bytes,
+            segments,
+            hard_state,
+            offset,
+            dir_changed: false,
+            hard_state_changed: false,
+        };
+        disk.remove_compacted()?;
+        if disk
+            .segments
+            .first()
+            .is_some_and(|segment| segment.first.get() > offset.index.get() + 1)
+        {
+            return Err(corrupt(&disk.segments[0].path));
+        }
+        Ok(Self {
+            disk: Arc::new(Mutex::new(disk)),
+        })
+    }
+
+    pub fn segment_count(&self) -> usize {
+        self.disk.lock().unwrap().segments.len()
+    }
+}
+
+impl Storage for SegmentStorage {
+    fn save_hard_state(&mut self, state: HardState) {
+        let mut disk = self.disk.lock().unwrap();
+        disk.hard_state = state;
+        disk.hard_state_changed = true;
+    }
+
+    fn truncate(&mut self, from: LogIndex) {
+        check(self.disk.lock().unwrap().truncate(from), "truncate the log");
+    }
+
+    fn append(&mut self, entries: &[LogEntry]) {
+        check(
+            self.disk.lock().unwrap().append(entries),
+            "append to the log",
+        );
+    }
+
+    fn compact(&mut self, offset: LogOffset) {
+        self.disk.lock().unwrap().compact(offset);
+    }
+
+    fn sync(&mut self) {
+        check(self.disk.lock().unwrap().sync(), "sync");
+    }
+
+    // Includes writes not yet synced, as the page cache would.
+    fn load(&self) -> (HardState, LogOffset, Vec<LogEntry>) {
+        let disk = self.disk.lock().unwrap();
+        let entries = check(
+            disk.read(LogIndex::ZERO, disk.last_index(), u64::MAX),
+            "read the log",
+        );
+        (disk.hard_state, disk.offset, entries)
+    }
+
+    fn load_tail(&self, budget: u64) -> (HardState, LogOffset, Vec<LogEntry>, LogIndex) {
+        let disk = self.disk.lock().unwrap();
+        let (entries, cached_from) = check(disk.load_tail(budget), "read the log");
+        (disk.hard_state, disk.offset, entries, cached_from)
+    }
+
+    fn log_reader(&self) -> Option<Arc<dyn LogReader>> {
+        Some(Arc::new(self.clone()))
+    }
+}
+
+impl LogReader for SegmentStorage {
+    fn read(&self, from: LogIndex, to: LogIndex, max_bytes: u64) -> Vec<LogEntry> {
+        check(
+            self.disk.lock().unwrap().read(from, to, max_bytes),
+            "read the log",
+        )
+    }
+}
@@ -1,25 +1,146 @@
+use std::collections::{HashMap, VecDeque};
 use std::sync::{Arc, Mutex};
 
+use tokio::sync::{mpsc, oneshot};
+use tonic::transport::server::{TcpConnectInfo, TlsConnectInfo, UdsConnectInfo};
+use tonic::transport::CertificateDer;
 use tonic::{Request, Response, Status};
 
-use crate::node::RaftNode;
+use crate::event::RaftEvent;
+use crate::grpc::PeerFeatures;
+use crate::handshake::{Features, Handshake};
 use crate::raft_proto::{
     raft_server::Raft,
     AppendEntriesRequest, AppendEntriesResponse,
+    ReadIndexRequest, ReadIndexResponse,
     RequestVoteRequest, RequestVoteResponse,
 };
-use crate::types::{LogIndex, NodeId, Term};
+use crate::rpc;
+use crate::tls;
+use crate::types::NodeId;
 
+// Inbound calls waiting for the event loop's reply. The loop answers vote
+// and append requests from one peer in the order it received them; reads
+// are matched by request id.
+#[derive(Default)]
+pub(crate) struct PendingReplies {
+    votes: HashMap<NodeId, VecDeque<oneshot::Sender<rpc::RequestVoteResponse>>>,
+    appends: HashMap<NodeId, VecDeque<oneshot::Sender<rpc::AppendEntriesResponse>>>,
+    reads: HashMap<(NodeId, u64), oneshot::Sender<rpc::ReadIndexResponse>>,
+}
+
+impl PendingReplies {
+    pub(crate) fn reply_vote(&mut self, to: NodeId, response: rpc::RequestVoteResponse) {
+        if let Some(tx) = self.votes.get_mut(&to).and_then(VecDeque::pop_front) {
+            let _ = tx.send(response);
+        }
+    }
+
+    pub(crate) fn reply_append(&mut self, to: NodeId, response: rpc::AppendEntriesResponse) {
+        if let Some(tx) = self.appends.get_mut(&to).and_then(VecDeque::pop_front) {
+            let _ = tx.send(response);
+        }
+    }
+
+    pub(crate) fn reply_read(&mut self, to: NodeId, response: rpc::ReadIndexResponse) {
+        if let Some(tx) = self.reads.remove(&(to, response.request_id)) {
+            let _ = tx.send(response);
+        }
+    }
+}
+
+// The gRPC service of a node run by `NodeHost` over `GrpcTransport`: each
+// call becomes an event for the event loop, and the call completes when the
+// loop sends its reply.
 pub struct RaftServer {
-    node: Arc<Mutex<RaftNode>>,
+    events: mpsc::UnboundedSender<RaftEvent>,
+    replies: Arc<Mutex<PendingReplies>>,
+    verify_peers: bool,
+    handshake: Handshake,
+    features: PeerFeatures,
 }
 
 impl RaftServer {
-    pub fn new(node: RaftNode) -> Self {
+    pub(crate) fn new(
+        events: mpsc::UnboundedSender<RaftEvent>,
+        replies: Arc<Mutex<PendingReplies>>,
+        verify_peers: bool,
+        handshake: Handshake,
+        features: PeerFeatures,
+    ) -> Self {
         Self {
-            node: Arc::new(Mutex::new(node)),
+            events,
+            replies,
+            verify_peers,
+            handshake,
+            features,
         }
     }
+
+    // Authenticates the sender, then checks its handshake and records the
+    // version and features the two nodes share.
+    fn admit<T>(&self, request: &Request<T>, sender: NodeId) -> Result<(), Status> {
+        self.check_sender(request, sender)?;
+        let theirs = Handshake::read(request.metadata())?;
+        let negotiated = self.handshake.accept(&theirs)?;
+        self.features.insert(sender, negotiated);
+        Ok(())
+    }
+
+    fn respond<T>(&self, message: T) -> Response<T> {
+        let mut response = Response::new(message);
+        self.handshake.write(response.metadata_mut());
+        response
+    }
+
+    // Under mutual TLS a node may only speak for itself: the certificate
+    // it connected with must name the node the message claims to be from.
+    fn check_sender<T>(&self, request: &Request<T>, sender: NodeId) -> Result<(), Status> {
+        if !self.verify_peers {
+            return Ok(());
+        }
+        let certs = peer_certs(request)
+            .ok_or_else(|| Status::unauthenticated("no client certificate"))?;
+        match certs.first() {
+            Some(cert) if tls::names_node(cert, sender) => Ok(()),
+            _ => Err(Status::permission_denied(format!(
+                "client certificate does not name node {}",
+                sender.get()
+            ))),
+        }
+    }
+
+    // Registers the reply slot and queues the event under one lock, so
+    // replies are matched in the order the loop sees the events.
+    fn submit<T>(
+        &self,
+        event: RaftEvent,
+        register: impl FnOnce(&mut PendingReplies, oneshot::Sender<T>),
+    ) -> Result<oneshot::Receiver<T>, Status> {
+        let (tx, rx) = oneshot::channel();
+        let mut replies = self.replies.lock().unwrap();
+        register(&mut replies, tx);
+        self.events
+            .send(event)
+            .map_err(|_| Status::unavailable("node is shutting down"))?;
+        Ok(rx)
+    }
+}
+
+fn peer_certs<T>(request: &Request<T>) -> Option<Arc<Vec<CertificateDer<'static>>>> {
+    let extensions = request.extensions();
+    extensions
+        .get::<TlsConnectInfo<TcpConnectInfo>>()
+        .and_then(|info| info.peer_certs())
+        .or_else(|| {
+            extensions
+                .get::<TlsConnectInfo<UdsConnectInfo>>()
+                .and_then(|info| info.peer_certs())
+        })
+}
+
+fn dropped(_: oneshot::error::RecvError) -> Status {
+    Status::aborted("node dropped the request")
 }
 
 #[tonic::async_trait]
@@ -28,62 +149,50 @@ impl Raft for RaftServer {
         &self,
         request: Request<RequestVoteRequest>,
     ) -> Result<Response<RequestVoteResponse>, Status> {
-        let req = request.into_inner();
-
-        let internal_req = crate::rpc::RequestVoteRequest {
-            term: Term::new(req.term),
-            candidate_id: NodeId::new(req.candidate_id),
-            last_log_index: LogIndex::new(req.last_log_index),
-            last_log_term: Term::new(req.last_log_term)
-        };
-
-        let internal_resp = {
-            let mut node = self.node.lock().unwrap();
-            node.handle_request_vote(internal_req)
-        };
-
-        let resp = RequestVoteResponse {
-            term: internal_resp.term.get(),
-            vote_granted: internal_resp.vote_granted,
-        };
-
-        Ok(Response::new(resp))
+        let candidate = NodeId::new(request.get_ref().candidate_id);
+        self.admit(&request, candidate)?;
+        let req = rpc::RequestVoteRequest::try_from(request.into_inner())?;
+
+        let rx = self.submit(RaftEvent::ReceivedRequestVote(req), |replies, tx| {
+            replies.votes.entry(candidate).or_default().push_back(tx)
+        })?;
+        let resp = rx.await.map_err(dropped)?;
+
+        Ok(self.respond(resp.into()))
     }
 
     async fn append_entries(
         &self,
         request: Request<AppendEntriesRequest>,
     ) -> Result<Response<AppendEntriesResponse>, Status> {
-        let req = request.into_inner();
-
-        let entries: Vec<crate::log::LogEntry> = req.entries
-            .into_iter()
-            .map(|e| crate::log::LogEntry {
-                term: Term::new(e.term),
-                index: LogIndex::new(e.index),
-                command: e.command,
-            })
-            .collect();
-
-        let internal_req = crate::rpc::AppendEntriesRequest {
-            term: Term::new(req.term),
-            leader_id: NodeId::new(req.leader_id),
-            prev_log_index: LogIndex::new(req.prev_log_index),
-            prev_log_term: Term::new(req.prev_log_term),
-            entries,
-            leader_commit: LogIndex::new(req.leader_commit),
-        };
-
-        let internal_resp = {
-            let mut node = self.node.lock().unwrap();
-            node.handle_append_entries(internal_req)
-        };
-
-        let resp = AppendEntriesResponse {
-            term: internal_resp.term.get(),
-            success: internal_resp.success,
-        };
-
-        Ok(Response::new(resp))
+        let leader = NodeId::new(request.get_ref().leader_id);
+        self.admit(&request, leader)?;
+        let req = rpc::AppendEntriesRequest::try_from(request.into_inner())?;
+
+        let rx = self.submit(RaftEvent::ReceivedAppendEntries(req), |replies, tx| {
+            replies.appends.entry(leader).or_default().push_back(tx)
+        })?;
+        let resp = rx.await.map_err(dropped)?;
+
+        Ok(self.respond(resp.into()))
+    }
+
+    async fn read_index(
+        &self,
+        request: Request<ReadIndexRequest>,
+    ) -> Result<Response<ReadIndexResponse>, Status> {
+        if !self.handshake.features.contains(Features::READ_INDEX) {
+            return Err(Status::unimplemented("read index is not enabled on this node"));
+        }
+        self.admit(&request, NodeId::new(request.get_ref().follower_id))?;
+        let req = rpc::ReadIndexRequest::try_from(request.into_inner())?;
+        let key = (req.follower_id, req.request_id);
+
+        let rx = self.submit(RaftEvent::ReceivedReadIndex(req), |replies, tx| {
+            replies.reads.insert(key, tx);
+        })?;
+        let resp = rx.await.map_err(dropped)?;
+
+        Ok(self.respond(resp.into()))
     }
 }
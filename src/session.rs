@@ -0,0 +1,251 @@
+use std::collections::HashMap;
+
+use crate::state_machine::{InvalidSnapshot, SnapshotReader, put_bytes, put_u64};
+use crate::types::{LogIndex, NodeId};
+
+pub type ClientId = u64;
+
+// Every command in the log starts with its kind, so whatever bytes a client
+// sends can never be mistaken for a session header.
+const PLAIN: u8 = 0;
+const SESSION: u8 = 1;
+const MEMBERSHIP: u8 = 2;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Command {
+    // Applied as is, without exactly-once tracking.
+    Plain(Vec<u8>),
+    Session(SessionCommand),
+    // The cluster's voting members from this entry on.
+    Membership(Vec<NodeId>),
+}
+
+impl Command {
+    pub fn encode(&self) -> Vec<u8> {
+        match self {
+            Command::Plain(command) => {
+                let mut out = Vec::with_capacity(1 + command.len());
+                out.push(PLAIN);
+                out.extend_from_slice(command);
+                out
+            }
+            Command::Session(command) => command.encode(),
+            Command::Membership(members) => {
+                let mut out = Vec::with_capacity(1 + 8 * members.len());
+                out.push(MEMBERSHIP);
+                for member in members {
+                    put_u64(&mut out, member.get());
+                }
+                out
+            }
+        }
+    }
+
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        match *bytes.first()? {
+            PLAIN => Some(Command::Plain(bytes[1..].to_vec())),
+            SESSION => SessionCommand::decode(bytes).map(Command::Session),
+            MEMBERSHIP => Self::membership(bytes).map(Command::Membership),
+            _ => None,
+        }
+    }
+
+    // The members listed by a membership command, without copying any other
+    // kind of command.
+    pub fn membership(bytes: &[u8]) -> Option<Vec<NodeId>> {
+        let (&kind, rest) = bytes.split_first()?;
+        if kind != MEMBERSHIP || !rest.len().is_multiple_of(8) {
+            return None;
+        }
+        let members = rest
+            .chunks_exact(8)
+            .map(|id| NodeId::new(u64::from_be_bytes(id.try_into().unwrap())))
+            .collect();
+        Some(members)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SessionCommand {
+    pub client_id: ClientId,
+    pub sequence: u64,
+    pub command: Vec<u8>,
+}
+
+impl SessionCommand {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut out = Vec::with_capacity(17 + self.command.len());
+        out.push(SESSION);
+        put_u64(&mut out, self.client_id);
+        put_u64(&mut out, self.sequence);
+        out.extend_from_slice(&self.command);
+        out
+    }
+
+    pub fn decode(bytes: &[u8]) -> Option<Self> {
+        let (&kind, rest) = bytes.split_first()?;
+        if kind != SESSION || rest.len() < 16 {
+            return None;
+        }
+
+        let (client_id, rest) = rest.split_at(8);
+        let (sequence, command) = rest.split_at(8);
+
+        Some(Self {
+            client_id: u64::from_be_bytes(client_id.try_into().unwrap()),
+            sequence: u64::from_be_bytes(sequence.try_into().unwrap()),
+            command: command.to_vec(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProposeOutcome {
+    // Waiting in the leader's proposal queue. The command's index is only
+    // known once its batch is appended; its result comes when it is applied.
+    Queued,
+    // The command was already applied; this is the response it produced.
+    Cached(Vec<u8>),
+    Stale,
+}
+
+// The client's session was dropped, so whether its command had already been
+// applied is unknown. It has to start over as a new client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SessionExpired;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CommandResult {
+    pub client_id: ClientId,
+    pub sequence: u64,
+    pub response: Result<Vec<u8>, SessionExpired>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SessionStatus {
+    New,
+    Duplicate(Vec<u8>),
+    // Older than the last command the session has a response cached for.
+    Stale,
+    // Past the first command of a client without a live session.
+    Expired,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Session {
+    last_sequence: u64,
+    last_response: Vec<u8>,
+    last_active: LogIndex,
+}
+
+impl Session {
+    fn is_live(&self, timeout: u64, now: LogIndex) -> bool {
+        self.last_active.get().saturating_add(timeout) >= now.get()
+    }
+}
+
+// Part of the replicated state: every replica applies the same commands in
+// the same order, so every replica ends up with the same table. Time is the
+// log index of the entry being applied, which keeps expiry deterministic:
+// a session past its timeout counts as gone whether or not `expire` has
+// dropped it yet.
+#[derive(Debug, Clone, Default)]
+pub struct SessionTable {
+    sessions: HashMap<ClientId, Session>,
+    timeout: u64,
+}
+
+impl SessionTable {
+    pub fn new(timeout: u64) -> Self {
+        Self {
+            sessions: HashMap::new(),
+            timeout,
+        }
+    }
+
+    pub fn status(&self, client_id: ClientId, sequence: u64, now: LogIndex) -> SessionStatus {
+        let session = self
+            .sessions
+            .get(&client_id)
+            .filter(|session| session.is_live(self.timeout, now));
+        match session {
+            None if sequence <= 1 => SessionStatus::New,
+            None => SessionStatus::Expired,
+            Some(session) if sequence > session.last_sequence => SessionStatus::New,
+            Some(session) if sequence == session.last_sequence => {
+                SessionStatus::Duplicate(session.last_response.clone())
+            }
+            Some(_) => SessionStatus::Stale,
+        }
+    }
+
+    pub fn record(&mut self, client_id: ClientId, sequence: u64, response: Vec<u8>, now: LogIndex) {
+        self.sessions.insert(
+            client_id,
+            Session {
+                last_sequence: sequence,
+                last_response: response,
+                last_active: now,
+            },
+        );
+    }
+
+    pub fn touch(&mut self, client_id: ClientId, now: LogIndex) {
+        if let Some(session) = self.sessions.get_mut(&client_id) {
+            session.last_active = now;
+        }
+    }
+
+    pub fn expire(&mut self, now: LogIndex) {
+        let timeout = self.timeout;
+        self.sessions
+            .retain(|_, session| session.is_live(timeout, now));
+    }
+
+    pub fn len(&self) -> usize {
+        self.sessions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sessions.is_empty()
+    }
+
+    pub fn snapshot(&self) -> Vec<u8> {
+        let mut sessions: Vec<_> = self.sessions.iter().collect();
+        sessions.sort_by_key(|&(&client_id, _)| client_id);
+
+        let mut out = Vec::new();
+        put_u64(&mut out, sessions.len() as u64);
+        for (&client_id, session) in sessions {
+            put_u64(&mut out, client_id);
+            put_u64(&mut out, session.last_sequence);
+            put_u64(&mut out, session.last_active.get());
+            put_bytes(&mut out, &session.last_response);
+        }
+        out
+    }
+
+    pub fn restore(&mut self, snapshot: &[u8]) -> Result<(), InvalidSnapshot> {
+        let mut reader = SnapshotReader::new(snapshot);
+        let mut sessions = HashMap::new();
+
+        for _ in 0..reader.u64()? {
+            let client_id = reader.u64()?;
+            let last_sequence = reader.u64()?;
+            let last_active = LogIndex::new(reader.u64()?);
+            let last_response = reader.bytes()?.to_vec();
+            sessions.insert(
+                client_id,
+                Session {
+                    last_sequence,
+                    last_response,
+                    last_active,
+                },
+            );
+        }
+        reader.finish()?;
+
+        self.sessions = sessions;
+        Ok(())
+    }
+}
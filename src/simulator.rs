@@ -1,155 +1,424 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
 
 use crate::{
-    event::RaftEvent,
+    clock::{ManualClock, SharedClock, SkewedClock},
+    config::RaftConfig,
+    invariants::{InvariantChecker, Violation},
+    network::Network,
     node::RaftNode,
-    raft::{RaftAction, RaftRunner},
-    types::{NodeId, RaftState},
+    raft::RaftRunner,
+    state_machine::KvStore,
+    storage::MemStorage,
+    trace::{ClusterLine, TraceEvent},
+    types::NodeId,
 };
 
+// Virtual time advanced on every `tick`.
+pub const TICK_INTERVAL: Duration = Duration::from_millis(1);
+
+// History events kept by default; older ones are dropped as new ones arrive.
+pub const HISTORY_LIMIT: usize = 1_000_000;
+
+#[derive(Debug, Clone)]
+pub enum HistoryEvent {
+    // An entry of a node's own trace, stamped with simulator time.
+    Node { at: Duration, id: NodeId, event: TraceEvent },
+    Crashed { at: Duration, id: NodeId },
+    Restarted { at: Duration, id: NodeId },
+    Added { at: Duration, id: NodeId },
+}
+
+impl fmt::Display for HistoryEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HistoryEvent::Node { at, id, event } => {
+                write!(f, "{:?} node {:?}: {}", at, id, serde_json::to_string(event).unwrap())
+            }
+            HistoryEvent::Crashed { at, id } => write!(f, "{:?} node {:?} crashed", at, id),
+            HistoryEvent::Restarted { at, id } => write!(f, "{:?} node {:?} restarted", at, id),
+            HistoryEvent::Added { at, id } => write!(f, "{:?} node {:?} added", at, id),
+        }
+    }
+}
+
+// Runs entirely on virtual time with every node's randomness derived from a
+// single seed, so the same seed always replays the same history.
 pub struct Simulator {
-    runners: HashMap<NodeId, RaftRunner>,
-    vote_counts: HashMap<(NodeId, u64), usize>,
+    // Every node ever started; only those in `initial_members` start out
+    // as members, the rest join through membership changes.
+    members: Vec<NodeId>,
+    initial_members: Vec<NodeId>,
+    config: RaftConfig,
+    rng: StdRng,
+    // Only running nodes have a runner; storage outlives crashes.
+    runners: BTreeMap<NodeId, RaftRunner>,
+    storages: BTreeMap<NodeId, MemStorage>,
+    network: Network,
+    seed: u64,
+    time: Arc<ManualClock>,
+    clocks: BTreeMap<NodeId, Arc<SkewedClock>>,
+    checker: InvariantChecker,
+    // When set, a violation is recorded instead of panicking.
+    record_violations: bool,
+    violation: Option<Violation>,
+    history: VecDeque<HistoryEvent>,
+    history_limit: usize,
+    // Events dropped off the front of `history`.
+    history_dropped: usize,
 }
 
 impl Simulator {
     pub fn new(node_ids: Vec<NodeId>) -> Self {
-        let mut runners = HashMap::new();
+        Self::with_seed(node_ids, rand::random())
+    }
+
+    pub fn with_seed(node_ids: Vec<NodeId>, seed: u64) -> Self {
+        Self::with_config(node_ids, RaftConfig::default(), seed)
+    }
+
+    pub fn with_config(node_ids: Vec<NodeId>, config: RaftConfig, seed: u64) -> Self {
+        let mut rng = StdRng::seed_from_u64(seed);
+        let time = Arc::new(ManualClock::new());
+        let mut clocks = BTreeMap::new();
+        let mut node_rngs = BTreeMap::new();
 
         for &id in &node_ids {
-            let peers: Vec<NodeId> = node_ids
+            let inner: SharedClock = time.clone();
+            clocks.insert(id, Arc::new(SkewedClock::new(inner, 1.0)));
+            node_rngs.insert(id, StdRng::seed_from_u64(rng.random()));
+        }
+
+        let network = Network::new(StdRng::seed_from_u64(rng.random()));
+
+        let mut simulator = Self {
+            members: node_ids.clone(),
+            initial_members: node_ids,
+            config,
+            rng,
+            runners: BTreeMap::new(),
+            storages: BTreeMap::new(),
+            network,
+            seed,
+            time,
+            clocks,
+            checker: InvariantChecker::new(),
+            record_violations: false,
+            violation: None,
+            history: VecDeque::new(),
+            history_limit: HISTORY_LIMIT,
+            history_dropped: 0,
+        };
+        for (id, node_rng) in node_rngs {
+            simulator.start(id, node_rng);
+        }
+        simulator
+    }
+
+    // Builds `id`'s node from whatever its storage holds.
+    fn start(&mut self, id: NodeId, rng: StdRng) {
+        let clock = self.clocks[&id].clone();
+        let node = if self.initial_members.contains(&id) {
+            let peers: Vec<NodeId> = self
+                .initial_members
                 .iter()
                 .copied()
                 .filter(|&peer_id| peer_id != id)
                 .collect();
+            RaftNode::with_clock_and_rng(id, peers, self.config.clone(), clock, rng)
+        } else {
+            RaftNode::joining(id, self.config.clone(), clock, rng)
+        };
+        let storage = self.storages.entry(id).or_default().clone();
+        let mut runner = RaftRunner::with_storage(node, Box::new(KvStore::new()), Box::new(storage));
+        runner.enable_trace();
+
+        self.runners.insert(id, runner);
+    }
 
-            let node = RaftNode::new(id, peers);
-            let runner = RaftRunner::new(node);
-            runners.insert(id, runner);
+    // Stops `id` as a process crash would: everything but its storage is
+    // lost, including messages already on their way to it.
+    pub fn crash(&mut self, id: NodeId) {
+        if let Some(mut runner) = self.runners.remove(&id) {
+            self.record_trace(id, &mut runner);
+            self.network.discard_to(id);
+            self.checker.forget_volatile(id);
+            self.record(HistoryEvent::Crashed {
+                at: self.elapsed(),
+                id,
+            });
         }
+    }
 
-        Self {
-            runners,
-            vote_counts: HashMap::new(),
+    // Like `crash`, but as a power failure: writes that were not synced are
+    // lost too.
+    pub fn crash_losing_unsynced(&mut self, id: NodeId) {
+        self.crash(id);
+        if let Some(storage) = self.storages.get(&id) {
+            storage.lose_unsynced();
         }
     }
 
-    pub fn tick(&mut self) {
-        let mut all_actions: Vec<(NodeId, RaftAction)> = Vec::new();
-        let mut new_leaders: Vec<NodeId> = Vec::new();
+    pub fn restart(&mut self, id: NodeId) {
+        if self.is_crashed(id) {
+            let rng = StdRng::seed_from_u64(self.rng.random());
+            self.start(id, rng);
+            self.record(HistoryEvent::Restarted {
+                at: self.elapsed(),
+                id,
+            });
+        }
+    }
 
-        for (&id, runner) in &mut self.runners {
-            let before_state = runner.node().state;
-            let before_term = runner.node().current_term;
+    // Starts a new, empty node that can then be added to the cluster with
+    // `RaftRunner::change_membership`.
+    pub fn add_node(&mut self, id: NodeId) {
+        if self.members.contains(&id) {
+            return;
+        }
 
-            let actions = runner.tick();
+        let inner: SharedClock = self.time.clone();
+        self.clocks.insert(id, Arc::new(SkewedClock::new(inner, 1.0)));
+        self.members.push(id);
+        let rng = StdRng::seed_from_u64(self.rng.random());
+        self.start(id, rng);
+        self.record(HistoryEvent::Added {
+            at: self.elapsed(),
+            id,
+        });
+    }
 
-            let after_state = runner.node().state;
-            let after_term = runner.node().current_term;
+    pub fn is_crashed(&self, id: NodeId) -> bool {
+        self.members.contains(&id) && !self.runners.contains_key(&id)
+    }
 
-            if before_state != after_state {
-                println!("  [STATE] Node {:?}: {:?} -> {:?}", id, before_state, after_state);
-                if after_state != RaftState::Candidate {
-                    self.vote_counts.retain(|(node_id, _), _| *node_id != id);
-                }
-            }
+    pub fn storage(&self, id: NodeId) -> Option<&MemStorage> {
+        self.storages.get(&id)
+    }
 
-            if after_state == RaftState::Candidate && after_term != before_term {
-                let term = after_term.get();
-                self.vote_counts.insert((id, term), 1);
-                if runner.node().quorum() == 1 {
-                    runner.node_mut().become_leader();
-                    println!("[LEADER] Node {:?} became Leader! (votes: 1)", id);
-                    new_leaders.push(id);
-                    self.vote_counts.remove(&(id, term));
-                }
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+
+    pub fn elapsed(&self) -> Duration {
+        self.time.elapsed()
+    }
+
+    // Makes `id`'s clock run at `rate` times the speed of virtual time.
+    pub fn set_clock_rate(&mut self, id: NodeId, rate: f64) {
+        if let Some(clock) = self.clocks.get(&id) {
+            clock.set_rate(rate);
+        }
+    }
+
+    pub fn clock(&self, id: NodeId) -> Option<SharedClock> {
+        self.clocks
+            .get(&id)
+            .map(|clock| clock.clone() as SharedClock)
+    }
+
+    pub fn advance(&mut self, duration: Duration) {
+        let until = self.time.elapsed() + duration;
+        while self.time.elapsed() < until {
+            self.tick();
+        }
+    }
+
+    pub fn node_ids(&self) -> Vec<NodeId> {
+        self.members.clone()
+    }
+
+    pub fn network(&self) -> &Network {
+        &self.network
+    }
+
+    pub fn network_mut(&mut self) -> &mut Network {
+        &mut self.network
+    }
+
+    pub fn partition(&mut self, groups: &[Vec<NodeId>]) {
+        self.network.partition(groups);
+    }
+
+    // Cuts every link to and from `id` until the network is healed.
+    pub fn isolate(&mut self, id: NodeId) {
+        let all = self.node_ids();
+        self.network.isolate(id, &all);
+    }
+
+    pub fn heal(&mut self) {
+        self.network.heal();
+    }
+
+    pub fn tick(&mut self) {
+        self.time.advance(TICK_INTERVAL);
+        let now = self.time.elapsed();
+
+        for (from, action) in self.network.deliver(now) {
+            let to = action.target();
+            if let Some(target_runner) = self.runners.get_mut(&to) {
+                target_runner.push_event(action.into_event(from));
             }
+        }
+
+        let ids: Vec<NodeId> = self.runners.keys().copied().collect();
+        for id in ids {
+            let mut runner = self.runners.remove(&id).unwrap();
+            let actions = runner.tick();
+            self.record_trace(id, &mut runner);
+            self.runners.insert(id, runner);
+
+            self.check_invariants(id);
 
             for action in actions {
-                all_actions.push((id, action));
+                self.network.send(now, id, action);
             }
         }
+    }
 
-        for leader_id in new_leaders {
-            self.send_heartbeats(leader_id);
+    fn record_trace(&mut self, id: NodeId, runner: &mut RaftRunner) {
+        let at = self.time.elapsed();
+        for event in runner.take_trace() {
+            self.record(HistoryEvent::Node { at, id, event });
         }
+    }
 
-        for (from, action) in all_actions {
-            self.handle_action(from, action);
+    fn record(&mut self, event: HistoryEvent) {
+        if self.history.len() == self.history_limit {
+            self.history.pop_front();
+            self.history_dropped += 1;
         }
+        self.history.push_back(event);
     }
 
-    fn handle_action(&mut self, from: NodeId, action: RaftAction) {
-        match action {
-            RaftAction::SendRequestVote(to, request) => {
-                if let Some(target_runner) = self.runners.get_mut(&to) {
-                    let response = target_runner.node_mut().handle_request_vote(request);
+    // Keeps only the last `limit` history events, so long runs do not grow
+    // without bound.
+    pub fn set_history_limit(&mut self, limit: usize) {
+        self.history_limit = limit.max(1);
+        while self.history.len() > self.history_limit {
+            self.history.pop_front();
+            self.history_dropped += 1;
+        }
+    }
 
-                    if let Some(sender_runner) = self.runners.get_mut(&from) {
-                        sender_runner.node_mut().handle_request_response(response.clone());
-                    }
+    // How many of the oldest history events were dropped to stay within the
+    // limit; traces are only complete while this is 0.
+    pub fn history_dropped(&self) -> usize {
+        self.history_dropped
+    }
 
-                    let sender_runner = self.runners.get(&from).unwrap();
-                    let current_term = sender_runner.node().current_term.get();
-                    let is_candidate = sender_runner.node().is_candidate();
-                    let quorum = sender_runner.node().quorum();
+    // Every trace entry `id` recorded, across restarts. Each run begins with
+    // a `Started` entry and can be replayed with `trace::replay`.
+    pub fn node_trace(&self, id: NodeId) -> Vec<TraceEvent> {
+        self.history
+            .iter()
+            .filter_map(|event| match event {
+                HistoryEvent::Node { id: node, event, .. } if *node == id => Some(event.clone()),
+                _ => None,
+            })
+            .collect()
+    }
 
-                    if !is_candidate {
-                        self.vote_counts.retain(|(node_id, _), _| *node_id != from);
-                    }
+    // Every node's trace entries in the order they were recorded.
+    pub fn cluster_trace(&self) -> Vec<(NodeId, TraceEvent)> {
+        self.history
+            .iter()
+            .filter_map(|event| match event {
+                HistoryEvent::Node { id, event, .. } => Some((*id, event.clone())),
+                _ => None,
+            })
+            .collect()
+    }
 
-                    if response.vote_granted && is_candidate && response.term.get() == current_term {
-                        let count = self.vote_counts.entry((from, current_term)).or_insert(1);
-                        *count += 1;
+    // The whole cluster's trace, one JSON object per line, with the node id
+    // and simulator time added to each node's own entry.
+    pub fn trace_json_lines(&self) -> String {
+        let mut out = String::new();
+        for event in &self.history {
+            if let HistoryEvent::Node { at, id, event } = event {
+                let line = ClusterLine {
+                    node: *id,
+                    at: *at,
+                    event: event.clone(),
+                };
+                out.push_str(&serde_json::to_string(&line).unwrap());
+                out.push('\n');
+            }
+        }
+        out
+    }
 
-                        if *count >= quorum {
-                            let sender_runner = self.runners.get_mut(&from).unwrap();
-                            sender_runner.node_mut().become_leader();
-                            println!("[LEADER] Node {:?} became Leader! (votes: {})", from, *count);
+    fn check_invariants(&mut self, id: NodeId) {
+        let runner = self.runners.get_mut(&id).unwrap();
+        let changed_from = runner.node_mut().log.take_changed_from();
 
-                            self.send_heartbeats(from);
+        if self.violation.is_some() {
+            return;
+        }
 
-                            self.vote_counts.remove(&(from, current_term));
-                        }
-                    }
-                }
-            }
-            
-            RaftAction::SendAppendEntries(to, request) => {
-                if let Some(target_runner) = self.runners.get_mut(&to) {
-                    target_runner.push_event(RaftEvent::ReceivedAppendEntries(request));
-                }
+        if let Err(violation) = self.checker.check(runner.node(), changed_from) {
+            if !self.record_violations {
+                self.fail(violation);
             }
+            self.violation = Some(violation);
         }
     }
 
-    fn send_heartbeats(&mut self, leader_id: NodeId) {
-        let peers: Vec<NodeId> = {
-            let leader = self.runners.get(&leader_id).unwrap();
-            leader.node().peers.clone()
-        };
-        
-        for peer_id in peers {
-            let request = {
-                let leader = self.runners.get(&leader_id).unwrap();
-                leader.node().create_append_entries(&peer_id)
-            };
-            
-            if let Some(target_runner) = self.runners.get_mut(&peer_id) {
-                target_runner.push_event(RaftEvent::ReceivedAppendEntries(request));
-                println!("  [HEARTBEAT] Node {:?} -> Node {:?}", leader_id, peer_id);
-            }
+    pub fn record_violations(&mut self) {
+        self.record_violations = true;
+    }
+
+    // The first invariant violation, if `record_violations` was set.
+    pub fn violation(&self) -> Option<&Violation> {
+        self.violation.as_ref()
+    }
+
+    fn fail(&self, violation: Violation) -> ! {
+        eprintln!(
+            "=== History ({} events, {} earlier dropped) ===",
+            self.history.len(),
+            self.history_dropped
+        );
+        for event in &self.history {
+            eprintln!("{}", event);
         }
+        eprintln!("=== Final state ===");
+        for runner in self.runners.values() {
+            let node = runner.node();
+            eprintln!(
+                "Node {:?}: {:?} term {:?} commit {:?} log {:?}",
+                node.id,
+                node.state,
+                node.current_term,
+                node.commit_index,
+                node.log.entries().iter().map(|entry| entry.term.get()).collect::<Vec<_>>()
+            );
+        }
+        panic!("Raft invariant violated: {}", violation);
+    }
+
+    pub fn history(&self) -> impl Iterator<Item = &HistoryEvent> {
+        self.history.iter()
     }
 
     pub fn print_status(&self) {
         println!("\n=== Cluster Status ===");
-        for (&id, runner) in &self.runners {
-            let node = runner.node();
-            println!(
-                "Node {:?}: {:?} (Term: {:?})", 
-                id, node.state, node.current_term
-            );
+        for &id in &self.members {
+            match self.runners.get(&id) {
+                Some(runner) => {
+                    let node = runner.node();
+                    println!(
+                        "Node {:?}: {:?} (Term: {:?})", 
+                        id, node.state, node.current_term
+                    );
+                }
+                None => println!("Node {:?}: Crashed", id),
+            }
         }
         println!("=====================\n");
     }
@@ -163,7 +432,29 @@ impl Simulator {
         None
     }
     
+    pub fn find_leader_except(&self, excluded: NodeId) -> Option<NodeId> {
+        self.runners
+            .iter()
+            .find(|&(&id, runner)| id != excluded && runner.node().is_leader())
+            .map(|(&id, _)| id)
+    }
+
     pub fn node(&self, id: NodeId) -> Option<&RaftNode> {
         self.runners.get(&id).map(|r| r.node())
     }
+
+    pub fn runner_mut(&mut self, id: NodeId) -> Option<&mut RaftRunner> {
+        self.runners.get_mut(&id)
+    }
+}
+
+impl Drop for Simulator {
+    fn drop(&mut self) {
+        if std::thread::panicking() {
+            eprintln!(
+                "Simulator seed: {} (replay with Simulator::with_seed)",
+                self.seed
+            );
+        }
+    }
 }
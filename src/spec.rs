@@ -0,0 +1,1083 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::fmt;
+
+use crate::event::RaftEvent;
+use crate::raft::RaftAction;
+use crate::rpc::{
+    AppendEntriesRequest, AppendEntriesResponse, RequestVoteRequest, RequestVoteResponse,
+};
+use crate::session::Command;
+use crate::trace::TraceEvent;
+use crate::types::{LogIndex, NodeId, RaftState, Term};
+
+// An executable model of the server state in the Raft spec (currentTerm,
+// state, votedFor, log, commitIndex, votesGranted, matchIndex), checked
+// against a cluster trace: every state change, commit and message a node
+// records must be one the spec allows from the model's previous state.
+//
+// Behavior beyond the spec is allowed where it cannot break safety: leaders
+// stepping down on CheckQuorum, vote requests ignored while a leader is
+// known, and messages dropped by their receiver. Reads are not modeled.
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpecViolation {
+    // Index of the offending entry in the cluster trace.
+    pub position: usize,
+    pub node: NodeId,
+    // The spec action being checked and the `RaftNode` method implementing it.
+    pub action: &'static str,
+    pub handler: &'static str,
+    pub message: String,
+}
+
+impl fmt::Display for SpecViolation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "trace event {} on node {}: {} (RaftNode::{}): {}",
+            self.position,
+            self.node.get(),
+            self.action,
+            self.handler,
+            self.message
+        )
+    }
+}
+
+// Reports the earliest violation in the trace.
+pub fn check(trace: &[(NodeId, TraceEvent)]) -> Result<(), SpecViolation> {
+    let mut violations = vec![check_messages(trace)];
+
+    let mut positions: BTreeMap<NodeId, Vec<usize>> = BTreeMap::new();
+    for (position, (id, _)) in trace.iter().enumerate() {
+        positions.entry(*id).or_default().push(position);
+    }
+    for (id, positions) in positions {
+        let events: Vec<(usize, &TraceEvent)> = positions
+            .into_iter()
+            .map(|position| (position, &trace[position].1))
+            .collect();
+        violations.push(NodeChecker::new(id, &events).run());
+    }
+
+    match violations
+        .into_iter()
+        .filter_map(Result::err)
+        .min_by_key(|violation| violation.position)
+    {
+        Some(violation) => Err(violation),
+        None => Ok(()),
+    }
+}
+
+// Every received message must have been sent earlier. The network may
+// duplicate messages, so a message can be received more than once.
+fn check_messages(trace: &[(NodeId, TraceEvent)]) -> Result<(), SpecViolation> {
+    let mut sent: HashMap<NodeId, Vec<RaftEvent>> = HashMap::new();
+
+    for (position, (id, event)) in trace.iter().enumerate() {
+        match event {
+            TraceEvent::Sent { action, .. } => {
+                sent.entry(action.target())
+                    .or_default()
+                    .push(action.clone().into_event(*id));
+            }
+            TraceEvent::Received { message, .. }
+                if !sent
+                    .get(id)
+                    .is_some_and(|messages| messages.contains(message)) =>
+            {
+                return Err(SpecViolation {
+                    position,
+                    node: *id,
+                    action: "Receive",
+                    handler: "handle_event",
+                    message: "received a message that was never sent".to_string(),
+                });
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct Entry {
+    term: Term,
+    // Unknown for a proposal until the leader sends it to a follower.
+    command: Option<Vec<u8>>,
+}
+
+#[derive(Debug, Clone)]
+struct Server {
+    id: NodeId,
+    initial_members: Vec<NodeId>,
+    check_quorum: bool,
+    term: Term,
+    state: RaftState,
+    voted_for: Option<NodeId>,
+    log: Vec<Entry>,
+    commit: LogIndex,
+    votes: BTreeSet<NodeId>,
+    match_index: BTreeMap<NodeId, LogIndex>,
+}
+
+impl Server {
+    // The newest configuration in the log. Entries whose command is not
+    // known yet are proposals, never membership changes.
+    fn members(&self) -> Vec<NodeId> {
+        self.log
+            .iter()
+            .rev()
+            .find_map(|entry| entry.command.as_deref().and_then(Command::membership))
+            .unwrap_or_else(|| self.initial_members.clone())
+    }
+
+    fn peers(&self) -> Vec<NodeId> {
+        let mut peers = self.members();
+        peers.retain(|&member| member != self.id);
+        peers
+    }
+
+    fn quorum(&self) -> usize {
+        self.members().len() / 2 + 1
+    }
+
+    fn last_index(&self) -> LogIndex {
+        LogIndex::new(self.log.len() as u64)
+    }
+
+    fn term_at(&self, index: LogIndex) -> Option<Term> {
+        match index.get() {
+            0 => Some(Term::ZERO),
+            i => self.log.get(i as usize - 1).map(|entry| entry.term),
+        }
+    }
+
+    fn last_term(&self) -> Term {
+        self.term_at(self.last_index()).unwrap()
+    }
+
+    // UpdateTerm
+    fn update_term(&mut self, term: Term) {
+        self.term = term;
+        self.state = RaftState::Follower;
+        self.voted_for = None;
+    }
+
+    // BecomeLeader, followed by the no-op entry this implementation appends.
+    fn become_leader(&mut self) {
+        self.state = RaftState::Leader;
+        self.match_index = self
+            .peers()
+            .into_iter()
+            .map(|peer| (peer, LogIndex::ZERO))
+            .collect();
+        self.log.push(Entry {
+            term: self.term,
+            command: Some(Vec::new()),
+        });
+    }
+
+    // The highest index AdvanceCommitIndex may commit: agreed on by a quorum
+    // and from the leader's own term.
+    fn committable(&self, index: LogIndex) -> bool {
+        let agreed = 1 + self
+            .peers()
+            .iter()
+            .filter(|peer| {
+                self.match_index
+                    .get(peer)
+                    .is_some_and(|&matched| matched >= index)
+            })
+            .count();
+        self.state == RaftState::Leader
+            && agreed >= self.quorum()
+            && self.term_at(index) == Some(self.term)
+    }
+}
+
+// One allowed outcome of handling an input.
+struct Outcome {
+    server: Server,
+    action: &'static str,
+    handler: &'static str,
+    response: Option<RaftAction>,
+    // For followers, how far the handled AppendEntries lets them commit.
+    commit_limit: LogIndex,
+}
+
+impl Outcome {
+    fn new(server: Server, action: &'static str, handler: &'static str) -> Self {
+        Self {
+            server,
+            action,
+            handler,
+            response: None,
+            commit_limit: LogIndex::ZERO,
+        }
+    }
+}
+
+struct NodeChecker<'a> {
+    id: NodeId,
+    events: &'a [(usize, &'a TraceEvent)],
+    next: usize,
+    server: Option<Server>,
+    // State and term as of the last recorded `StateChanged`.
+    observed: (RaftState, Term),
+    inbox: Vec<(usize, RaftEvent)>,
+}
+
+// What a node may send during one tick.
+#[derive(Default)]
+struct Allowed {
+    request_vote: Option<RequestVoteRequest>,
+    // The leader's term and log while it was leader during the tick.
+    leader: Option<(Term, Vec<Entry>)>,
+    responses: Vec<(usize, &'static str, &'static str, RaftAction)>,
+}
+
+impl<'a> NodeChecker<'a> {
+    fn new(id: NodeId, events: &'a [(usize, &'a TraceEvent)]) -> Self {
+        Self {
+            id,
+            events,
+            next: 0,
+            server: None,
+            observed: (RaftState::Follower, Term::ZERO),
+            inbox: Vec::new(),
+        }
+    }
+
+    fn violation(
+        &self,
+        position: usize,
+        action: &'static str,
+        handler: &'static str,
+        message: String,
+    ) -> SpecViolation {
+        SpecViolation {
+            position,
+            node: self.id,
+            action,
+            handler,
+            message,
+        }
+    }
+
+    fn peek(&self) -> Option<&'a TraceEvent> {
+        self.events.get(self.next).map(|(_, event)| *event)
+    }
+
+    fn position(&self) -> usize {
+        self.events
+            .get(self.next)
+            .or(self.events.last())
+            .map_or(0, |(position, _)| *position)
+    }
+
+    fn server(&self) -> &Server {
+        self.server.as_ref().unwrap()
+    }
+
+    fn server_mut(&mut self) -> &mut Server {
+        self.server.as_mut().unwrap()
+    }
+
+    fn run(mut self) -> Result<(), SpecViolation> {
+        while let Some(&(position, event)) = self.events.get(self.next) {
+            self.next += 1;
+
+            if self.server.is_none() && !matches!(event, TraceEvent::Started { .. }) {
+                return Err(self.violation(
+                    position,
+                    "Init",
+                    "new",
+                    "trace does not begin with `started`".to_string(),
+                ));
+            }
+
+            match event {
+                TraceEvent::Started { .. } => self.restart(position, event)?,
+                TraceEvent::Received { message, .. } => {
+                    self.inbox.push((position, message.clone()))
+                }
+                TraceEvent::Proposed { .. } => self.client_request(position)?,
+                TraceEvent::ReadRequested { .. } => {}
+                TraceEvent::MembershipRequested { members, .. } => {
+                    self.membership_request(position, members)?
+                }
+                TraceEvent::Tick {
+                    election_timeout, ..
+                } => self.tick(position, *election_timeout)?,
+                other => {
+                    return Err(self.violation(
+                        position,
+                        "Next",
+                        "tick",
+                        format!("unexpected {}", serde_json::to_string(other).unwrap()),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // Restart: volatile state is lost, persistent state must be what the
+    // node had. A power failure may lose log entries that were not synced.
+    fn restart(&mut self, position: usize, event: &TraceEvent) -> Result<(), SpecViolation> {
+        let TraceEvent::Started {
+            id,
+            members,
+            config,
+            hard_state,
+            entries,
+            ..
+        } = event
+        else {
+            unreachable!()
+        };
+
+        let log: Vec<Entry> = entries
+            .iter()
+            .map(|entry| Entry {
+                term: entry.term,
+                command: Some(entry.command.clone()),
+            })
+            .collect();
+
+        if let Some(server) = &self.server {
+            let error = |message: String| self.violation(position, "Restart", "restore", message);
+            if hard_state.current_term != server.term || hard_state.voted_for != server.voted_for {
+                return Err(error(format!(
+                    "restarted with term {} and vote {:?}, had term {} and vote {:?}",
+                    hard_state.current_term.get(),
+                    hard_state.voted_for,
+                    server.term.get(),
+                    server.voted_for
+                )));
+            }
+            let prefix = log.len() <= server.log.len()
+                && log.iter().zip(&server.log).all(|(restored, had)| {
+                    restored.term == had.term
+                        && (had.command.is_none() || restored.command == had.command)
+                });
+            if !prefix {
+                return Err(error(
+                    "restored log is not a prefix of the log it had".to_string(),
+                ));
+            }
+        }
+
+        self.server = Some(Server {
+            id: *id,
+            initial_members: members.clone(),
+            check_quorum: config.check_quorum,
+            term: hard_state.current_term,
+            state: RaftState::Follower,
+            voted_for: hard_state.voted_for,
+            log,
+            commit: LogIndex::ZERO,
+            votes: BTreeSet::new(),
+            match_index: BTreeMap::new(),
+        });
+        self.observed = (RaftState::Follower, hard_state.current_term);
+        self.inbox.clear();
+        Ok(())
+    }
+
+    // ClientRequest: the proposal is queued; its entry is appended with the
+    // batch it lands in, at the start of a later tick.
+    fn client_request(&mut self, position: usize) -> Result<(), SpecViolation> {
+        self.appended()?;
+        let server = self.server().clone();
+        self.settle(
+            position,
+            vec![Outcome::new(server, "ClientRequest", "client_request")],
+            false,
+        )?;
+        Ok(())
+    }
+
+    // A single-server change: the leader appends the new configuration and
+    // uses it from then on, as do followers once they have the entry.
+    fn membership_request(
+        &mut self,
+        position: usize,
+        members: &[NodeId],
+    ) -> Result<(), SpecViolation> {
+        let mut server = self.server().clone();
+        if let Some(TraceEvent::Appended { index, .. }) = self.peek() {
+            let current = server.members();
+            let changed = members.iter().filter(|id| !current.contains(id)).count()
+                + current.iter().filter(|id| !members.contains(id)).count();
+            let pending = server.log[server.commit.get() as usize..]
+                .iter()
+                .any(|entry| {
+                    entry
+                        .command
+                        .as_deref()
+                        .and_then(Command::membership)
+                        .is_some()
+                });
+            if server.state != RaftState::Leader
+                || *index != LogIndex::new(server.log.len() as u64 + 1)
+                || changed != 1
+                || pending
+                || !members.contains(&self.id)
+            {
+                return Err(self.violation(
+                    self.position(),
+                    "ChangeMembership",
+                    "change_membership",
+                    format!(
+                        "appended {:?} at index {} as {:?} with members {:?}",
+                        members,
+                        index.get(),
+                        server.state,
+                        current
+                    ),
+                ));
+            }
+            self.next += 1;
+            server.log.push(Entry {
+                term: server.term,
+                command: Some(Command::Membership(members.to_vec()).encode()),
+            });
+            let peers = server.peers();
+            server.match_index.retain(|peer, _| peers.contains(peer));
+        }
+        self.settle(
+            position,
+            vec![Outcome::new(
+                server,
+                "ChangeMembership",
+                "change_membership",
+            )],
+            false,
+        )?;
+        Ok(())
+    }
+
+    // Only a leader appends, at the end of its log.
+    fn appended(&mut self) -> Result<(), SpecViolation> {
+        while let Some(TraceEvent::Appended { index, .. }) = self.peek() {
+            let server = self.server();
+            if server.state != RaftState::Leader
+                || *index != LogIndex::new(server.log.len() as u64 + 1)
+            {
+                return Err(self.violation(
+                    self.position(),
+                    "ClientRequest",
+                    "flush_proposals",
+                    format!(
+                        "appended index {} as {:?} with last index {}",
+                        index.get(),
+                        server.state,
+                        server.last_index().get()
+                    ),
+                ));
+            }
+            let term = server.term;
+            self.next += 1;
+            self.server_mut().log.push(Entry {
+                term,
+                command: None,
+            });
+        }
+        Ok(())
+    }
+
+    fn tick(&mut self, position: usize, election_timeout: bool) -> Result<(), SpecViolation> {
+        let mut allowed = Allowed::default();
+        self.appended()?;
+        let server = self.server().clone();
+
+        let outcomes = if !election_timeout {
+            vec![Outcome::new(server, "Next", "tick")]
+        } else if server.state == RaftState::Leader {
+            let mut outcomes = vec![Outcome::new(server.clone(), "CheckQuorum", "check_quorum")];
+            if server.check_quorum {
+                let mut stepped_down = server;
+                stepped_down.state = RaftState::Follower;
+                outcomes.push(Outcome::new(stepped_down, "CheckQuorum", "check_quorum"));
+            }
+            outcomes
+        } else if !server.members().contains(&self.id) {
+            // Nodes outside the configuration never campaign.
+            vec![Outcome::new(server, "Timeout", "tick")]
+        } else {
+            // Timeout
+            let mut candidate = server;
+            candidate.state = RaftState::Candidate;
+            candidate.term = Term::new(candidate.term.get() + 1);
+            candidate.voted_for = Some(self.id);
+            candidate.votes = BTreeSet::from([self.id]);
+            allowed.request_vote = Some(RequestVoteRequest {
+                term: candidate.term,
+                candidate_id: self.id,
+                last_log_index: candidate.last_index(),
+                last_log_term: candidate.last_term(),
+            });
+            if candidate.votes.len() >= candidate.quorum() {
+                candidate.become_leader();
+            }
+            vec![Outcome::new(candidate, "Timeout", "become_candidate")]
+        };
+        let inbox = std::mem::take(&mut self.inbox);
+        self.settle(position, outcomes, !inbox.is_empty())?;
+        self.note_leader(&mut allowed);
+
+        let replies = self.replies();
+        for (i, (position, message)) in inbox.iter().enumerate() {
+            let mut outcomes = self.receive(*position, message)?;
+            // Where the spec allows several replies, the one sent decides.
+            if let Some(reply) = replies.get(allowed.responses.len())
+                && outcomes
+                    .iter()
+                    .any(|outcome| outcome.response.as_ref() == Some(*reply))
+            {
+                outcomes.retain(|outcome| {
+                    outcome
+                        .response
+                        .as_ref()
+                        .is_none_or(|response| response == *reply)
+                });
+            }
+            let outcome = self.settle(*position, outcomes, i + 1 < inbox.len())?;
+            if let Some(response) = outcome.response {
+                allowed
+                    .responses
+                    .push((*position, outcome.action, outcome.handler, response));
+            }
+            self.note_leader(&mut allowed);
+        }
+
+        if let Some(TraceEvent::Applied { index, .. }) = self.peek() {
+            if *index != self.server().commit {
+                return Err(self.violation(
+                    self.position(),
+                    "Apply",
+                    "take_committed_entries",
+                    format!(
+                        "applied through {} with commit index {}",
+                        index.get(),
+                        self.server().commit.get()
+                    ),
+                ));
+            }
+            self.next += 1;
+        }
+
+        while let Some(TraceEvent::Sent { action, .. }) = self.peek() {
+            self.sent(action, &mut allowed)?;
+            self.next += 1;
+        }
+
+        match allowed.responses.first() {
+            Some((position, action, handler, response)) => Err(self.violation(
+                *position,
+                action,
+                handler,
+                format!("expected the reply {:?}, none was sent", response),
+            )),
+            None => Ok(()),
+        }
+    }
+
+    // The replies sent at the end of the current tick, in order.
+    fn replies(&self) -> Vec<&'a RaftAction> {
+        self.events[self.next..]
+            .iter()
+            .map(|(_, event)| *event)
+            .take_while(|event| {
+                !matches!(
+                    event,
+                    TraceEvent::Started { .. }
+                        | TraceEvent::Tick { .. }
+                        | TraceEvent::Received { .. }
+                        | TraceEvent::Proposed { .. }
+                        | TraceEvent::ReadRequested { .. }
+                        | TraceEvent::MembershipRequested { .. }
+                )
+            })
+            .filter_map(|event| match event {
+                TraceEvent::Sent {
+                    action:
+                        action @ (RaftAction::SendRequestVoteResponse(..)
+                        | RaftAction::SendAppendEntriesResponse(..)),
+                    ..
+                } => Some(action),
+                _ => None,
+            })
+            .collect()
+    }
+
+    fn note_leader(&self, allowed: &mut Allowed) {
+        let server = self.server();
+        let current = allowed
+            .leader
+            .as_ref()
+            .is_some_and(|(term, _)| *term == server.term);
+        if server.state == RaftState::Leader && !current {
+            allowed.leader = Some((server.term, server.log.clone()));
+        }
+    }
+
+    // Every outcome the spec allows for `message`, most likely first.
+    fn receive(&self, position: usize, message: &RaftEvent) -> Result<Vec<Outcome>, SpecViolation> {
+        let server = self.server().clone();
+
+        Ok(match message {
+            RaftEvent::ReceivedRequestVote(request) => {
+                let mut outcomes = Vec::new();
+                if request.term > server.term && server.check_quorum {
+                    // A node that still hears from a leader ignores the
+                    // request instead of updating its term.
+                    let mut ignored = Outcome::new(
+                        server.clone(),
+                        "HandleRequestVoteRequest",
+                        "handle_request_vote",
+                    );
+                    ignored.response = Some(vote_response(&server, request.candidate_id, false));
+                    outcomes.push(ignored);
+                }
+                outcomes.insert(0, handle_request_vote(server, request));
+                outcomes
+            }
+            RaftEvent::ReceivedRequestVoteResponse(peer, response) => {
+                vec![handle_request_vote_response(server, *peer, response)]
+            }
+            RaftEvent::ReceivedAppendEntries(request) => {
+                vec![handle_append_entries(server, request).map_err(|message| {
+                    self.violation(
+                        position,
+                        "HandleAppendEntriesRequest",
+                        "handle_append_entries",
+                        message,
+                    )
+                })?]
+            }
+            RaftEvent::ReceivedAppendEntriesResponse(peer, response) => {
+                let dropped = Outcome::new(
+                    server.clone(),
+                    "DropStaleResponse",
+                    "handle_append_entries_response",
+                );
+                if server.state != RaftState::Leader {
+                    let mut updated = server;
+                    let mut outcomes = vec![dropped];
+                    if response.term > updated.term {
+                        updated.update_term(response.term);
+                        outcomes.push(Outcome::new(
+                            updated,
+                            "UpdateTerm",
+                            "handle_append_entries_response",
+                        ));
+                    }
+                    outcomes
+                } else {
+                    vec![handle_append_entries_response(server, *peer, response)]
+                }
+            }
+            RaftEvent::ReceivedReadIndex(_)
+            | RaftEvent::ReceivedReadIndexResponse(_)
+            | RaftEvent::ElectionTimeout
+            | RaftEvent::HeartbeatTimeout => vec![Outcome::new(server, "Next", "handle_event")],
+        })
+    }
+
+    // Picks the first outcome matching the recorded state change, if any,
+    // and then the recorded commit, if the outcome allows it. `more` is set
+    // when later inputs of the tick could account for the commit instead.
+    fn settle(
+        &mut self,
+        position: usize,
+        outcomes: Vec<Outcome>,
+        more: bool,
+    ) -> Result<Outcome, SpecViolation> {
+        let recorded = match self.peek() {
+            Some(TraceEvent::StateChanged { from, to, term, .. }) => Some((*from, *to, *term)),
+            _ => None,
+        };
+        let (action, handler) = (outcomes[0].action, outcomes[0].handler);
+
+        let changed =
+            |outcome: &Outcome| (outcome.server.state, outcome.server.term) != self.observed;
+        let matches = |outcome: &Outcome| match recorded {
+            Some((from, to, term)) => {
+                changed(outcome)
+                    && from == self.observed.0
+                    && (to, term) == (outcome.server.state, outcome.server.term)
+            }
+            None => false,
+        };
+        // A state change recorded after this input may belong to a later one.
+        let consumed = outcomes.iter().any(matches);
+        let chosen = outcomes.into_iter().find(|outcome| {
+            if consumed {
+                matches(outcome)
+            } else {
+                !changed(outcome) && (recorded.is_none() || more)
+            }
+        });
+
+        let Some(mut outcome) = chosen else {
+            let recorded = match recorded {
+                Some((from, to, term)) => format!("{:?} -> {:?} in term {}", from, to, term.get()),
+                None => "no state change".to_string(),
+            };
+            return Err(self.violation(
+                if recorded == "no state change" {
+                    position
+                } else {
+                    self.position()
+                },
+                action,
+                handler,
+                format!("recorded {}, which the spec does not allow here", recorded),
+            ));
+        };
+        if consumed {
+            self.next += 1;
+            self.observed = (outcome.server.state, outcome.server.term);
+        }
+
+        if let Some(TraceEvent::Committed { index, .. }) = self.peek() {
+            let server = &outcome.server;
+            let allowed = *index > server.commit
+                && *index <= server.last_index()
+                && if server.state == RaftState::Leader {
+                    server.committable(*index)
+                } else {
+                    *index <= outcome.commit_limit
+                };
+            if allowed {
+                outcome.server.commit = *index;
+                self.next += 1;
+            } else if !more {
+                let (action, handler) = if server.state == RaftState::Leader {
+                    ("AdvanceCommitIndex", "update_commit_index")
+                } else {
+                    (outcome.action, outcome.handler)
+                };
+                return Err(self.violation(
+                    self.position(),
+                    action,
+                    handler,
+                    format!(
+                        "committed {} with commit index {}, last index {}",
+                        index.get(),
+                        server.commit.get(),
+                        server.last_index().get()
+                    ),
+                ));
+            }
+        }
+
+        self.server = Some(outcome.server.clone());
+        Ok(outcome)
+    }
+
+    fn sent(&mut self, action: &RaftAction, allowed: &mut Allowed) -> Result<(), SpecViolation> {
+        let position = self.position();
+
+        match action {
+            RaftAction::SendRequestVote(_, request) => {
+                if allowed.request_vote.as_ref() != Some(request) {
+                    return Err(self.violation(
+                        position,
+                        "RequestVote",
+                        "become_candidate",
+                        format!("sent {:?}, allowed {:?}", request, allowed.request_vote),
+                    ));
+                }
+            }
+            RaftAction::SendAppendEntries(_, request) => {
+                let result = match &allowed.leader {
+                    Some((term, log)) if *term == request.term => {
+                        check_append_entries(self.id, log, request)
+                    }
+                    _ => Err(format!("not leader in term {}", request.term.get())),
+                };
+                let result = result.and_then(|commands| {
+                    let server = self.server_mut();
+                    if request.leader_commit > server.commit {
+                        return Err(format!(
+                            "leader commit {} is past the commit index {}",
+                            request.leader_commit.get(),
+                            server.commit.get()
+                        ));
+                    }
+                    // The leader's log is unchanged while it leads, so
+                    // commands first seen here complete the model's log.
+                    if server.term == request.term && server.state == RaftState::Leader {
+                        for (index, command) in commands {
+                            server.log[index].command.get_or_insert(command);
+                        }
+                    }
+                    Ok(())
+                });
+                result.map_err(|message| {
+                    self.violation(position, "AppendEntries", "create_append_entries", message)
+                })?;
+            }
+            RaftAction::SendRequestVoteResponse(..) | RaftAction::SendAppendEntriesResponse(..) => {
+                if allowed.responses.is_empty() {
+                    return Err(self.violation(
+                        position,
+                        "Reply",
+                        "handle_event",
+                        "sent a reply to no request".to_string(),
+                    ));
+                }
+                let (_, action_name, handler, expected) = allowed.responses.remove(0);
+                if expected != *action {
+                    return Err(self.violation(
+                        position,
+                        action_name,
+                        handler,
+                        format!("replied {:?}, the spec replies {:?}", action, expected),
+                    ));
+                }
+            }
+            RaftAction::SendReadIndex(..) | RaftAction::SendReadIndexResponse(..) => {}
+        }
+        Ok(())
+    }
+}
+
+fn vote_response(server: &Server, candidate: NodeId, vote_granted: bool) -> RaftAction {
+    RaftAction::SendRequestVoteResponse(
+        candidate,
+        RequestVoteResponse {
+            term: server.term,
+            vote_granted,
+        },
+    )
+}
+
+// HandleRequestVoteRequest, after UpdateTerm if the request is newer.
+fn handle_request_vote(mut server: Server, request: &RequestVoteRequest) -> Outcome {
+    if request.term > server.term {
+        server.update_term(request.term);
+    }
+
+    let log_ok = request.last_log_term > server.last_term()
+        || (request.last_log_term == server.last_term()
+            && request.last_log_index >= server.last_index());
+    let grant = request.term == server.term
+        && log_ok
+        && server
+            .voted_for
+            .is_none_or(|voted| voted == request.candidate_id);
+    if grant {
+        server.voted_for = Some(request.candidate_id);
+    }
+
+    let response = vote_response(&server, request.candidate_id, grant);
+    let mut outcome = Outcome::new(server, "HandleRequestVoteRequest", "handle_request_vote");
+    outcome.response = Some(response);
+    outcome
+}
+
+// HandleRequestVoteResponse, then BecomeLeader once a quorum voted.
+fn handle_request_vote_response(
+    mut server: Server,
+    peer: NodeId,
+    response: &RequestVoteResponse,
+) -> Outcome {
+    if response.term > server.term {
+        server.update_term(response.term);
+        return Outcome::new(server, "UpdateTerm", "handle_request_vote_response");
+    }
+
+    if server.state == RaftState::Candidate
+        && response.term == server.term
+        && response.vote_granted
+        && server.peers().contains(&peer)
+    {
+        server.votes.insert(peer);
+        if server.votes.len() >= server.quorum() {
+            server.become_leader();
+            return Outcome::new(server, "BecomeLeader", "become_leader");
+        }
+    }
+    Outcome::new(
+        server,
+        "HandleRequestVoteResponse",
+        "handle_request_vote_response",
+    )
+}
+
+// HandleAppendEntriesRequest, after UpdateTerm if the request is newer.
+fn handle_append_entries(
+    mut server: Server,
+    request: &AppendEntriesRequest,
+) -> Result<Outcome, String> {
+    if request.term > server.term {
+        server.update_term(request.term);
+    }
+
+    let reject = |server: Server| {
+        let response = RaftAction::SendAppendEntriesResponse(
+            request.leader_id,
+            AppendEntriesResponse {
+                term: server.term,
+                success: false,
+                match_index: LogIndex::ZERO,
+                heartbeat_round: request.heartbeat_round,
+            },
+        );
+        let mut outcome = Outcome::new(
+            server,
+            "HandleAppendEntriesRequest",
+            "handle_append_entries",
+        );
+        outcome.response = Some(response);
+        outcome
+    };
+
+    if request.term < server.term {
+        return Ok(reject(server));
+    }
+    if server.state == RaftState::Leader {
+        return Err(format!("two leaders in term {}", server.term.get()));
+    }
+    server.state = RaftState::Follower;
+
+    if server.term_at(request.prev_log_index) != Some(request.prev_log_term) {
+        return Ok(reject(server));
+    }
+
+    for (offset, entry) in request.entries.iter().enumerate() {
+        let position = request.prev_log_index.get() as usize + offset;
+        if entry.index.get() as usize != position + 1 {
+            return Err(format!(
+                "entry {} sent at index {}",
+                entry.index.get(),
+                position + 1
+            ));
+        }
+        match server.log.get(position) {
+            Some(existing) if existing.term == entry.term => {
+                if existing
+                    .command
+                    .as_ref()
+                    .is_some_and(|command| *command != entry.command)
+                {
+                    return Err(format!(
+                        "entry {} of term {} differs from the one already in the log",
+                        entry.index.get(),
+                        entry.term.get()
+                    ));
+                }
+            }
+            Some(_) => {
+                server.log.truncate(position);
+                server.log.push(Entry {
+                    term: entry.term,
+                    command: Some(entry.command.clone()),
+                });
+            }
+            None => server.log.push(Entry {
+                term: entry.term,
+                command: Some(entry.command.clone()),
+            }),
+        }
+    }
+
+    let match_index = LogIndex::new(request.prev_log_index.get() + request.entries.len() as u64);
+    let response = RaftAction::SendAppendEntriesResponse(
+        request.leader_id,
+        AppendEntriesResponse {
+            term: server.term,
+            success: true,
+            match_index,
+            heartbeat_round: request.heartbeat_round,
+        },
+    );
+    let mut outcome = Outcome::new(
+        server,
+        "HandleAppendEntriesRequest",
+        "handle_append_entries",
+    );
+    outcome.response = Some(response);
+    outcome.commit_limit = request.leader_commit.min(match_index);
+    Ok(outcome)
+}
+
+// HandleAppendEntriesResponse; AdvanceCommitIndex is checked on the commit
+// the implementation records afterwards.
+fn handle_append_entries_response(
+    mut server: Server,
+    peer: NodeId,
+    response: &AppendEntriesResponse,
+) -> Outcome {
+    if response.term > server.term {
+        server.update_term(response.term);
+        return Outcome::new(server, "UpdateTerm", "handle_append_entries_response");
+    }
+
+    if response.term == server.term && response.success && server.peers().contains(&peer) {
+        let matched = server.match_index.entry(peer).or_insert(LogIndex::ZERO);
+        *matched = (*matched).max(response.match_index);
+    }
+    Outcome::new(
+        server,
+        "HandleAppendEntriesResponse",
+        "handle_append_entries_response",
+    )
+}
+
+// AppendEntries: the entries must be the leader's, after a matching
+// previous entry. Returns the commands seen, by log position.
+fn check_append_entries(
+    leader: NodeId,
+    log: &[Entry],
+    request: &AppendEntriesRequest,
+) -> Result<Vec<(usize, Vec<u8>)>, String> {
+    if request.leader_id != leader {
+        return Err(format!("sent as node {}", request.leader_id.get()));
+    }
+
+    let prev = request.prev_log_index.get() as usize;
+    let prev_term = match prev {
+        0 => Some(Term::ZERO),
+        i => log.get(i - 1).map(|entry| entry.term),
+    };
+    if prev_term != Some(request.prev_log_term) {
+        return Err(format!(
+            "previous entry {} of term {} is not in the leader's log",
+            prev,
+            request.prev_log_term.get()
+        ));
+    }
+
+    let mut commands = Vec::new();
+    for (offset, entry) in request.entries.iter().enumerate() {
+        let position = prev + offset;
+        match log.get(position) {
+            Some(own)
+                if own.term == entry.term
+                    && entry.index.get() as usize == position + 1
+                    && own
+                        .command
+                        .as_ref()
+                        .is_none_or(|command| *command == entry.command) =>
+            {
+                commands.push((position, entry.command.clone()));
+            }
+            _ => {
+                return Err(format!(
+                    "entry {} of term {} is not the leader's entry {}",
+                    entry.index.get(),
+                    entry.term.get(),
+                    position + 1
+                ));
+            }
+        }
+    }
+    Ok(commands)
+}
@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidSnapshot;
+
+pub trait StateMachine: Send {
+    fn apply(&mut self, command: &[u8]) -> Vec<u8>;
+
+    fn query(&self, query: &[u8]) -> Vec<u8>;
+
+    fn snapshot(&self) -> Vec<u8>;
+
+    fn restore(&mut self, snapshot: &[u8]) -> Result<(), InvalidSnapshot>;
+}
+
+// Commands are `key=value` pairs and queries are plain keys. Applying a
+// command returns the previous value, querying a missing key returns nothing.
+#[derive(Debug, Clone, Default)]
+pub struct KvStore {
+    data: HashMap<Vec<u8>, Vec<u8>>,
+}
+
+impl KvStore {
+    pub fn new() -> Self {
+        Self {
+            data: HashMap::new(),
+        }
+    }
+
+    pub fn get(&self, key: &[u8]) -> Option<&[u8]> {
+        self.data.get(key).map(|value| value.as_slice())
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+}
+
+impl StateMachine for KvStore {
+    fn apply(&mut self, command: &[u8]) -> Vec<u8> {
+        let Some(split) = command.iter().position(|&b| b == b'=') else {
+            return Vec::new();
+        };
+
+        let key = command[..split].to_vec();
+        let value = command[split + 1..].to_vec();
+
+        self.data.insert(key, value).unwrap_or_default()
+    }
+
+    fn query(&self, query: &[u8]) -> Vec<u8> {
+        self.get(query).map(|value| value.to_vec()).unwrap_or_default()
+    }
+
+    fn snapshot(&self) -> Vec<u8> {
+        let mut pairs: Vec<_> = self.data.iter().collect();
+        pairs.sort();
+
+        let mut out = Vec::new();
+        put_u64(&mut out, pairs.len() as u64);
+        for (key, value) in pairs {
+            put_bytes(&mut out, key);
+            put_bytes(&mut out, value);
+        }
+        out
+    }
+
+    fn restore(&mut self, snapshot: &[u8]) -> Result<(), InvalidSnapshot> {
+        let mut reader = SnapshotReader::new(snapshot);
+        let mut data = HashMap::new();
+
+        for _ in 0..reader.u64()? {
+            let key = reader.bytes()?.to_vec();
+            let value = reader.bytes()?.to_vec();
+            data.insert(key, value);
+        }
+        reader.finish()?;
+
+        self.data = data;
+        Ok(())
+    }
+}
+
+pub(crate) fn put_u64(out: &mut Vec<u8>, value: u64) {
+    out.extend_from_slice(&value.to_be_bytes());
+}
+
+pub(crate) fn put_bytes(out: &mut Vec<u8>, bytes: &[u8]) {
+    put_u64(out, bytes.len() as u64);
+    out.extend_from_slice(bytes);
+}
+
+pub(crate) struct SnapshotReader<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> SnapshotReader<'a> {
+    pub(crate) fn new(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    pub(crate) fn u64(&mut self) -> Result<u64, InvalidSnapshot> {
+        let bytes = self.take(8)?;
+        Ok(u64::from_be_bytes(bytes.try_into().unwrap()))
+    }
+
+    pub(crate) fn bytes(&mut self) -> Result<&'a [u8], InvalidSnapshot> {
+        let len = usize::try_from(self.u64()?).map_err(|_| InvalidSnapshot)?;
+        self.take(len)
+    }
+
+    pub(crate) fn finish(self) -> Result<(), InvalidSnapshot> {
+        if self.buf.is_empty() { Ok(()) } else { Err(InvalidSnapshot) }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8], InvalidSnapshot> {
+        if self.buf.len() < len {
+            return Err(InvalidSnapshot);
+        }
+
+        let (head, tail) = self.buf.split_at(len);
+        self.buf = tail;
+        Ok(head)
+    }
+}
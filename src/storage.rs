@@ -0,0 +1,186 @@
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+
+use crate::log::{self, LogEntry, LogOffset, LogReader};
+use crate::types::{LogIndex, NodeId, Term};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct HardState {
+    pub current_term: Term,
+    pub voted_for: Option<NodeId>,
+}
+
+// Durable home of a node's persistent state. Writes may sit in a buffer
+// until `sync` returns; only then are they guaranteed to survive a crash.
+pub trait Storage: Send {
+    fn save_hard_state(&mut self, state: HardState);
+
+    // Removes every entry with an index >= `from`.
+    fn truncate(&mut self, from: LogIndex);
+
+    fn append(&mut self, entries: &[LogEntry]);
+
+    // Removes every entry through `offset.index`, e.g. once a snapshot
+    // covers them, and keeps `offset` with the hard state so the log loads
+    // with the term of the entry before its first one.
+    fn compact(&mut self, offset: LogOffset);
+
+    fn sync(&mut self);
+
+    fn load(&self) -> (HardState, LogOffset, Vec<LogEntry>);
+
+    // Like `load`, but only the commands of the newest entries that fit in
+    // `budget` bytes need to be read; older entries may come with empty
+    // commands, for `log_reader` to read back. Also returns the index the
+    // commands start at.
+    fn load_tail(&self, _budget: u64) -> (HardState, LogOffset, Vec<LogEntry>, LogIndex) {
+        let (hard_state, offset, entries) = self.load();
+        let first = LogIndex::new(offset.index.get() + 1);
+        (hard_state, offset, entries, first)
+    }
+
+    // Lets the log read back entries it dropped from memory. Storage that
+    // cannot leaves the log unbounded.
+    fn log_reader(&self) -> Option<Arc<dyn LogReader>> {
+        None
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Write {
+    HardState(HardState),
+    Truncate(LogIndex),
+    Append(Vec<LogEntry>),
+    Compact(LogOffset),
+}
+
+#[derive(Debug, Clone, Default)]
+struct Persisted {
+    hard_state: HardState,
+    offset: LogOffset,
+    entries: Vec<LogEntry>,
+}
+
+impl Persisted {
+    fn apply(&mut self, write: &Write) {
+        match write {
+            Write::HardState(state) => self.hard_state = *state,
+            Write::Truncate(from) => {
+                let keep = self.entries.partition_point(|entry| entry.index < *from);
+                self.entries.truncate(keep);
+            }
+            Write::Append(entries) => self.entries.extend_from_slice(entries),
+            Write::Compact(offset) => {
+                self.entries.retain(|entry| entry.index > offset.index);
+                self.offset = *offset;
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+struct Disk {
+    synced: Persisted,
+    unsynced: Vec<Write>,
+}
+
+// In-memory storage for tests and the simulator. Clones share the same disk,
+// so a handle kept outside a node survives that node being dropped.
+#[derive(Debug, Clone, Default)]
+pub struct MemStorage {
+    disk: Arc<Mutex<Disk>>,
+}
+
+impl MemStorage {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // What a power failure does: writes that were never synced are gone.
+    pub fn lose_unsynced(&self) {
+        self.disk.lock().unwrap().unsynced.clear();
+    }
+
+    pub fn unsynced_writes(&self) -> usize {
+        self.disk.lock().unwrap().unsynced.len()
+    }
+
+    fn write(&mut self, write: Write) {
+        self.disk.lock().unwrap().unsynced.push(write);
+    }
+}
+
+impl Storage for MemStorage {
+    fn save_hard_state(&mut self, state: HardState) {
+        self.write(Write::HardState(state));
+    }
+
+    fn truncate(&mut self, from: LogIndex) {
+        self.write(Write::Truncate(from));
+    }
+
+    fn append(&mut self, entries: &[LogEntry]) {
+        if !entries.is_empty() {
+            self.write(Write::Append(entries.to_vec()));
+        }
+    }
+
+    fn compact(&mut self, offset: LogOffset) {
+        self.write(Write::Compact(offset));
+    }
+
+    fn sync(&mut self) {
+        let mut disk = self.disk.lock().unwrap();
+        let unsynced = std::mem::take(&mut disk.unsynced);
+        for write in &unsynced {
+            disk.synced.apply(write);
+        }
+    }
+
+    // A process that crashes without losing power still finds its unsynced
+    // writes in the page cache, so they are part of what is loaded.
+    fn load(&self) -> (HardState, LogOffset, Vec<LogEntry>) {
+        let disk = self.disk.lock().unwrap();
+        let mut state = disk.synced.clone();
+        for write in &disk.unsynced {
+            state.apply(write);
+        }
+        (state.hard_state, state.offset, state.entries)
+    }
+
+    fn log_reader(&self) -> Option<Arc<dyn LogReader>> {
+        Some(Arc::new(self.clone()))
+    }
+}
+
+impl LogReader for MemStorage {
+    // Copies the synced entries asked for and replays only the unsynced
+    // writes over them.
+    fn read(&self, from: LogIndex, to: LogIndex, max_bytes: u64) -> Vec<LogEntry> {
+        let disk = self.disk.lock().unwrap();
+        let synced = &disk.synced.entries;
+        let start = synced.partition_point(|entry| entry.index < from);
+        let end = synced.partition_point(|entry| entry.index <= to);
+        let mut entries = synced[start..end].to_vec();
+
+        for write in &disk.unsynced {
+            match write {
+                Write::HardState(_) => {}
+                Write::Truncate(from) => {
+                    let keep = entries.partition_point(|entry| entry.index < *from);
+                    entries.truncate(keep);
+                }
+                Write::Append(appended) => entries.extend(
+                    appended
+                        .iter()
+                        .filter(|entry| (from..=to).contains(&entry.index))
+                        .cloned(),
+                ),
+                Write::Compact(offset) => entries.retain(|entry| entry.index > offset.index),
+            }
+        }
+        entries.truncate(log::fitting(&entries, max_bytes));
+        entries
+    }
+}
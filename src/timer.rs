@@ -1,41 +1,56 @@
+use std::sync::Arc;
 use std::time::{Duration, Instant};
 
 use rand::Rng;
 
+use crate::clock::{SharedClock, SystemClock};
+
+const ELECTION_TIMEOUT_MIN_MS: u64 = 150;
+const ELECTION_TIMEOUT_MAX_MS: u64 = 300;
+
 #[derive(Debug, Clone)]
 pub struct Timer {
     deadline: Instant,
     duration: Duration,
+    clock: SharedClock,
 }
 
 impl Timer {
     pub fn new(duration: Duration) -> Self {
+        Self::with_clock(duration, Arc::new(SystemClock))
+    }
+
+    pub fn with_clock(duration: Duration, clock: SharedClock) -> Self {
         Self {
-            deadline: Instant::now() + duration,
+            deadline: clock.now() + duration,
             duration,
+            clock,
         }
     }
 
     pub fn is_elapsed(&self) -> bool {
-        Instant::now() >= self.deadline
+        self.clock.now() >= self.deadline
     }
 
     pub fn reset(&mut self) {
-        self.deadline = Instant::now() + self.duration
+        self.deadline = self.clock.now() + self.duration
     }
 
     pub fn reset_with(&mut self, duration: Duration) {
-        self.deadline = Instant::now() + duration;
+        self.deadline = self.clock.now() + duration;
         self.duration = duration;
     }
 }
 
-pub fn random_election_timeout() -> Duration {
-    let mut rng = rand::rng();
-    let millis = rng.random_range(150..=300);
+pub fn random_election_timeout(rng: &mut impl Rng) -> Duration {
+    let millis = rng.random_range(ELECTION_TIMEOUT_MIN_MS..=ELECTION_TIMEOUT_MAX_MS);
     Duration::from_millis(millis)
 }
 
+pub fn min_election_timeout() -> Duration {
+    Duration::from_millis(ELECTION_TIMEOUT_MIN_MS)
+}
+
 pub fn heartbeat_interval() -> Duration {
     Duration::from_millis(50)
 }
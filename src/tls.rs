@@ -0,0 +1,67 @@
+use std::fs;
+use std::io;
+use std::path::Path;
+
+use tonic::transport::{Certificate, ClientTlsConfig, Identity, ServerTlsConfig};
+use x509_parser::extensions::GeneralName;
+
+use crate::types::NodeId;
+
+// Mutual TLS for the Raft service. Every node presents a certificate signed
+// by the cluster CA and verifies its peer's against the same CA, both as
+// client and as server. A node's certificate identifies it by a DNS subject
+// alternative name of the form `node-<id>`.
+#[derive(Clone)]
+pub struct TlsConfig {
+    identity: Identity,
+    ca: Certificate,
+}
+
+impl TlsConfig {
+    // PEM-encoded certificate chain, private key and CA bundle.
+    pub fn new(cert: impl AsRef<[u8]>, key: impl AsRef<[u8]>, ca: impl AsRef<[u8]>) -> Self {
+        Self {
+            identity: Identity::from_pem(cert, key),
+            ca: Certificate::from_pem(ca),
+        }
+    }
+
+    pub fn from_files(cert: &Path, key: &Path, ca: &Path) -> io::Result<Self> {
+        Ok(Self::new(fs::read(cert)?, fs::read(key)?, fs::read(ca)?))
+    }
+
+    // Requires clients to present a certificate signed by the CA.
+    pub fn server(&self) -> ServerTlsConfig {
+        ServerTlsConfig::new()
+            .identity(self.identity.clone())
+            .client_ca_root(self.ca.clone())
+    }
+
+    // Accepts the server only if its certificate names `peer`.
+    pub fn client(&self, peer: NodeId) -> ClientTlsConfig {
+        ClientTlsConfig::new()
+            .ca_certificate(self.ca.clone())
+            .identity(self.identity.clone())
+            .domain_name(node_name(peer))
+    }
+}
+
+pub fn node_name(id: NodeId) -> String {
+    format!("node-{}", id.get())
+}
+
+// Whether a DER certificate carries `id`'s node name.
+pub fn names_node(cert: &[u8], id: NodeId) -> bool {
+    let Ok((_, cert)) = x509_parser::parse_x509_certificate(cert) else {
+        return false;
+    };
+    let Ok(Some(san)) = cert.subject_alternative_name() else {
+        return false;
+    };
+
+    let name = node_name(id);
+    san.value
+        .general_names
+        .iter()
+        .any(|general| matches!(general, GeneralName::DNSName(dns) if *dns == name))
+}
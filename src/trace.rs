@@ -0,0 +1,492 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::clock::ManualClock;
+use crate::config::RaftConfig;
+use crate::event::RaftEvent;
+use crate::log::LogEntry;
+use crate::node::RaftNode;
+use crate::raft::{RaftAction, RaftRunner};
+use crate::read_index::ReadConsistency;
+use crate::rpc::{
+    AppendEntriesRequest, AppendEntriesResponse, ReadIndexRequest, ReadIndexResponse,
+    RequestVoteRequest, RequestVoteResponse,
+};
+use crate::session::ClientId;
+use crate::state_machine::KvStore;
+use crate::storage::{HardState, MemStorage, Storage};
+use crate::types::{LogIndex, NodeId, RaftState, Term};
+
+// One entry of a node's trace. `at` is the node's own clock, measured from
+// when tracing started. `Started`, `Tick`, `Received`, `Proposed`,
+// `ReadRequested` and `MembershipRequested` are the inputs replay feeds back
+// in; everything else is output the replayed node must reproduce.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum TraceEvent {
+    Started {
+        #[serde(rename = "at_ns", with = "nanos")]
+        at: Duration,
+        id: NodeId,
+        // The voting members the node started with; without `id` if it
+        // joined a running cluster.
+        members: Vec<NodeId>,
+        config: RaftConfig,
+        #[serde(flatten)]
+        hard_state: HardState,
+        entries: Vec<LogEntry>,
+    },
+    // A tick that had work to do, with the timers that had fired.
+    Tick {
+        #[serde(rename = "at_ns", with = "nanos")]
+        at: Duration,
+        election_timeout: bool,
+        heartbeat_timeout: bool,
+    },
+    Received {
+        #[serde(rename = "at_ns", with = "nanos")]
+        at: Duration,
+        #[serde(flatten, with = "received")]
+        message: RaftEvent,
+    },
+    Proposed {
+        #[serde(rename = "at_ns", with = "nanos")]
+        at: Duration,
+        client_id: ClientId,
+        sequence: u64,
+        #[serde(with = "hex")]
+        command: Vec<u8>,
+    },
+    ReadRequested {
+        #[serde(rename = "at_ns", with = "nanos")]
+        at: Duration,
+        #[serde(with = "hex")]
+        query: Vec<u8>,
+        consistency: ReadConsistency,
+    },
+    MembershipRequested {
+        #[serde(rename = "at_ns", with = "nanos")]
+        at: Duration,
+        members: Vec<NodeId>,
+    },
+    StateChanged {
+        #[serde(rename = "at_ns", with = "nanos")]
+        at: Duration,
+        from: RaftState,
+        to: RaftState,
+        term: Term,
+    },
+    Sent {
+        #[serde(rename = "at_ns", with = "nanos")]
+        at: Duration,
+        #[serde(flatten, with = "sent")]
+        action: RaftAction,
+    },
+    // A proposal the leader appended to its log.
+    Appended {
+        #[serde(rename = "at_ns", with = "nanos")]
+        at: Duration,
+        index: LogIndex,
+    },
+    Committed {
+        #[serde(rename = "at_ns", with = "nanos")]
+        at: Duration,
+        index: LogIndex,
+    },
+    Applied {
+        #[serde(rename = "at_ns", with = "nanos")]
+        at: Duration,
+        index: LogIndex,
+    },
+}
+
+impl TraceEvent {
+    pub fn at(&self) -> Duration {
+        match self {
+            TraceEvent::Started { at, .. }
+            | TraceEvent::Tick { at, .. }
+            | TraceEvent::Received { at, .. }
+            | TraceEvent::Proposed { at, .. }
+            | TraceEvent::ReadRequested { at, .. }
+            | TraceEvent::MembershipRequested { at, .. }
+            | TraceEvent::StateChanged { at, .. }
+            | TraceEvent::Sent { at, .. }
+            | TraceEvent::Appended { at, .. }
+            | TraceEvent::Committed { at, .. }
+            | TraceEvent::Applied { at, .. } => *at,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonError(pub String);
+
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+// A line of a cluster trace: a node's event with the node id and the
+// simulator time added.
+#[derive(Serialize, Deserialize)]
+pub struct ClusterLine {
+    pub node: NodeId,
+    #[serde(rename = "sim_ns", with = "nanos")]
+    pub at: Duration,
+    #[serde(flatten)]
+    pub event: TraceEvent,
+}
+
+// One JSON object per line.
+pub fn to_json_lines(trace: &[TraceEvent]) -> String {
+    let mut out = String::new();
+    for event in trace {
+        out.push_str(&serde_json::to_string(event).unwrap());
+        out.push('\n');
+    }
+    out
+}
+
+fn parse_lines<T: for<'de> Deserialize<'de>>(text: &str) -> Result<Vec<T>, JsonError> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| {
+            serde_json::from_str(line).map_err(|err| JsonError(format!("line {}: {}", i + 1, err)))
+        })
+        .collect()
+}
+
+pub fn from_json_lines(text: &str) -> Result<Vec<TraceEvent>, JsonError> {
+    parse_lines(text)
+}
+
+// Reads a cluster trace, as written by `Simulator::trace_json_lines`,
+// keeping the order events happened in.
+pub fn from_cluster_json_lines(text: &str) -> Result<Vec<(NodeId, TraceEvent)>, JsonError> {
+    let lines: Vec<ClusterLine> = parse_lines(text)?;
+    Ok(lines.into_iter().map(|line| (line.node, line.event)).collect())
+}
+
+// Splits a cluster trace into one trace per node.
+pub fn by_node(trace: Vec<(NodeId, TraceEvent)>) -> BTreeMap<NodeId, Vec<TraceEvent>> {
+    let mut traces: BTreeMap<NodeId, Vec<TraceEvent>> = BTreeMap::new();
+    for (id, event) in trace {
+        traces.entry(id).or_default().push(event);
+    }
+    traces
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ReplayError {
+    // The trace does not begin with a `Started` event.
+    NotStarted,
+    // The replayed node's trace differs from the recorded one at `position`.
+    Diverged {
+        position: usize,
+        expected: Option<Box<TraceEvent>>,
+        actual: Option<Box<TraceEvent>>,
+    },
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::NotStarted => write!(f, "trace does not start with a `started` event"),
+            ReplayError::Diverged {
+                position,
+                expected,
+                actual,
+            } => {
+                let show = |event: &Option<Box<TraceEvent>>| {
+                    event
+                        .as_ref()
+                        .map_or("end of trace".to_string(), |event| {
+                            serde_json::to_string(event).unwrap()
+                        })
+                };
+                write!(
+                    f,
+                    "replay diverged at event {}:\n  recorded: {}\n  replayed: {}",
+                    position,
+                    show(expected),
+                    show(actual)
+                )
+            }
+        }
+    }
+}
+
+// Feeds the inputs of a recorded node trace into a fresh node on a manual
+// clock, with timers firing exactly where the trace says they did, and checks
+// that the node produces the same trace.
+pub fn replay(trace: &[TraceEvent]) -> Result<(), ReplayError> {
+    if !matches!(trace.first(), Some(TraceEvent::Started { .. })) {
+        return Err(ReplayError::NotStarted);
+    }
+
+    // A restarted node's trace holds one run per start.
+    let starts: Vec<usize> = (0..trace.len())
+        .filter(|&i| matches!(trace[i], TraceEvent::Started { .. }))
+        .chain([trace.len()])
+        .collect();
+    for run in starts.windows(2) {
+        replay_run(&trace[run[0]..run[1]]).map_err(|error| match error {
+            ReplayError::Diverged {
+                position,
+                expected,
+                actual,
+            } => ReplayError::Diverged {
+                position: run[0] + position,
+                expected,
+                actual,
+            },
+            error => error,
+        })?;
+    }
+    Ok(())
+}
+
+fn replay_run(trace: &[TraceEvent]) -> Result<(), ReplayError> {
+    let Some(TraceEvent::Started {
+        id,
+        members,
+        config,
+        hard_state,
+        entries,
+        ..
+    }) = trace.first()
+    else {
+        return Err(ReplayError::NotStarted);
+    };
+
+    let mut storage = MemStorage::new();
+    storage.save_hard_state(*hard_state);
+    storage.append(entries);
+    storage.sync();
+
+    let clock = Arc::new(ManualClock::new());
+    // Timers are driven by the trace, so the node's randomness is irrelevant.
+    let rng = StdRng::seed_from_u64(0);
+    let node = if members.contains(id) {
+        let peers = members.iter().copied().filter(|member| member != id).collect();
+        RaftNode::with_clock_and_rng(*id, peers, config.clone(), clock.clone(), rng)
+    } else {
+        RaftNode::joining(*id, config.clone(), clock.clone(), rng)
+    };
+    let mut runner = RaftRunner::with_storage(node, Box::new(KvStore::new()), Box::new(storage));
+    runner.enable_trace();
+
+    for event in &trace[1..] {
+        let at = event.at();
+        if at > clock.elapsed() {
+            clock.advance(at - clock.elapsed());
+        }
+
+        match event {
+            TraceEvent::Tick {
+                election_timeout,
+                heartbeat_timeout,
+                ..
+            } => {
+                runner.step(*election_timeout, *heartbeat_timeout);
+            }
+            TraceEvent::Received { message, .. } => runner.push_event(message.clone()),
+            TraceEvent::Proposed {
+                client_id,
+                sequence,
+                command,
+                ..
+            } => {
+                runner.propose(*client_id, *sequence, command.clone());
+            }
+            TraceEvent::ReadRequested {
+                query, consistency, ..
+            } => {
+                runner.read(query.clone(), *consistency);
+            }
+            TraceEvent::MembershipRequested { members, .. } => {
+                runner.change_membership(members.clone());
+            }
+            _ => {}
+        }
+    }
+
+    let replayed = runner.take_trace();
+    let len = trace.len().max(replayed.len());
+    match (0..len).find(|&i| trace.get(i) != replayed.get(i)) {
+        Some(position) => Err(ReplayError::Diverged {
+            position,
+            expected: trace.get(position).cloned().map(Box::new),
+            actual: replayed.get(position).cloned().map(Box::new),
+        }),
+        None => Ok(()),
+    }
+}
+
+// Byte strings are written as hex.
+pub mod hex {
+    use serde::de::Error;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(bytes: &[u8], serializer: S) -> Result<S::Ok, S::Error> {
+        let text: String = bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        serializer.serialize_str(&text)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Vec<u8>, D::Error> {
+        let text = String::deserialize(deserializer)?;
+        let invalid = || D::Error::custom(format!("invalid hex `{}`", text));
+        if !text.len().is_multiple_of(2) {
+            return Err(invalid());
+        }
+        (0..text.len())
+            .step_by(2)
+            .map(|i| {
+                text.get(i..i + 2)
+                    .and_then(|byte| u8::from_str_radix(byte, 16).ok())
+                    .ok_or_else(invalid)
+            })
+            .collect()
+    }
+}
+
+// Durations are written as whole nanoseconds.
+pub mod nanos {
+    use std::time::Duration;
+
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(duration.as_nanos() as u64)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Duration, D::Error> {
+        u64::deserialize(deserializer).map(Duration::from_nanos)
+    }
+}
+
+// Messages on the wire of a trace, tagged with their `type`. Sent and
+// received messages share them; who sent or receives one goes next to it.
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum Message {
+    ElectionTimeout,
+    HeartbeatTimeout,
+    RequestVote(RequestVoteRequest),
+    RequestVoteResponse(RequestVoteResponse),
+    AppendEntries(AppendEntriesRequest),
+    AppendEntriesResponse(AppendEntriesResponse),
+    ReadIndex(ReadIndexRequest),
+    ReadIndexResponse(ReadIndexResponse),
+}
+
+mod sent {
+    use serde::de::Error;
+
+    use super::*;
+
+    pub fn serialize<S: Serializer>(action: &RaftAction, serializer: S) -> Result<S::Ok, S::Error> {
+        let message = match action.clone() {
+            RaftAction::SendRequestVote(_, request) => Message::RequestVote(request),
+            RaftAction::SendRequestVoteResponse(_, response) => {
+                Message::RequestVoteResponse(response)
+            }
+            RaftAction::SendAppendEntries(_, request) => Message::AppendEntries(request),
+            RaftAction::SendAppendEntriesResponse(_, response) => {
+                Message::AppendEntriesResponse(response)
+            }
+            RaftAction::SendReadIndex(_, request) => Message::ReadIndex(request),
+            RaftAction::SendReadIndexResponse(_, response) => Message::ReadIndexResponse(response),
+        };
+        Sent {
+            to: action.target(),
+            message,
+        }
+        .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<RaftAction, D::Error> {
+        let Sent { to, message } = Sent::deserialize(deserializer)?;
+        Ok(match message {
+            Message::RequestVote(request) => RaftAction::SendRequestVote(to, request),
+            Message::RequestVoteResponse(response) => {
+                RaftAction::SendRequestVoteResponse(to, response)
+            }
+            Message::AppendEntries(request) => RaftAction::SendAppendEntries(to, request),
+            Message::AppendEntriesResponse(response) => {
+                RaftAction::SendAppendEntriesResponse(to, response)
+            }
+            Message::ReadIndex(request) => RaftAction::SendReadIndex(to, request),
+            Message::ReadIndexResponse(response) => RaftAction::SendReadIndexResponse(to, response),
+            Message::ElectionTimeout | Message::HeartbeatTimeout => {
+                return Err(D::Error::custom("timeouts are not sent"));
+            }
+        })
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Sent {
+        to: NodeId,
+        message: Message,
+    }
+}
+
+// Only responses say who they are from.
+mod received {
+    use serde::de::Error;
+
+    use super::*;
+
+    pub fn serialize<S: Serializer>(event: &RaftEvent, serializer: S) -> Result<S::Ok, S::Error> {
+        let (from, message) = match event.clone() {
+            RaftEvent::ElectionTimeout => (None, Message::ElectionTimeout),
+            RaftEvent::HeartbeatTimeout => (None, Message::HeartbeatTimeout),
+            RaftEvent::ReceivedRequestVote(request) => (None, Message::RequestVote(request)),
+            RaftEvent::ReceivedRequestVoteResponse(from, response) => {
+                (Some(from), Message::RequestVoteResponse(response))
+            }
+            RaftEvent::ReceivedAppendEntries(request) => (None, Message::AppendEntries(request)),
+            RaftEvent::ReceivedAppendEntriesResponse(from, response) => {
+                (Some(from), Message::AppendEntriesResponse(response))
+            }
+            RaftEvent::ReceivedReadIndex(request) => (None, Message::ReadIndex(request)),
+            RaftEvent::ReceivedReadIndexResponse(response) => {
+                (None, Message::ReadIndexResponse(response))
+            }
+        };
+        Received { from, message }.serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<RaftEvent, D::Error> {
+        let Received { from, message } = Received::deserialize(deserializer)?;
+        let from = || from.ok_or_else(|| D::Error::custom("response is missing `from`"));
+        Ok(match message {
+            Message::ElectionTimeout => RaftEvent::ElectionTimeout,
+            Message::HeartbeatTimeout => RaftEvent::HeartbeatTimeout,
+            Message::RequestVote(request) => RaftEvent::ReceivedRequestVote(request),
+            Message::RequestVoteResponse(response) => {
+                RaftEvent::ReceivedRequestVoteResponse(from()?, response)
+            }
+            Message::AppendEntries(request) => RaftEvent::ReceivedAppendEntries(request),
+            Message::AppendEntriesResponse(response) => {
+                RaftEvent::ReceivedAppendEntriesResponse(from()?, response)
+            }
+            Message::ReadIndex(request) => RaftEvent::ReceivedReadIndex(request),
+            Message::ReadIndexResponse(response) => RaftEvent::ReceivedReadIndexResponse(response),
+        })
+    }
+
+    #[derive(Serialize, Deserialize)]
+    struct Received {
+        from: Option<NodeId>,
+        message: Message,
+    }
+}
@@ -0,0 +1,68 @@
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
+
+use tokio::sync::mpsc;
+
+use crate::event::RaftEvent;
+use crate::raft::RaftAction;
+use crate::types::NodeId;
+
+// How a node exchanges messages with its peers. `send` hands an outgoing
+// message to the transport and never blocks; messages that cannot be
+// delivered are dropped, as Raft tolerates. `recv` yields incoming requests
+// and the replies to this node's own requests, already addressed as events.
+pub trait Transport: Send + 'static {
+    fn send(&mut self, action: RaftAction);
+
+    // `None` once the transport is shut down.
+    fn recv(&mut self) -> impl Future<Output = Option<RaftEvent>> + Send;
+}
+
+// In-process transport over tokio channels, for running several nodes in one
+// process on real time.
+pub struct ChannelTransport {
+    id: NodeId,
+    inbox: mpsc::UnboundedReceiver<RaftEvent>,
+    peers: HashMap<NodeId, mpsc::UnboundedSender<RaftEvent>>,
+}
+
+impl ChannelTransport {
+    // One connected transport per node.
+    pub fn cluster(ids: &[NodeId]) -> BTreeMap<NodeId, ChannelTransport> {
+        let (senders, receivers): (HashMap<_, _>, Vec<_>) = ids
+            .iter()
+            .map(|&id| {
+                let (tx, rx) = mpsc::unbounded_channel();
+                ((id, tx), (id, rx))
+            })
+            .unzip();
+
+        receivers
+            .into_iter()
+            .map(|(id, inbox)| {
+                let transport = ChannelTransport {
+                    id,
+                    inbox,
+                    peers: senders.clone(),
+                };
+                (id, transport)
+            })
+            .collect()
+    }
+
+    pub fn id(&self) -> NodeId {
+        self.id
+    }
+}
+
+impl Transport for ChannelTransport {
+    fn send(&mut self, action: RaftAction) {
+        if let Some(peer) = self.peers.get(&action.target()) {
+            let _ = peer.send(action.into_event(self.id));
+        }
+    }
+
+    fn recv(&mut self) -> impl Future<Output = Option<RaftEvent>> + Send {
+        self.inbox.recv()
+    }
+}
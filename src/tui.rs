@@ -0,0 +1,337 @@
+use std::fmt::Write;
+use std::time::Duration;
+
+use crate::fuzz::Driver;
+use crate::node::RaftNode;
+use crate::raft::RaftAction;
+use crate::simulator::TICK_INTERVAL;
+use crate::types::{NodeId, RaftState};
+
+// Log entries shown per node; older ones scroll off to the left.
+const LOG_WIDTH: usize = 16;
+const MAX_MESSAGES: usize = 12;
+const SPEEDS: [Duration; 5] = [
+    Duration::from_millis(1),
+    Duration::from_millis(5),
+    Duration::from_millis(20),
+    Duration::from_millis(50),
+    Duration::from_millis(200),
+];
+
+const RESET: &str = "\x1b[0m";
+const BOLD: &str = "\x1b[1m";
+const DIM: &str = "\x1b[2m";
+const RED: &str = "\x1b[31m";
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const CYAN: &str = "\x1b[36m";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Key {
+    Char(char),
+    Enter,
+    Backspace,
+    Escape,
+}
+
+// State of the simulator UI, independent of the terminal: the binary feeds
+// it keys and draws what `render` returns.
+pub struct App {
+    driver: Driver,
+    paused: bool,
+    // Index into `SPEEDS`: simulated time advanced per frame.
+    speed: usize,
+    selected: NodeId,
+    // Text being typed after `x`, until Enter or Escape.
+    input: Option<String>,
+    next_write: u64,
+    status: String,
+    quit: bool,
+}
+
+impl App {
+    pub fn new(nodes: u64, seed: u64) -> Self {
+        Self {
+            driver: Driver::new(nodes, seed),
+            paused: true,
+            speed: 2,
+            selected: NodeId::new(1),
+            input: None,
+            next_write: 1,
+            status: format!("seed {}, paused: press space to run", seed),
+            quit: false,
+        }
+    }
+
+    pub fn driver(&self) -> &Driver {
+        &self.driver
+    }
+
+    pub fn should_quit(&self) -> bool {
+        self.quit
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    // Called once per frame; runs the simulation unless paused.
+    pub fn frame(&mut self) {
+        if !self.paused {
+            let speed = SPEEDS[self.speed];
+            self.advance(speed);
+        }
+    }
+
+    pub fn handle_key(&mut self, key: Key) {
+        if let Some(input) = &mut self.input {
+            match key {
+                Key::Char(c) => input.push(c),
+                Key::Backspace => {
+                    input.pop();
+                }
+                Key::Escape => self.input = None,
+                Key::Enter => {
+                    let command = self.input.take().unwrap();
+                    self.propose(command);
+                }
+            }
+            return;
+        }
+
+        let id = self.selected;
+        let sim = self.driver.sim_mut();
+        match key {
+            Key::Char('q') => self.quit = true,
+            Key::Char(' ') => self.paused = !self.paused,
+            Key::Char('.') | Key::Char('s') => {
+                self.paused = true;
+                self.advance(TICK_INTERVAL);
+            }
+            Key::Char('+') => self.speed = (self.speed + 1).min(SPEEDS.len() - 1),
+            Key::Char('-') => self.speed = self.speed.saturating_sub(1),
+            Key::Char(c @ '1'..='9') => {
+                let id = NodeId::new(c as u64 - '0' as u64);
+                if sim.node_ids().contains(&id) {
+                    self.selected = id;
+                }
+            }
+            Key::Char('i') => {
+                sim.isolate(id);
+                self.status = format!("isolated node {}", id.get());
+            }
+            Key::Char('h') => {
+                sim.heal();
+                self.status = "healed the network".to_string();
+            }
+            Key::Char('c') => {
+                sim.crash(id);
+                self.status = format!("crashed node {}", id.get());
+            }
+            Key::Char('p') => {
+                sim.crash_losing_unsynced(id);
+                self.status = format!("power-failed node {}", id.get());
+            }
+            Key::Char('r') => {
+                sim.restart(id);
+                self.status = format!("restarted node {}", id.get());
+            }
+            Key::Char('x') => self.input = Some(format!("k{}=v{}", self.next_write % 3, self.next_write)),
+            _ => {}
+        }
+    }
+
+    fn advance(&mut self, duration: Duration) {
+        if let Err(failure) = self.driver.advance(duration) {
+            self.paused = true;
+            self.status = format!("{}{}{}", RED, failure, RESET);
+        }
+    }
+
+    fn propose(&mut self, command: String) {
+        self.status = match self.driver.propose(command.as_bytes()) {
+            Some(leader) => {
+                self.next_write += 1;
+                format!("proposed {:?} to node {}", command, leader.get())
+            }
+            None => format!("no leader to propose {:?} to", command),
+        };
+    }
+
+    pub fn render(&self) -> String {
+        let sim = self.driver.sim();
+        let mut out = String::new();
+
+        let speed = if self.paused {
+            "paused".to_string()
+        } else {
+            format!("{:?}/frame", SPEEDS[self.speed])
+        };
+        let _ = writeln!(
+            out,
+            "{}mini-raft{}  t={:.3}s  seed {}  [{}]\n",
+            BOLD,
+            RESET,
+            sim.elapsed().as_secs_f64(),
+            sim.seed(),
+            speed
+        );
+
+        let _ = writeln!(
+            out,
+            "{}   node  state      term  commit  applied  log (term per entry){}",
+            DIM, RESET
+        );
+        for id in sim.node_ids() {
+            let marker = if id == self.selected { ">" } else { " " };
+            let line = match sim.node(id) {
+                Some(node) => format!(
+                    "{:<9}  {:>4}  {:>6}  {:>7}  {}",
+                    state_label(node.state),
+                    node.current_term.get(),
+                    node.commit_index.get(),
+                    node.last_applied.get(),
+                    log_line(node)
+                ),
+                None => format!("{}crashed{}", RED, RESET),
+            };
+            let _ = writeln!(out, " {} {:>4}  {}", marker, id.get(), line);
+        }
+        let _ = writeln!(
+            out,
+            "{}   log: {}applied{}{} {}committed{}{} uncommitted{}",
+            DIM, GREEN, RESET, DIM, YELLOW, RESET, DIM, RESET
+        );
+
+        if let Some(leader) = sim.find_leader().and_then(|id| sim.node(id)) {
+            let _ = writeln!(out, "\nleader {} replication:", leader.id.get());
+            let mut peers = leader.peers.clone();
+            peers.sort();
+            for peer in peers {
+                let _ = writeln!(
+                    out,
+                    "  -> {}  next {:>4}  match {:>4}",
+                    peer.get(),
+                    leader.next_index.get(&peer).map_or(0, |i| i.get()),
+                    leader.match_index.get(&peer).map_or(0, |i| i.get())
+                );
+            }
+        } else {
+            let _ = writeln!(out, "\nno leader");
+        }
+
+        let network = sim.network();
+        let mut blocked = Vec::new();
+        for from in sim.node_ids() {
+            for to in sim.node_ids() {
+                if from < to && network.is_blocked(from, to) {
+                    blocked.push(format!("{}-{}", from.get(), to.get()));
+                }
+            }
+        }
+        if !blocked.is_empty() {
+            let _ = writeln!(out, "{}cut links: {}{}", RED, blocked.join(" "), RESET);
+        }
+
+        let mut messages: Vec<_> = network.in_flight().collect();
+        messages.sort_by_key(|message| message.deliver_at);
+        let _ = writeln!(out, "\nin flight ({}):", messages.len());
+        for message in messages.iter().take(MAX_MESSAGES) {
+            let _ = writeln!(
+                out,
+                "  {} -> {}  {:<40} {}in {:?}{}",
+                message.from.get(),
+                message.action.target().get(),
+                describe(&message.action),
+                DIM,
+                message.deliver_at.saturating_sub(sim.elapsed()),
+                RESET
+            );
+        }
+        if messages.len() > MAX_MESSAGES {
+            let _ = writeln!(out, "  ... {} more", messages.len() - MAX_MESSAGES);
+        }
+
+        let _ = writeln!(out, "\n{}", self.status);
+        match &self.input {
+            Some(input) => {
+                let _ = writeln!(out, "propose: {}_  (enter to send, esc to cancel)", input);
+            }
+            None => {
+                let _ = writeln!(
+                    out,
+                    "{}space run/pause  . step  +/- speed  1-9 select  x propose  i isolate  h heal  c crash  p power-fail  r restart  q quit{}",
+                    DIM, RESET
+                );
+            }
+        }
+        out
+    }
+}
+
+fn state_label(state: RaftState) -> String {
+    match state {
+        RaftState::Leader => format!("{}{}Leader{}   ", BOLD, CYAN, RESET),
+        RaftState::Candidate => format!("{}Candidate{}", YELLOW, RESET),
+        RaftState::Follower => "Follower ".to_string(),
+    }
+}
+
+fn log_line(node: &RaftNode) -> String {
+    let entries = node.log.entries();
+    let skipped = entries.len().saturating_sub(LOG_WIDTH);
+
+    let mut out = String::new();
+    if skipped > 0 {
+        let _ = write!(out, "{}..{} ", DIM, RESET);
+    }
+    for entry in &entries[skipped..] {
+        let color = if entry.index <= node.last_applied {
+            GREEN
+        } else if entry.index <= node.commit_index {
+            YELLOW
+        } else {
+            DIM
+        };
+        let _ = write!(out, "{}{}{} ", color, entry.term.get(), RESET);
+    }
+    out
+}
+
+fn describe(action: &RaftAction) -> String {
+    match action {
+        RaftAction::SendRequestVote(_, request) => format!(
+            "RequestVote t{} last {}@t{}",
+            request.term.get(),
+            request.last_log_index.get(),
+            request.last_log_term.get()
+        ),
+        RaftAction::SendRequestVoteResponse(_, response) => format!(
+            "VoteResponse t{} {}",
+            response.term.get(),
+            if response.vote_granted { "granted" } else { "denied" }
+        ),
+        RaftAction::SendAppendEntries(_, request) => format!(
+            "AppendEntries t{} prev {} +{} commit {}",
+            request.term.get(),
+            request.prev_log_index.get(),
+            request.entries.len(),
+            request.leader_commit.get()
+        ),
+        RaftAction::SendAppendEntriesResponse(_, response) => format!(
+            "AppendEntriesResponse t{} {} match {}",
+            response.term.get(),
+            if response.success { "ok" } else { "rejected" },
+            response.match_index.get()
+        ),
+        RaftAction::SendReadIndex(_, request) => {
+            format!("ReadIndex t{} #{}", request.term.get(), request.request_id)
+        }
+        RaftAction::SendReadIndexResponse(_, response) => format!(
+            "ReadIndexResponse t{} #{} index {}",
+            response.term.get(),
+            response.request_id,
+            response.read_index.get()
+        ),
+    }
+}
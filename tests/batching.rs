@@ -0,0 +1,250 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+
+use mini_raft::clock::ManualClock;
+use mini_raft::config::RaftConfig;
+use mini_raft::log::{LogEntry, LogOffset};
+use mini_raft::node::RaftNode;
+use mini_raft::raft::{RaftAction, RaftRunner};
+use mini_raft::session::ProposeOutcome;
+use mini_raft::simulator::Simulator;
+use mini_raft::spec;
+use mini_raft::state_machine::KvStore;
+use mini_raft::storage::{HardState, MemStorage, Storage};
+use mini_raft::trace::{self, TraceEvent};
+use mini_raft::types::{LogIndex, NodeId};
+
+// Counts the appends and syncs that reach the storage underneath.
+#[derive(Clone, Default)]
+struct CountingStorage {
+    inner: Arc<Mutex<MemStorage>>,
+    appends: Arc<AtomicUsize>,
+    syncs: Arc<AtomicUsize>,
+}
+
+impl Storage for CountingStorage {
+    fn save_hard_state(&mut self, state: HardState) {
+        self.inner.lock().unwrap().save_hard_state(state);
+    }
+
+    fn truncate(&mut self, from: LogIndex) {
+        self.inner.lock().unwrap().truncate(from);
+    }
+
+    fn append(&mut self, entries: &[LogEntry]) {
+        self.appends.fetch_add(1, Ordering::SeqCst);
+        self.inner.lock().unwrap().append(entries);
+    }
+
+    fn compact(&mut self, offset: LogOffset) {
+        self.inner.lock().unwrap().compact(offset);
+    }
+
+    fn sync(&mut self) {
+        self.syncs.fetch_add(1, Ordering::SeqCst);
+        self.inner.lock().unwrap().sync();
+    }
+
+    fn load(&self) -> (HardState, LogOffset, Vec<LogEntry>) {
+        self.inner.lock().unwrap().load()
+    }
+}
+
+// A single-node cluster elects itself, so proposals are all that is left
+// to write.
+#[test]
+fn concurrent_proposals_share_one_append_and_one_sync() {
+    let config = RaftConfig {
+        max_batch_size: 64,
+        max_batch_delay: Duration::from_millis(20),
+        ..RaftConfig::default()
+    };
+    let clock = Arc::new(ManualClock::new());
+    let node = RaftNode::with_clock_and_rng(
+        NodeId::new(1),
+        vec![],
+        config,
+        clock.clone(),
+        StdRng::seed_from_u64(1),
+    );
+    let storage = CountingStorage::default();
+    let mut runner =
+        RaftRunner::with_storage(node, Box::new(KvStore::new()), Box::new(storage.clone()));
+
+    while !runner.node().is_leader() {
+        clock.advance(Duration::from_millis(5));
+        runner.tick();
+    }
+    let (appends, syncs) = (
+        storage.appends.load(Ordering::SeqCst),
+        storage.syncs.load(Ordering::SeqCst),
+    );
+    let last = runner.node().log.last_log_index();
+
+    for sequence in 1..=10 {
+        let command = format!("k{}=v", sequence).into_bytes();
+        assert_eq!(
+            runner.propose(1, sequence, command),
+            Some(ProposeOutcome::Queued)
+        );
+    }
+    let batch_end = LogIndex::new(last.get() + 10);
+
+    // The batch waits for more proposals until its delay is up.
+    clock.advance(Duration::from_millis(10));
+    runner.tick();
+    assert_eq!(runner.node().log.last_log_index(), last);
+    assert_eq!(runner.node().proposals.len(), 10);
+
+    clock.advance(Duration::from_millis(10));
+    runner.tick();
+    assert_eq!(runner.node().log.last_log_index(), batch_end);
+    assert_eq!(storage.appends.load(Ordering::SeqCst), appends + 1);
+
+    // The leader counts itself towards the commit once the batch is synced.
+    assert_eq!(runner.node().commit_index, last);
+    runner.sync();
+    assert_eq!(storage.syncs.load(Ordering::SeqCst), syncs + 1);
+    assert_eq!(runner.node().commit_index, batch_end);
+    runner.tick();
+
+    let results = runner.take_command_results();
+    let sequences: Vec<_> = results.iter().map(|result| result.sequence).collect();
+    assert_eq!(sequences, (1..=10).collect::<Vec<_>>());
+}
+
+#[test]
+fn full_batches_are_replicated_together() {
+    let config = RaftConfig {
+        max_batch_size: 4,
+        max_batch_delay: Duration::from_millis(30),
+        ..RaftConfig::default()
+    };
+    let mut sim = Simulator::with_config((1..=3).map(NodeId::new).collect(), config, 5);
+    let leader = loop {
+        sim.tick();
+        if let Some(leader) = sim.find_leader()
+            && sim.node(leader).unwrap().has_committed_in_current_term()
+        {
+            break leader;
+        }
+    };
+
+    let runner = sim.runner_mut(leader).unwrap();
+    for sequence in 1..=10 {
+        let command = format!("k{}=v", sequence).into_bytes();
+        assert_eq!(
+            runner.propose(1, sequence, command),
+            Some(ProposeOutcome::Queued)
+        );
+    }
+    sim.advance(Duration::from_millis(100));
+
+    // Two full batches are cut at once, the rest after the delay. Each one
+    // leaves in the tick it is appended in, to every follower.
+    let mut batches: Vec<(Vec<LogIndex>, usize)> = Vec::new();
+    for event in sim.node_trace(leader) {
+        match event {
+            TraceEvent::Tick { .. } => batches.push((Vec::new(), 0)),
+            TraceEvent::Appended { index, .. } => batches.last_mut().unwrap().0.push(index),
+            TraceEvent::Sent {
+                action: RaftAction::SendAppendEntries(_, request),
+                ..
+            } => {
+                let (appended, sent) = batches.last_mut().unwrap();
+                if appended.last().is_some_and(|last| {
+                    request.entries.last().map(|entry| entry.index) == Some(*last)
+                }) {
+                    *sent += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+    batches.retain(|(appended, _)| !appended.is_empty());
+    let sizes: Vec<_> = batches.iter().map(|(appended, _)| appended.len()).collect();
+    assert_eq!(sizes, [4, 4, 2]);
+    assert!(batches.iter().all(|&(_, sent)| sent == 2));
+
+    let commit = sim.node(leader).unwrap().commit_index;
+    for id in sim.node_ids() {
+        assert_eq!(sim.node(id).unwrap().commit_index, commit);
+        assert_eq!(trace::replay(&sim.node_trace(id)), Ok(()));
+    }
+    assert_eq!(spec::check(&sim.cluster_trace()), Ok(()));
+}
+
+// Queued proposals were never appended, so a leader that steps down drops
+// them and the client retries with whoever leads next.
+#[test]
+fn stepping_down_drops_queued_proposals() {
+    let config = RaftConfig {
+        max_batch_size: 64,
+        max_batch_delay: Duration::from_secs(3600),
+        ..RaftConfig::default()
+    };
+    let mut sim = Simulator::with_config((1..=3).map(NodeId::new).collect(), config, 7);
+    let leader = loop {
+        sim.tick();
+        if let Some(leader) = sim.find_leader()
+            && sim.node(leader).unwrap().has_committed_in_current_term()
+        {
+            break leader;
+        }
+    };
+    let last = sim.node(leader).unwrap().log.last_log_index();
+
+    let runner = sim.runner_mut(leader).unwrap();
+    for sequence in 1..=3 {
+        let command = format!("k{}=lost", sequence).into_bytes();
+        assert_eq!(
+            runner.propose(1, sequence, command),
+            Some(ProposeOutcome::Queued)
+        );
+    }
+    assert_eq!(runner.node().proposals.len(), 3);
+
+    // CheckQuorum makes the isolated leader step down on its own.
+    sim.isolate(leader);
+    while sim.node(leader).unwrap().is_leader() {
+        sim.tick();
+    }
+    let old = sim.node(leader).unwrap();
+    assert!(old.proposals.is_empty());
+    assert_eq!(old.log.last_log_index(), last);
+
+    sim.heal();
+    let new_leader = loop {
+        sim.tick();
+        if let Some(id) = sim.find_leader()
+            && sim.node(id).unwrap().has_committed_in_current_term()
+        {
+            break id;
+        }
+    };
+    sim.advance(Duration::from_millis(500));
+    for id in sim.node_ids() {
+        let node = sim.node(id).unwrap();
+        assert!(node.proposals.is_empty());
+        for index in 1..=node.log.last_log_index().get() {
+            let entry = node.log.get(LogIndex::new(index)).unwrap();
+            assert!(!entry.command.ends_with(b"=lost"));
+        }
+    }
+    assert!(
+        sim.runner_mut(leader)
+            .unwrap()
+            .take_command_results()
+            .is_empty()
+    );
+    assert!(
+        sim.runner_mut(new_leader)
+            .unwrap()
+            .take_command_results()
+            .is_empty()
+    );
+}
@@ -0,0 +1,90 @@
+mod common;
+
+use std::time::Duration;
+
+use mini_raft::config::RaftConfig;
+use mini_raft::node::RaftNode;
+use mini_raft::rpc::{AppendEntriesRequest, RequestVoteRequest};
+use mini_raft::simulator::Simulator;
+use mini_raft::timer::min_election_timeout;
+use mini_raft::types::{LogIndex, NodeId, Term};
+
+use common::elect;
+
+fn simulator(check_quorum: bool, seed: u64) -> Simulator {
+    let config = RaftConfig {
+        check_quorum,
+        ..RaftConfig::default()
+    };
+    Simulator::with_config((1..=3).map(NodeId::new).collect(), config, seed)
+}
+
+// A leader cut off from every follower steps down within two election
+// timeouts, keeping its term, while the others elect a new leader.
+#[test]
+fn isolated_leader_steps_down() {
+    let mut sim = simulator(true, 1);
+    let leader = elect(&mut sim);
+    let term = sim.node(leader).unwrap().current_term;
+
+    sim.isolate(leader);
+    sim.advance(min_election_timeout() * 2);
+    let node = sim.node(leader).unwrap();
+    assert!(!node.is_leader());
+    assert_eq!(node.current_term, term);
+
+    sim.advance(Duration::from_millis(500));
+    let new_leader = sim.find_leader_except(leader).expect("no new leader");
+    assert!(sim.node(new_leader).unwrap().current_term > term);
+}
+
+// One follower down still leaves a quorum, so the leader keeps leading.
+#[test]
+fn leader_with_a_quorum_stays() {
+    let mut sim = simulator(true, 2);
+    let leader = elect(&mut sim);
+    let term = sim.node(leader).unwrap().current_term;
+    let follower = sim.node_ids().into_iter().find(|&id| id != leader).unwrap();
+
+    sim.crash(follower);
+    sim.advance(min_election_timeout() * 5);
+    let node = sim.node(leader).unwrap();
+    assert!(node.is_leader());
+    assert_eq!(node.current_term, term);
+}
+
+// Without CheckQuorum an isolated leader never finds out.
+#[test]
+fn isolated_leader_stays_without_check_quorum() {
+    let mut sim = simulator(false, 1);
+    let leader = elect(&mut sim);
+
+    sim.isolate(leader);
+    sim.advance(min_election_timeout() * 5);
+    assert!(sim.node(leader).unwrap().is_leader());
+}
+
+// A follower that just heard from its leader ignores a candidate with a
+// higher term, such as a node rejoining after a partition.
+#[test]
+fn follower_with_a_live_leader_ignores_vote_requests() {
+    let mut node = RaftNode::new(NodeId::new(2), vec![NodeId::new(1), NodeId::new(3)]);
+    node.handle_append_entries(AppendEntriesRequest {
+        term: Term::new(1),
+        leader_id: NodeId::new(1),
+        prev_log_index: LogIndex::ZERO,
+        prev_log_term: Term::ZERO,
+        entries: Vec::new(),
+        leader_commit: LogIndex::ZERO,
+        heartbeat_round: 0,
+    });
+
+    let response = node.handle_request_vote(RequestVoteRequest {
+        term: Term::new(5),
+        candidate_id: NodeId::new(3),
+        last_log_index: LogIndex::new(10),
+        last_log_term: Term::new(4),
+    });
+    assert!(!response.vote_granted);
+    assert_eq!(node.current_term, Term::new(1));
+}
@@ -0,0 +1,277 @@
+use std::collections::BTreeMap;
+
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use tonic::transport::Endpoint;
+use tonic::{Code, Request, Status};
+
+use mini_raft::codec::DecodeError;
+use mini_raft::grpc::{GrpcTransport, Listener};
+use mini_raft::handshake::Handshake;
+use mini_raft::host::NodeHost;
+use mini_raft::log::LogEntry;
+use mini_raft::node::RaftNode;
+use mini_raft::raft::RaftRunner;
+use mini_raft::raft_proto::{self, raft_client::RaftClient};
+use mini_raft::rpc;
+use mini_raft::types::{LogIndex, NodeId, Term};
+
+const CASES: u64 = 2_000;
+
+// A log position a message of term `term` may point at.
+fn position(rng: &mut StdRng, term: u64) -> (LogIndex, Term) {
+    let index = rng.random_range(0..50);
+    let entry_term = if index == 0 {
+        0
+    } else {
+        rng.random_range(1..=term)
+    };
+    (LogIndex::new(index), Term::new(entry_term))
+}
+
+fn request_vote(rng: &mut StdRng) -> rpc::RequestVoteRequest {
+    let term = rng.random_range(1..100);
+    let (last_log_index, last_log_term) = position(rng, term);
+    rpc::RequestVoteRequest {
+        term: Term::new(term),
+        candidate_id: NodeId::new(rng.random_range(1..10)),
+        last_log_index,
+        last_log_term,
+    }
+}
+
+fn append_entries(rng: &mut StdRng) -> rpc::AppendEntriesRequest {
+    let term = rng.random_range(1..100);
+    let (prev_log_index, prev_log_term) = position(rng, term);
+    let mut entry_term = prev_log_term.get().max(1);
+    let entries = (1..=rng.random_range(0..5))
+        .map(|offset| {
+            entry_term = rng.random_range(entry_term..=term);
+            let command = (0..rng.random_range(0..8)).map(|_| rng.random()).collect();
+            LogEntry {
+                term: Term::new(entry_term),
+                index: LogIndex::new(prev_log_index.get() + offset),
+                command,
+            }
+        })
+        .collect();
+
+    rpc::AppendEntriesRequest {
+        term: Term::new(term),
+        leader_id: NodeId::new(rng.random_range(1..10)),
+        prev_log_index,
+        prev_log_term,
+        entries,
+        leader_commit: LogIndex::new(rng.random_range(0..50)),
+        heartbeat_round: rng.random(),
+    }
+}
+
+fn read_index(rng: &mut StdRng) -> rpc::ReadIndexRequest {
+    rpc::ReadIndexRequest {
+        term: Term::new(rng.random_range(0..100)),
+        follower_id: NodeId::new(rng.random_range(1..10)),
+        request_id: rng.random(),
+    }
+}
+
+// Encoding then decoding gives back the message, and decoding its encoding
+// gives back the wire form.
+fn round_trips<T, P>(message: T)
+where
+    T: Clone + PartialEq + std::fmt::Debug + Into<P> + TryFrom<P, Error = DecodeError>,
+    P: Clone + PartialEq + std::fmt::Debug + From<T>,
+{
+    let wire: P = message.clone().into();
+    let decoded = T::try_from(wire.clone()).unwrap_or_else(|e| panic!("{}: {:?}", e, message));
+    assert_eq!(decoded, message);
+    assert_eq!(P::from(decoded), wire);
+}
+
+#[test]
+fn requests_round_trip() {
+    let mut rng = StdRng::seed_from_u64(7);
+    for _ in 0..CASES {
+        round_trips::<_, raft_proto::RequestVoteRequest>(request_vote(&mut rng));
+        round_trips::<_, raft_proto::AppendEntriesRequest>(append_entries(&mut rng));
+        round_trips::<_, raft_proto::ReadIndexRequest>(read_index(&mut rng));
+    }
+}
+
+#[test]
+fn responses_round_trip() {
+    let mut rng = StdRng::seed_from_u64(11);
+    for _ in 0..CASES {
+        let term = Term::new(rng.random_range(1..100));
+        round_trips::<_, raft_proto::RequestVoteResponse>(rpc::RequestVoteResponse {
+            term,
+            vote_granted: rng.random(),
+        });
+        round_trips::<_, raft_proto::AppendEntriesResponse>(rpc::AppendEntriesResponse {
+            term,
+            success: rng.random(),
+            match_index: LogIndex::new(rng.random_range(0..50)),
+            heartbeat_round: rng.random(),
+        });
+        round_trips::<_, raft_proto::ReadIndexResponse>(rpc::ReadIndexResponse {
+            term,
+            request_id: rng.random(),
+            success: rng.random(),
+            read_index: LogIndex::new(rng.random_range(0..50)),
+        });
+    }
+}
+
+fn rejects<T, P>(wire: P, field: &str)
+where
+    T: TryFrom<P, Error = DecodeError> + std::fmt::Debug,
+{
+    let error = T::try_from(wire).expect_err("message should not decode");
+    assert_eq!(error.field, field, "{}", error);
+    assert_eq!(Status::from(error).code(), Code::InvalidArgument);
+}
+
+fn wire_entry(index: u64, term: u64) -> raft_proto::LogEntry {
+    raft_proto::LogEntry {
+        term,
+        index,
+        command: Vec::new(),
+    }
+}
+
+fn wire_append(entries: Vec<raft_proto::LogEntry>) -> raft_proto::AppendEntriesRequest {
+    raft_proto::AppendEntriesRequest {
+        term: 3,
+        leader_id: 1,
+        prev_log_index: 4,
+        prev_log_term: 2,
+        entries,
+        leader_commit: 4,
+        heartbeat_round: 0,
+    }
+}
+
+#[test]
+fn malformed_requests_are_rejected() {
+    let vote = raft_proto::RequestVoteRequest {
+        term: 2,
+        candidate_id: 1,
+        last_log_index: 3,
+        last_log_term: 1,
+    };
+    type Vote = rpc::RequestVoteRequest;
+    rejects::<Vote, _>(
+        raft_proto::RequestVoteRequest {
+            candidate_id: 0,
+            ..vote
+        },
+        "candidate_id",
+    );
+    rejects::<Vote, _>(raft_proto::RequestVoteRequest { term: 0, ..vote }, "term");
+    rejects::<Vote, _>(
+        raft_proto::RequestVoteRequest {
+            last_log_term: 3,
+            ..vote
+        },
+        "last_log_term",
+    );
+    rejects::<Vote, _>(
+        raft_proto::RequestVoteRequest {
+            last_log_index: 0,
+            ..vote
+        },
+        "last_log_term",
+    );
+    rejects::<Vote, _>(
+        raft_proto::RequestVoteRequest {
+            last_log_term: 0,
+            ..vote
+        },
+        "last_log_term",
+    );
+
+    type Append = rpc::AppendEntriesRequest;
+    rejects::<Append, _>(
+        raft_proto::AppendEntriesRequest {
+            leader_id: 0,
+            ..wire_append(vec![])
+        },
+        "leader_id",
+    );
+    rejects::<Append, _>(
+        raft_proto::AppendEntriesRequest {
+            prev_log_term: 4,
+            ..wire_append(vec![])
+        },
+        "prev_log_term",
+    );
+    // A gap, a repeated index, a term going back, one past the leader's,
+    // and an entry that is not an entry.
+    rejects::<Append, _>(wire_append(vec![wire_entry(6, 2)]), "entries");
+    rejects::<Append, _>(
+        wire_append(vec![wire_entry(5, 2), wire_entry(5, 2)]),
+        "entries",
+    );
+    rejects::<Append, _>(
+        wire_append(vec![wire_entry(5, 3), wire_entry(6, 2)]),
+        "entries",
+    );
+    rejects::<Append, _>(wire_append(vec![wire_entry(5, 1)]), "entries");
+    rejects::<Append, _>(wire_append(vec![wire_entry(5, 4)]), "entries");
+    rejects::<Append, _>(wire_append(vec![wire_entry(5, 0)]), "term");
+    // No entry fits after the largest index.
+    rejects::<Append, _>(
+        raft_proto::AppendEntriesRequest {
+            prev_log_index: u64::MAX,
+            ..wire_append(vec![wire_entry(u64::MAX, 2)])
+        },
+        "entries",
+    );
+    rpc::AppendEntriesRequest::try_from(wire_append(vec![wire_entry(5, 2), wire_entry(6, 3)]))
+        .unwrap();
+
+    rejects::<rpc::ReadIndexRequest, _>(
+        raft_proto::ReadIndexRequest {
+            term: 1,
+            follower_id: 0,
+            request_id: 1,
+        },
+        "follower_id",
+    );
+    rejects::<rpc::RequestVoteResponse, _>(
+        raft_proto::RequestVoteResponse {
+            term: 0,
+            vote_granted: true,
+        },
+        "term",
+    );
+}
+
+#[tokio::test]
+async fn server_answers_malformed_requests_with_invalid_argument() {
+    let listener = Listener::bind(&"127.0.0.1:0".parse().unwrap())
+        .await
+        .unwrap();
+    let addr = listener.local_addr().unwrap();
+    let transport = GrpcTransport::new(Handshake::new("test"), &BTreeMap::new());
+    tokio::spawn(transport.serve(listener));
+    tokio::spawn(
+        NodeHost::new(
+            RaftRunner::new(RaftNode::new(NodeId::new(1), vec![])),
+            transport,
+        )
+        .run(),
+    );
+
+    let endpoint = Endpoint::from_shared(format!("http://{}", addr)).unwrap();
+    let mut client = RaftClient::new(endpoint.connect_lazy());
+    let mut request = Request::new(wire_append(vec![wire_entry(7, 2)]));
+    Handshake::new("test").write(request.metadata_mut());
+
+    let status = client.append_entries(request).await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+    assert_eq!(
+        status.message(),
+        "invalid AppendEntriesRequest.entries: expected entry 5, got 7"
+    );
+}
@@ -0,0 +1,86 @@
+// Fixtures shared by the integration tests. Each test crate uses only some.
+#![allow(dead_code)]
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use mini_raft::host::NodeHost;
+use mini_raft::node::RaftNode;
+use mini_raft::raft::RaftRunner;
+use mini_raft::session::ProposeOutcome;
+use mini_raft::simulator::Simulator;
+use mini_raft::transport::Transport;
+use mini_raft::types::NodeId;
+
+pub type Runners = BTreeMap<NodeId, Arc<Mutex<RaftRunner>>>;
+
+// Ticks until a leader has committed an entry of its own term.
+pub fn elect(sim: &mut Simulator) -> NodeId {
+    for _ in 0..5_000 {
+        sim.tick();
+        if let Some(leader) = sim.find_leader()
+            && sim.node(leader).unwrap().has_committed_in_current_term()
+        {
+            return leader;
+        }
+    }
+    panic!("no leader was elected");
+}
+
+// Runs node `id` of the cluster `all` over `transport` in the background.
+pub fn start<T: Transport>(id: NodeId, all: &[NodeId], transport: T) -> Arc<Mutex<RaftRunner>> {
+    let peers = all.iter().copied().filter(|&peer| peer != id).collect();
+    let host = NodeHost::new(RaftRunner::new(RaftNode::new(id, peers)), transport);
+    let runner = host.runner();
+    tokio::spawn(host.run());
+    runner
+}
+
+pub async fn eventually<T>(mut check: impl FnMut() -> Option<T>) -> T {
+    for _ in 0..500 {
+        if let Some(value) = check() {
+            return value;
+        }
+        tokio::time::sleep(Duration::from_millis(10)).await;
+    }
+    panic!("cluster did not make progress in time");
+}
+
+// Elects a leader, replicates a proposal through it and waits for every
+// node to apply it. Returns the leader.
+pub async fn replicates_a_proposal(runners: &Runners) -> NodeId {
+    let leader = eventually(|| {
+        runners
+            .iter()
+            .find(|(_, runner)| runner.lock().unwrap().node().is_leader())
+            .map(|(&id, _)| id)
+    })
+    .await;
+
+    let outcome = runners[&leader]
+        .lock()
+        .unwrap()
+        .propose(1, 1, b"SET x 1".to_vec());
+    assert_eq!(outcome, Some(ProposeOutcome::Queued));
+
+    // The leader answers once it has applied the command.
+    let index = eventually(|| {
+        let mut runner = runners[&leader].lock().unwrap();
+        let answered = runner
+            .take_command_results()
+            .iter()
+            .any(|result| (result.client_id, result.sequence) == (1, 1));
+        answered.then(|| runner.node().last_applied)
+    })
+    .await;
+
+    eventually(|| {
+        runners
+            .values()
+            .all(|runner| runner.lock().unwrap().node().last_applied >= index)
+            .then_some(())
+    })
+    .await;
+    leader
+}
@@ -0,0 +1,105 @@
+mod common;
+
+use std::time::Duration;
+
+use mini_raft::event::RaftEvent;
+use mini_raft::raft::RaftAction;
+use mini_raft::read_index::{ReadConsistency, ReadError};
+use mini_raft::rpc::{ReadIndexRequest, ReadIndexResponse};
+use mini_raft::simulator::Simulator;
+use mini_raft::types::{LogIndex, NodeId, Term};
+
+use common::elect;
+
+fn cluster(seed: u64) -> (Simulator, NodeId, NodeId) {
+    let mut sim = Simulator::with_seed((1..=3).map(NodeId::new).collect(), seed);
+    let leader = elect(&mut sim);
+    let follower = sim.node_ids().into_iter().find(|&id| id != leader).unwrap();
+    (sim, leader, follower)
+}
+
+// The follower gets the leader's read index and answers once it has
+// applied that far itself.
+#[test]
+fn follower_read_sees_a_committed_write() {
+    let (mut sim, leader, follower) = cluster(3);
+    sim.runner_mut(leader)
+        .unwrap()
+        .propose(1, 1, b"x=1".to_vec())
+        .unwrap();
+    sim.advance(Duration::from_millis(50));
+
+    let id = sim
+        .runner_mut(follower)
+        .unwrap()
+        .read(b"x".to_vec(), ReadConsistency::LinearizableFollower)
+        .expect("follower should accept the read");
+    sim.advance(Duration::from_millis(20));
+    let results = sim.runner_mut(follower).unwrap().take_read_results();
+    assert_eq!(results, vec![(id, Ok(b"1".to_vec()))]);
+}
+
+// A read forwarded to a leader that is cut off fails once the follower
+// moves on to another leader.
+#[test]
+fn forwarded_read_fails_when_the_leader_changes() {
+    let (mut sim, leader, follower) = cluster(4);
+    sim.isolate(leader);
+    let id = sim
+        .runner_mut(follower)
+        .unwrap()
+        .read(b"x".to_vec(), ReadConsistency::LinearizableFollower)
+        .unwrap();
+
+    sim.advance(Duration::from_millis(1_000));
+    let results = sim.runner_mut(follower).unwrap().take_read_results();
+    assert_eq!(results, vec![(id, Err(ReadError::NotLeader))]);
+}
+
+// The leader refuses a read index to a follower of another term.
+#[test]
+fn leader_refuses_a_request_from_another_term() {
+    let (mut sim, leader, follower) = cluster(5);
+    let runner = sim.runner_mut(leader).unwrap();
+    let term = runner.node().current_term;
+
+    for other in [Term::new(term.get() - 1), Term::new(term.get() + 1)] {
+        runner.push_event(RaftEvent::ReceivedReadIndex(ReadIndexRequest {
+            term: other,
+            follower_id: follower,
+            request_id: 7,
+        }));
+        let refused = runner.tick().into_iter().any(|action| {
+            matches!(
+                action,
+                RaftAction::SendReadIndexResponse(to, response)
+                    if to == follower && response.request_id == 7 && !response.success
+            )
+        });
+        assert!(refused, "request in term {:?} was not refused", other);
+    }
+}
+
+// A follower does not trust a read index from a leader of another term.
+#[test]
+fn follower_ignores_a_response_from_another_term() {
+    let (mut sim, _, follower) = cluster(6);
+    let runner = sim.runner_mut(follower).unwrap();
+    let term = runner.node().current_term;
+    let id = runner
+        .read(b"x".to_vec(), ReadConsistency::LinearizableFollower)
+        .unwrap();
+    runner.tick();
+
+    runner.push_event(RaftEvent::ReceivedReadIndexResponse(ReadIndexResponse {
+        term: Term::new(term.get() - 1),
+        request_id: id,
+        success: true,
+        read_index: LogIndex::ZERO,
+    }));
+    runner.tick();
+    assert_eq!(
+        runner.take_read_results(),
+        vec![(id, Err(ReadError::NotLeader))]
+    );
+}
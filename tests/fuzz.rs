@@ -0,0 +1,139 @@
+use std::env;
+use std::fs;
+
+use mini_raft::fuzz::{self, Failure, Schedule, Step};
+use mini_raft::invariants::Violation;
+use mini_raft::types::{LogIndex, NodeId};
+
+const NODES: u64 = 3;
+const STEPS: usize = 20;
+
+fn env_u64(name: &str, default: u64) -> u64 {
+    env::var(name)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+fn check_seeds(default_seeds: u64, max_append_entries: Option<usize>) {
+    let start = env_u64("FUZZ_SEED_START", 0);
+    let seeds = env_u64("FUZZ_SEEDS", default_seeds);
+
+    for seed in start..start + seeds {
+        let mut schedule = Schedule::generate(seed, NODES, STEPS);
+        schedule.max_append_entries = max_append_entries;
+        if let Err((schedule, failure)) = fuzz::check(schedule) {
+            let dir = env::temp_dir().join("mini-raft-fuzz");
+            fs::create_dir_all(&dir).unwrap();
+            let path = dir.join(format!("seed-{}.scenario", seed));
+            fs::write(&path, schedule.to_string()).unwrap();
+
+            panic!(
+                "seed {}: {}\nshrunk schedule written to {} (replay with `cargo run --bin scenario -- {}`):\n{}",
+                seed,
+                failure,
+                path.display(),
+                path.display(),
+                schedule
+            );
+        }
+    }
+}
+
+// FUZZ_SEEDS and FUZZ_SEED_START widen the search, e.g. for overnight runs.
+#[test]
+fn random_schedules_stay_safe() {
+    check_seeds(1_000, None);
+}
+
+// Followers that fall behind catch up over many AppendEntries windows, some
+// of which arrive late or twice.
+#[test]
+fn random_schedules_stay_safe_with_small_append_windows() {
+    check_seeds(500, Some(2));
+}
+
+#[test]
+fn schedule_round_trips_through_text() {
+    let mut schedule = Schedule::generate(7, 5, 50);
+    schedule.max_append_entries = Some(3);
+    let text = schedule.to_string();
+
+    assert_eq!(Schedule::parse(&text), Ok(schedule.clone()));
+    assert_eq!(
+        fuzz::run(&schedule),
+        fuzz::run(&Schedule::parse(&text).unwrap())
+    );
+}
+
+#[test]
+fn any_proposed_bytes_round_trip_through_text() {
+    for command in [&b"say \"hi\"\\n"[..], b"\xff\x00\n\xc1", b""] {
+        let step = Step::Propose(command.to_vec());
+        assert_eq!(step.to_string().parse(), Ok(step));
+    }
+    assert_eq!(
+        "propose \"x=1\"".parse(),
+        Ok(Step::Propose(b"x=1".to_vec()))
+    );
+    assert!("propose 0x7".parse::<Step>().is_err());
+}
+
+#[test]
+fn client_and_membership_steps_round_trip_through_text() {
+    let steps = [
+        Step::Read(b"x".to_vec()),
+        Step::Retry,
+        Step::Add(NodeId::new(4)),
+        Step::Remove(NodeId::new(2)),
+    ];
+    for step in steps {
+        assert_eq!(step.to_string().parse(), Ok(step));
+    }
+    assert_eq!("read \"x\"".parse(), Ok(Step::Read(b"x".to_vec())));
+    assert!("add".parse::<Step>().is_err());
+}
+
+#[test]
+fn failures_of_the_same_kind_match_across_nodes_and_indexes() {
+    let commit_regressed = |node: u64, to: u64| {
+        Failure::Invariant(Violation::CommitRegressed {
+            node: NodeId::new(node),
+            from: LogIndex::new(to + 1),
+            to: LogIndex::new(to),
+        })
+    };
+    let state_machine = Failure::Invariant(Violation::StateMachineSafety {
+        node: NodeId::new(1),
+        index: LogIndex::new(3),
+    });
+
+    assert!(commit_regressed(1, 2).same_kind(&commit_regressed(3, 7)));
+    assert!(!commit_regressed(1, 2).same_kind(&state_machine));
+}
+
+#[test]
+fn shrinks_to_the_steps_that_matter() {
+    let mut schedule = Schedule::generate(3, 3, 40);
+    schedule.steps.insert(10, Step::Crash(NodeId::new(2)));
+    schedule.steps.insert(30, Step::PowerFail(NodeId::new(3)));
+
+    // Stand-in for a bug that needs node 2 down while node 3 loses power.
+    let fails = |candidate: &Schedule| {
+        let crash = candidate
+            .steps
+            .iter()
+            .position(|step| *step == Step::Crash(NodeId::new(2)));
+        let power_fail = candidate
+            .steps
+            .iter()
+            .position(|step| *step == Step::PowerFail(NodeId::new(3)));
+        matches!((crash, power_fail), (Some(crash), Some(power_fail)) if crash < power_fail)
+    };
+
+    let shrunk = fuzz::shrink(&schedule, fails);
+    assert_eq!(
+        shrunk.steps,
+        vec![Step::Crash(NodeId::new(2)), Step::PowerFail(NodeId::new(3))]
+    );
+}
@@ -0,0 +1,205 @@
+mod common;
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+use tonic::metadata::MetadataMap;
+use tonic::transport::Endpoint;
+use tonic::{Code, Request};
+
+use mini_raft::grpc::{GrpcTransport, Listener, PeerAddr, PeerFeatures};
+use mini_raft::handshake::{
+    Features, Handshake, HandshakeError, MIN_PROTOCOL_VERSION, Negotiated, PROTOCOL_VERSION,
+};
+use mini_raft::raft::RaftRunner;
+use mini_raft::raft_proto::RequestVoteRequest;
+use mini_raft::raft_proto::raft_client::RaftClient;
+use mini_raft::read_index::ReadConsistency;
+use mini_raft::types::NodeId;
+
+use common::{Runners, eventually, replicates_a_proposal, start};
+
+struct Node {
+    runner: Arc<Mutex<RaftRunner>>,
+    features: PeerFeatures,
+    addr: PeerAddr,
+}
+
+async fn cluster(handshakes: Vec<Handshake>) -> BTreeMap<NodeId, Node> {
+    let all: Vec<_> = (1..=handshakes.len() as u64).map(NodeId::new).collect();
+    let mut listeners = Vec::new();
+    let mut bound = BTreeMap::new();
+    for &id in &all {
+        let listener = Listener::bind(&"127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        bound.insert(id, listener.local_addr().unwrap());
+        listeners.push(listener);
+    }
+
+    let mut nodes = BTreeMap::new();
+    for ((&id, listener), handshake) in all.iter().zip(listeners).zip(handshakes) {
+        let mut peers = bound.clone();
+        peers.remove(&id);
+        let transport = GrpcTransport::new(handshake, &peers);
+        let features = transport.features();
+        tokio::spawn(transport.serve(listener));
+
+        let node = Node {
+            runner: start(id, &all, transport),
+            features,
+            addr: bound[&id].clone(),
+        };
+        nodes.insert(id, node);
+    }
+    nodes
+}
+
+fn vote_request(handshake: Option<Handshake>) -> Request<RequestVoteRequest> {
+    let mut request = Request::new(RequestVoteRequest {
+        term: 1,
+        candidate_id: 9,
+        last_log_index: 0,
+        last_log_term: 0,
+    });
+    if let Some(handshake) = handshake {
+        handshake.write(request.metadata_mut());
+    }
+    request
+}
+
+async fn client(addr: &PeerAddr) -> RaftClient<tonic::transport::Channel> {
+    let endpoint = Endpoint::from_shared(format!("http://{}", addr)).unwrap();
+    RaftClient::new(endpoint.connect_lazy())
+}
+
+#[tokio::test]
+async fn calls_from_another_cluster_are_refused() {
+    let nodes = cluster(vec![Handshake::new("blue")]).await;
+    let mut client = client(&nodes[&NodeId::new(1)].addr).await;
+
+    let status = client
+        .request_vote(vote_request(Some(Handshake::new("green"))))
+        .await
+        .unwrap_err();
+    assert_eq!(status.code(), Code::FailedPrecondition);
+    assert!(
+        status.message().contains("cluster id mismatch"),
+        "{}",
+        status.message()
+    );
+
+    let status = client.request_vote(vote_request(None)).await.unwrap_err();
+    assert_eq!(status.code(), Code::InvalidArgument);
+
+    let response = client
+        .request_vote(vote_request(Some(Handshake::new("blue"))))
+        .await
+        .unwrap();
+    let theirs = Handshake::read(response.metadata()).unwrap();
+    assert_eq!(theirs, Handshake::new("blue"));
+}
+
+#[test]
+fn too_old_protocol_versions_are_refused() {
+    let ours = Handshake::new("c");
+    let old = Handshake {
+        version: MIN_PROTOCOL_VERSION - 1,
+        ..Handshake::new("c")
+    };
+    assert_eq!(
+        ours.accept(&old),
+        Err(HandshakeError::UnsupportedVersion {
+            version: MIN_PROTOCOL_VERSION - 1,
+            min: MIN_PROTOCOL_VERSION,
+        })
+    );
+
+    // A newer peer is accepted and both speak this node's version: the
+    // newer one is the one to speak down.
+    let newer = Handshake {
+        version: ours.version + 1,
+        ..Handshake::new("c")
+    };
+    let negotiated = Negotiated {
+        version: ours.version,
+        features: Features::ALL,
+    };
+    assert_eq!(ours.accept(&newer), Ok(negotiated));
+    assert_eq!(newer.accept(&ours), Ok(negotiated));
+}
+
+#[test]
+fn handshake_round_trips_through_metadata() {
+    let handshake = Handshake::with_features("c", Features::READ_INDEX);
+    let mut metadata = MetadataMap::new();
+    handshake.write(&mut metadata);
+    assert_eq!(Handshake::read(&metadata), Ok(handshake));
+
+    // Features only newer builds know about are ignored.
+    metadata.insert("x-raft-features", "pre-vote,read-index".parse().unwrap());
+    assert_eq!(
+        Handshake::read(&metadata).unwrap().features,
+        Features::READ_INDEX
+    );
+
+    metadata.remove("x-raft-cluster-id");
+    assert_eq!(
+        Handshake::read(&metadata),
+        Err(HandshakeError::Missing("x-raft-cluster-id"))
+    );
+}
+
+// Node 3 runs a build without ReadIndex. The cluster still replicates, and
+// follower reads work exactly where both the follower and the leader
+// support them; elsewhere they fail fast instead of hanging.
+#[tokio::test]
+async fn mixed_feature_cluster_negotiates_follower_reads() {
+    let nodes = cluster(vec![
+        Handshake::new("c"),
+        Handshake::new("c"),
+        Handshake::with_features("c", Features::NONE),
+    ])
+    .await;
+    let old = NodeId::new(3);
+
+    let runners: Runners = nodes
+        .iter()
+        .map(|(&id, node)| (id, node.runner.clone()))
+        .collect();
+    let leader = replicates_a_proposal(&runners).await;
+
+    for (&id, node) in &nodes {
+        if id == leader {
+            continue;
+        }
+        let expected = if id == old || leader == old {
+            Features::NONE
+        } else {
+            Features::READ_INDEX
+        };
+        assert_eq!(node.features.get(leader), Some(expected));
+        assert_eq!(node.features.version(leader), Some(PROTOCOL_VERSION));
+
+        let read = node
+            .runner
+            .lock()
+            .unwrap()
+            .read(b"x".to_vec(), ReadConsistency::LinearizableFollower)
+            .expect("follower should accept the read");
+        let result = eventually(|| {
+            let results = node.runner.lock().unwrap().take_read_results();
+            results
+                .into_iter()
+                .find(|&(result_id, _)| result_id == read)
+                .map(|(_, result)| result)
+        })
+        .await;
+        assert_eq!(
+            result.is_ok(),
+            expected == Features::READ_INDEX,
+            "node {:?}",
+            id
+        );
+    }
+}
@@ -0,0 +1,166 @@
+mod common;
+
+use std::time::Duration;
+
+use mini_raft::config::RaftConfig;
+use mini_raft::log::LogEntry;
+use mini_raft::node::RaftNode;
+use mini_raft::raft::RaftAction;
+use mini_raft::rpc::AppendEntriesRequest;
+use mini_raft::session::{ProposeOutcome, SessionCommand};
+use mini_raft::simulator::Simulator;
+use mini_raft::spec;
+use mini_raft::storage::Storage;
+use mini_raft::trace::TraceEvent;
+use mini_raft::types::{LogIndex, NodeId, Term};
+
+use common::elect;
+
+// The leader's new entry leaves for the followers in the same pass it is
+// written in, before it is synced, and the leader's durable index holds its
+// own vote back until then.
+#[test]
+fn leader_sends_entries_before_syncing_them() {
+    let mut sim = Simulator::with_seed((1..=3).map(NodeId::new).collect(), 3);
+    let leader = elect(&mut sim);
+    sim.advance(Duration::from_millis(10));
+
+    let index = LogIndex::new(sim.node(leader).unwrap().log.last_log_index().get() + 1);
+    let outcome = sim
+        .runner_mut(leader)
+        .unwrap()
+        .propose(1, 1, b"x=1".to_vec());
+    assert_eq!(outcome, Some(ProposeOutcome::Queued));
+    sim.tick();
+
+    let node = sim.node(leader).unwrap();
+    assert_eq!(node.log.last_log_index(), index);
+    assert!(node.durable_index < index);
+    assert!(node.commit_index < index);
+    assert!(sim.storage(leader).unwrap().unsynced_writes() > 0);
+    let sent = sim.node_trace(leader).into_iter().rev().any(|event| {
+        matches!(
+            event,
+            TraceEvent::Sent {
+                action: RaftAction::SendAppendEntries(_, request),
+                ..
+            } if request.entries.iter().any(|entry| entry.index == index)
+        )
+    });
+    assert!(sent, "the entry was not sent before the leader synced it");
+
+    sim.tick();
+    assert_eq!(sim.node(leader).unwrap().durable_index, index);
+    assert_eq!(sim.storage(leader).unwrap().unsynced_writes(), 0);
+}
+
+// A leader that loses power before syncing loses the entry itself, but the
+// followers it already reached keep it, and the next leader commits it.
+#[test]
+fn entry_lost_by_the_leader_survives_on_followers() {
+    let mut sim = Simulator::with_seed((1..=3).map(NodeId::new).collect(), 8);
+    let leader = elect(&mut sim);
+    sim.advance(Duration::from_millis(10));
+
+    let index = LogIndex::new(sim.node(leader).unwrap().log.last_log_index().get() + 1);
+    let outcome = sim
+        .runner_mut(leader)
+        .unwrap()
+        .propose(1, 1, b"x=1".to_vec());
+    assert_eq!(outcome, Some(ProposeOutcome::Queued));
+    sim.tick();
+    sim.crash_losing_unsynced(leader);
+    let (_, _, entries) = sim.storage(leader).unwrap().load();
+    assert!(entries.iter().all(|entry| entry.index < index));
+    sim.advance(Duration::from_millis(1_000));
+
+    let new_leader = sim.find_leader().expect("the followers elect a new leader");
+    let node = sim.node(new_leader).unwrap();
+    assert!(node.commit_index >= index);
+    let entry = node.log.get(index).unwrap().into_owned();
+    let command = SessionCommand::decode(&entry.command).unwrap();
+    assert_eq!(command.command, b"x=1");
+
+    sim.restart(leader);
+    sim.advance(Duration::from_millis(500));
+    let restarted = sim.node(leader).unwrap();
+    assert_eq!(restarted.log.get(index).as_deref(), Some(&entry));
+    assert_eq!(spec::check(&sim.cluster_trace()), Ok(()));
+}
+
+// A follower that missed many entries gets them a window at a time, each
+// sent as soon as the last one is acknowledged.
+#[test]
+fn lagging_follower_catches_up_a_window_at_a_time() {
+    let config = RaftConfig {
+        max_append_entries: 4,
+        ..RaftConfig::default()
+    };
+    let mut sim = Simulator::with_config((1..=3).map(NodeId::new).collect(), config, 4);
+    let leader = elect(&mut sim);
+    let follower = sim.node_ids().into_iter().find(|&id| id != leader).unwrap();
+    sim.crash(follower);
+
+    let runner = sim.runner_mut(leader).unwrap();
+    for sequence in 1..=30 {
+        let command = format!("k{}=v", sequence).into_bytes();
+        assert_eq!(
+            runner.propose(1, sequence, command),
+            Some(ProposeOutcome::Queued)
+        );
+    }
+    sim.advance(Duration::from_millis(100));
+    let last = sim.node(leader).unwrap().log.last_log_index();
+    assert_eq!(sim.node(leader).unwrap().commit_index, last);
+
+    sim.restart(follower);
+    sim.advance(Duration::from_millis(500));
+    let node = sim.node(follower).unwrap();
+    assert_eq!(node.log.last_log_index(), last);
+    assert_eq!(node.commit_index, last);
+
+    let sent = sim
+        .node_trace(leader)
+        .into_iter()
+        .filter_map(|event| match event {
+            TraceEvent::Sent {
+                action: RaftAction::SendAppendEntries(_, request),
+                ..
+            } => Some(request.entries.len()),
+            _ => None,
+        });
+    assert!(sent.max().unwrap() <= 4);
+    assert_eq!(spec::check(&sim.cluster_trace()), Ok(()));
+}
+
+// A request covering fewer entries than one already handled, e.g. a window
+// the leader resent from further back, must not pull the commit index back
+// even if it carries a newer leader commit.
+#[test]
+fn shorter_append_entries_does_not_move_the_commit_index_back() {
+    let mut node = RaftNode::new(NodeId::new(2), vec![NodeId::new(1), NodeId::new(3)]);
+    let request = |last: u64, leader_commit: u64| AppendEntriesRequest {
+        term: Term::new(1),
+        leader_id: NodeId::new(1),
+        prev_log_index: LogIndex::ZERO,
+        prev_log_term: Term::ZERO,
+        entries: (1..=last)
+            .map(|index| LogEntry {
+                term: Term::new(1),
+                index: LogIndex::new(index),
+                command: vec![index as u8],
+            })
+            .collect(),
+        leader_commit: LogIndex::new(leader_commit),
+        heartbeat_round: 0,
+    };
+
+    assert!(node.handle_append_entries(request(5, 4)).success);
+    assert_eq!(node.commit_index, LogIndex::new(4));
+
+    let response = node.handle_append_entries(request(2, 5));
+    assert!(response.success);
+    assert_eq!(response.match_index, LogIndex::new(2));
+    assert_eq!(node.commit_index, LogIndex::new(4));
+    assert_eq!(node.log.last_log_index(), LogIndex::new(5));
+}
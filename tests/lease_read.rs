@@ -0,0 +1,85 @@
+use mini_raft::config::RaftConfig;
+use mini_raft::read_index::ReadConsistency;
+use mini_raft::simulator::Simulator;
+use mini_raft::types::NodeId;
+
+fn simulator(size: u64, max_clock_drift: f64, seed: u64) -> Simulator {
+    let config = RaftConfig {
+        lease_read: true,
+        max_clock_drift,
+        ..RaftConfig::default()
+    };
+
+    Simulator::with_config((1..=size).map(NodeId::new).collect(), config, seed)
+}
+
+fn wait_for_lease(sim: &mut Simulator) -> NodeId {
+    for _ in 0..5_000 {
+        sim.tick();
+
+        if let Some(leader) = sim.find_leader() {
+            let node = sim.node(leader).unwrap();
+            if node.has_lease() && node.has_committed_in_current_term() {
+                return leader;
+            }
+        }
+    }
+    panic!("no leader acquired a lease");
+}
+
+#[test]
+fn lease_read_is_served_without_heartbeat_round() {
+    let mut sim = simulator(3, 0.05, 1);
+    let leader = wait_for_lease(&mut sim);
+
+    let runner = sim.runner_mut(leader).unwrap();
+    let id = runner
+        .read(b"x".to_vec(), ReadConsistency::LinearizableLeader)
+        .expect("leader should accept reads");
+    assert!(runner.node().read_queue.is_empty());
+
+    // No responses are needed to serve the read.
+    sim.isolate(leader);
+    sim.tick();
+    let runner = sim.runner_mut(leader).unwrap();
+    assert_eq!(runner.take_read_results(), vec![(id, Ok(Vec::new()))]);
+}
+
+#[test]
+fn lease_expires_before_new_leader_with_skewed_clocks() {
+    let max_clock_drift = 0.1;
+
+    for seed in 0..20 {
+        let mut sim = simulator(5, max_clock_drift, seed);
+        let old_leader = wait_for_lease(&mut sim);
+        let old_term = sim.node(old_leader).unwrap().current_term;
+
+        // Worst case within the bound: the leader's clock runs slow, so it
+        // believes its lease lasts longer, and every follower's runs fast.
+        for id in (1..=5).map(NodeId::new) {
+            let rate = if id == old_leader {
+                1.0 - max_clock_drift
+            } else {
+                1.0 + max_clock_drift
+            };
+            sim.set_clock_rate(id, rate);
+        }
+
+        sim.isolate(old_leader);
+        let isolated_at = sim.elapsed();
+
+        let new_leader = (0..5_000).find_map(|_| {
+            sim.tick();
+            sim.find_leader_except(old_leader)
+        });
+        let new_leader = new_leader.expect("no new leader was elected");
+
+        assert!(sim.node(new_leader).unwrap().current_term > old_term);
+        assert!(
+            !sim.node(old_leader).unwrap().has_lease(),
+            "old leader still holds a lease {:?} after isolation while node {:?} leads",
+            sim.elapsed() - isolated_at,
+            new_leader,
+        );
+    }
+}
@@ -0,0 +1,310 @@
+use std::time::Duration;
+
+use mini_raft::config::RaftConfig;
+use mini_raft::linearizability::{History, KvInput, OpId};
+use mini_raft::network::{Latency, LinkConfig};
+use mini_raft::read_index::{ReadConsistency, ReadId};
+use mini_raft::session::ProposeOutcome;
+use mini_raft::simulator::Simulator;
+use mini_raft::types::NodeId;
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+const KEYS: [&[u8]; 2] = [b"x", b"y"];
+const RETRY_AFTER: Duration = Duration::from_millis(200);
+const READ_TIMEOUT: Duration = Duration::from_secs(1);
+
+fn ms(ms: u64) -> Duration {
+    Duration::from_millis(ms)
+}
+
+fn put(key: &str, value: &str) -> KvInput {
+    KvInput::Put {
+        key: key.as_bytes().to_vec(),
+        value: value.as_bytes().to_vec(),
+    }
+}
+
+fn get(key: &str) -> KvInput {
+    KvInput::Get {
+        key: key.as_bytes().to_vec(),
+    }
+}
+
+enum Pending {
+    Put { op: OpId, sequence: u64, command: Vec<u8>, sent_at: Option<Duration> },
+    Read { op: OpId, node: NodeId, read_id: ReadId, sent_at: Duration },
+}
+
+// Closed-loop clients: each has at most one operation outstanding, retries
+// puts with the same sequence number and gives up on reads that time out.
+struct Clients {
+    rng: StdRng,
+    pending: Vec<Option<Pending>>,
+    sequences: Vec<u64>,
+    read_consistency: ReadConsistency,
+    history: History,
+}
+
+impl Clients {
+    fn new(count: usize, read_consistency: ReadConsistency, seed: u64) -> Self {
+        Self {
+            rng: StdRng::seed_from_u64(seed),
+            pending: (0..count).map(|_| None).collect(),
+            sequences: vec![0; count],
+            read_consistency,
+            history: History::new(),
+        }
+    }
+
+    fn step(&mut self, sim: &mut Simulator) {
+        let now = sim.elapsed();
+        self.collect_results(sim, now);
+
+        for client in 0..self.pending.len() {
+            match self.pending[client].take() {
+                None if self.rng.random_bool(0.05) => self.start(sim, client, now),
+                Some(Pending::Put { op, sequence, command, sent_at })
+                    if sent_at.is_none_or(|sent_at| now - sent_at >= RETRY_AFTER) =>
+                {
+                    self.submit(sim, client, op, sequence, command, now);
+                }
+                Some(Pending::Read { sent_at, .. }) if now - sent_at >= READ_TIMEOUT => {}
+                pending => self.pending[client] = pending,
+            }
+        }
+    }
+
+    fn collect_results(&mut self, sim: &mut Simulator, now: Duration) {
+        for id in sim.node_ids() {
+            let Some(runner) = sim.runner_mut(id) else {
+                continue;
+            };
+
+            for result in runner.take_command_results() {
+                let client = result.client_id as usize;
+                if let Some(Pending::Put { op, sequence, .. }) = self.pending[client]
+                    && sequence == result.sequence
+                {
+                    // An expired session leaves the write's outcome unknown.
+                    if let Ok(response) = result.response {
+                        self.history.complete(op, response, now);
+                    }
+                    self.pending[client] = None;
+                }
+            }
+
+            for (read_id, result) in runner.take_read_results() {
+                let client = self.pending.iter().position(|pending| {
+                    matches!(pending, Some(Pending::Read { node, read_id: pending_id, .. })
+                        if *node == id && *pending_id == read_id)
+                });
+                let Some(client) = client else {
+                    continue;
+                };
+
+                let Some(Pending::Read { op, .. }) = self.pending[client].take() else {
+                    unreachable!();
+                };
+                if let Ok(value) = result {
+                    self.history.complete(op, value, now);
+                }
+            }
+        }
+    }
+
+    fn start(&mut self, sim: &mut Simulator, client: usize, now: Duration) {
+        let key = KEYS[self.rng.random_range(0..KEYS.len())];
+
+        if self.rng.random_bool(0.5) {
+            self.sequences[client] += 1;
+            let sequence = self.sequences[client];
+            let mut command = key.to_vec();
+            command.extend_from_slice(format!("=c{}s{}", client, sequence).as_bytes());
+
+            let input = KvInput::from_command(&command).unwrap();
+            let op = self.history.invoke(client as u64, input, now);
+            self.submit(sim, client, op, sequence, command, now);
+            return;
+        }
+
+        let node = match self.read_consistency {
+            ReadConsistency::LinearizableLeader => sim.find_leader(),
+            _ => {
+                let ids = sim.node_ids();
+                Some(ids[self.rng.random_range(0..ids.len())])
+            }
+        };
+        let Some(runner) = node.and_then(|node| sim.runner_mut(node)) else {
+            return;
+        };
+
+        if let Some(read_id) = runner.read(key.to_vec(), self.read_consistency) {
+            let input = KvInput::Get { key: key.to_vec() };
+            let op = self.history.invoke(client as u64, input, now);
+            self.pending[client] = Some(Pending::Read {
+                op,
+                node: node.unwrap(),
+                read_id,
+                sent_at: now,
+            });
+        }
+    }
+
+    fn submit(
+        &mut self,
+        sim: &mut Simulator,
+        client: usize,
+        op: OpId,
+        sequence: u64,
+        command: Vec<u8>,
+        now: Duration,
+    ) {
+        let outcome = sim
+            .find_leader()
+            .and_then(|leader| sim.runner_mut(leader))
+            .and_then(|runner| runner.propose(client as u64, sequence, command.clone()));
+
+        let sent_at = match outcome {
+            Some(ProposeOutcome::Cached(response)) => {
+                self.history.complete(op, response, now);
+                return;
+            }
+            Some(ProposeOutcome::Queued) => Some(now),
+            Some(ProposeOutcome::Stale) | None => None,
+        };
+        self.pending[client] = Some(Pending::Put {
+            op,
+            sequence,
+            command,
+            sent_at,
+        });
+    }
+}
+
+fn run_with_faults(seed: u64, read_consistency: ReadConsistency, lease_read: bool) -> History {
+    let config = RaftConfig {
+        lease_read,
+        ..RaftConfig::default()
+    };
+    let ids: Vec<NodeId> = (1..=5).map(NodeId::new).collect();
+    let mut sim = Simulator::with_config(ids.clone(), config, seed);
+    sim.network_mut().set_default_link(LinkConfig {
+        latency: Latency::Uniform {
+            min: ms(1),
+            max: ms(20),
+        },
+        drop_rate: 0.05,
+        duplicate_rate: 0.05,
+        reorder_rate: 0.1,
+        reorder_window: ms(30),
+    });
+
+    let mut faults = StdRng::seed_from_u64(seed ^ 0xFA17);
+    let mut clients = Clients::new(4, read_consistency, seed);
+
+    for step in 0..3_000 {
+        if step % 250 == 0 {
+            match faults.random_range(0..4) {
+                0 => sim.heal(),
+                1 => {
+                    let split = faults.random_range(1..ids.len());
+                    sim.partition(&[ids[..split].to_vec(), ids[split..].to_vec()]);
+                }
+                2 => sim.crash(ids[faults.random_range(0..ids.len())]),
+                _ => ids.iter().for_each(|&id| sim.restart(id)),
+            }
+        }
+
+        sim.tick();
+        clients.step(&mut sim);
+    }
+
+    clients.history
+}
+
+#[test]
+fn accepts_concurrent_history() {
+    let mut history = History::new();
+    let a = history.invoke(1, put("x", "1"), ms(0));
+    let b = history.invoke(2, get("x"), ms(1));
+    let c = history.invoke(3, put("x", "2"), ms(2));
+    // The get overlaps both puts, so it may see the second value.
+    history.complete(b, b"2".to_vec(), ms(5));
+    history.complete(c, b"1".to_vec(), ms(4));
+    history.complete(a, Vec::new(), ms(3));
+    // Never completed: may or may not have taken effect.
+    history.invoke(4, put("x", "3"), ms(6));
+
+    assert_eq!(history.check(), Ok(()));
+}
+
+#[test]
+fn reports_minimal_stale_read() {
+    let mut history = History::new();
+    let put_1 = history.invoke(1, put("x", "1"), ms(0));
+    history.complete(put_1, Vec::new(), ms(1));
+    let noise = history.invoke(3, put("y", "1"), ms(1));
+    history.complete(noise, Vec::new(), ms(9));
+    let put_2 = history.invoke(1, put("x", "2"), ms(2));
+    history.complete(put_2, b"1".to_vec(), ms(3));
+    let get_1 = history.invoke(3, get("x"), ms(3));
+    history.complete(get_1, b"2".to_vec(), ms(4));
+    let stale = history.invoke(2, get("x"), ms(5));
+    history.complete(stale, b"1".to_vec(), ms(6));
+
+    let failure = history.check().unwrap_err();
+    assert_eq!(failure.key, b"x");
+    let operations: Vec<_> = failure.operations.iter().map(|op| op.input.clone()).collect();
+    assert_eq!(operations, vec![put("x", "1"), put("x", "2"), get("x")]);
+}
+
+#[test]
+fn linearizable_under_faults() {
+    for seed in 0..8 {
+        for (consistency, lease_read) in [
+            (ReadConsistency::LinearizableLeader, false),
+            (ReadConsistency::LinearizableLeader, true),
+            (ReadConsistency::LinearizableFollower, false),
+        ] {
+            let history = run_with_faults(seed, consistency, lease_read);
+            assert!(history.len() > 20, "seed {}: only {} operations", seed, history.len());
+            if let Err(failure) = history.check() {
+                panic!("seed {} {:?} lease {}: {}", seed, consistency, lease_read, failure);
+            }
+        }
+    }
+}
+
+#[test]
+fn detects_stale_reads_from_deposed_leader() {
+    let ids: Vec<NodeId> = (1..=3).map(NodeId::new).collect();
+    let mut sim = Simulator::with_seed(ids, 7);
+    let mut history = History::new();
+    sim.advance(Duration::from_secs(1));
+    let old_leader = sim.find_leader().unwrap();
+
+    let op = history.invoke(1, put("x", "1"), sim.elapsed());
+    sim.runner_mut(old_leader).unwrap().propose(1, 1, b"x=1".to_vec());
+    sim.advance(ms(100));
+    history.complete(op, Vec::new(), sim.elapsed());
+
+    sim.isolate(old_leader);
+    sim.advance(Duration::from_secs(1));
+    let new_leader = sim.find_leader_except(old_leader).unwrap();
+
+    let op = history.invoke(2, put("x", "2"), sim.elapsed());
+    sim.runner_mut(new_leader).unwrap().propose(2, 1, b"x=2".to_vec());
+    sim.advance(ms(100));
+    history.complete(op, b"1".to_vec(), sim.elapsed());
+    sim.tick();
+
+    let op = history.invoke(3, get("x"), sim.elapsed());
+    let runner = sim.runner_mut(old_leader).unwrap();
+    runner.read(b"x".to_vec(), ReadConsistency::Stale).unwrap();
+    let (_, value) = runner.take_read_results().pop().unwrap();
+    history.complete(op, value.unwrap(), sim.elapsed());
+
+    let failure = history.check().unwrap_err();
+    assert_eq!(failure.operations.len(), 3);
+}
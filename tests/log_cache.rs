@@ -0,0 +1,158 @@
+mod common;
+
+use std::time::Duration;
+use std::{env, fs, process};
+
+use mini_raft::config::RaftConfig;
+use mini_raft::log::LogEntry;
+use mini_raft::node::RaftNode;
+use mini_raft::raft::{RaftAction, RaftRunner};
+use mini_raft::rpc::AppendEntriesRequest;
+use mini_raft::segment::SegmentStorage;
+use mini_raft::session::ProposeOutcome;
+use mini_raft::simulator::Simulator;
+use mini_raft::spec;
+use mini_raft::state_machine::KvStore;
+use mini_raft::trace::TraceEvent;
+use mini_raft::types::{LogIndex, NodeId, Term};
+
+use common::elect;
+
+const BUDGET: u64 = 512;
+
+fn config() -> RaftConfig {
+    RaftConfig {
+        log_cache_bytes: Some(BUDGET),
+        ..RaftConfig::default()
+    }
+}
+
+// A follower that was down while the log grew past the leader's cache is
+// caught up with entries the leader reads back from storage, a window of
+// at most `max_append_bytes` at a time.
+#[test]
+fn lagging_follower_is_served_from_storage() {
+    let config = RaftConfig {
+        max_append_bytes: 128,
+        ..config()
+    };
+    let mut sim = Simulator::with_config((1..=3).map(NodeId::new).collect(), config, 4);
+    let leader = elect(&mut sim);
+    let follower = sim.node_ids().into_iter().find(|&id| id != leader).unwrap();
+    sim.crash(follower);
+
+    for sequence in 1..=40 {
+        let command = format!("key{}={}", sequence, "v".repeat(32)).into_bytes();
+        let outcome = sim
+            .runner_mut(leader)
+            .unwrap()
+            .propose(1, sequence, command);
+        assert_eq!(outcome, Some(ProposeOutcome::Queued));
+        sim.advance(Duration::from_millis(5));
+    }
+    let last = sim.node(leader).unwrap().log.last_log_index();
+    assert!(sim.node(leader).unwrap().log.cached_bytes() <= BUDGET);
+
+    sim.restart(follower);
+    sim.advance(Duration::from_millis(500));
+    let node = sim.node(follower).unwrap();
+    assert!(node.commit_index >= last);
+    assert_eq!(
+        *node.log.entries(),
+        *sim.node(leader).unwrap().log.entries()
+    );
+    assert!(node.log.cached_bytes() <= BUDGET);
+
+    for event in sim.node_trace(leader) {
+        if let TraceEvent::Sent {
+            action: RaftAction::SendAppendEntries(_, request),
+            ..
+        } = event
+        {
+            let bytes: usize = request
+                .entries
+                .iter()
+                .map(|entry| entry.command.len())
+                .sum();
+            assert!(bytes <= 128 || request.entries.len() == 1);
+        }
+    }
+    assert_eq!(spec::check(&sim.cluster_trace()), Ok(()));
+}
+
+// A restarted node loads its log from the segment files but only keeps the
+// newest commands in memory.
+#[test]
+fn restarted_node_keeps_within_its_budget() {
+    let dir = env::temp_dir().join(format!("mini-raft-log-cache-{}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    let id = NodeId::new(1);
+    let runner = |storage: &SegmentStorage| {
+        let node = RaftNode::with_config(id, Vec::new(), config());
+        RaftRunner::with_storage(node, Box::new(KvStore::new()), Box::new(storage.clone()))
+    };
+
+    let storage = SegmentStorage::with_max_segment_bytes(&dir, 1024).unwrap();
+    let mut first = runner(&storage);
+    while !first.node().has_committed_in_current_term() {
+        first.tick();
+    }
+    for sequence in 1..=40 {
+        first.propose(1, sequence, vec![b'x'; 64]).unwrap();
+        first.tick();
+        first.sync();
+    }
+    first.tick();
+    let entries = first.node().log.entries().into_owned();
+    assert_eq!(entries.len(), 41);
+    assert!(first.node().log.cached_bytes() <= BUDGET);
+    drop(first);
+
+    let storage = SegmentStorage::open(&dir).unwrap();
+    assert!(storage.segment_count() > 1);
+    let restarted = runner(&storage);
+    assert!(restarted.node().log.cached_bytes() <= BUDGET);
+    assert_eq!(*restarted.node().log.entries(), *entries);
+    assert_eq!(
+        restarted.node().log.get(LogIndex::new(2)).as_deref(),
+        Some(&entries[1])
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// Committed entries are handed out for applying a budget's worth at a time,
+// so a long backlog is never read back all at once.
+#[test]
+fn committed_entries_are_taken_within_the_budget() {
+    let mut node = RaftNode::with_config(NodeId::new(2), vec![NodeId::new(1)], config());
+    let entries = (1..=20)
+        .map(|index| LogEntry {
+            term: Term::new(1),
+            index: LogIndex::new(index),
+            command: vec![b'x'; 100],
+        })
+        .collect();
+    let response = node.handle_append_entries(AppendEntriesRequest {
+        term: Term::new(1),
+        leader_id: NodeId::new(1),
+        prev_log_index: LogIndex::ZERO,
+        prev_log_term: Term::ZERO,
+        entries,
+        leader_commit: LogIndex::new(20),
+        heartbeat_round: 0,
+    });
+    assert!(response.success);
+
+    let mut applied = Vec::new();
+    loop {
+        let batch = node.take_committed_entries();
+        if batch.is_empty() {
+            break;
+        }
+        let bytes: usize = batch.iter().map(|entry| entry.command.len()).sum();
+        assert!(bytes as u64 <= BUDGET);
+        applied.extend(batch.iter().map(|entry| entry.index.get()));
+    }
+    assert_eq!(applied, (1..=20).collect::<Vec<_>>());
+    assert_eq!(node.last_applied, LogIndex::new(20));
+}
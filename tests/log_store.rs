@@ -0,0 +1,231 @@
+use std::borrow::Cow;
+
+use mini_raft::log::{LogEntry, LogOffset, LogStore};
+use mini_raft::node::RaftNode;
+use mini_raft::storage::{MemStorage, Storage};
+use mini_raft::types::{LogIndex, NodeId, Term};
+
+fn entry(index: u64, term: u64) -> LogEntry {
+    LogEntry {
+        term: Term::new(term),
+        index: LogIndex::new(index),
+        command: index.to_be_bytes().to_vec(),
+    }
+}
+
+fn log(last: u64) -> LogStore {
+    let mut log = LogStore::new();
+    for index in 1..=last {
+        log.append(entry(index, index.div_ceil(4)));
+    }
+    log
+}
+
+fn offset(index: u64, term: u64) -> LogOffset {
+    LogOffset {
+        index: LogIndex::new(index),
+        term: Term::new(term),
+    }
+}
+
+fn indices(entries: &[LogEntry]) -> Vec<u64> {
+    entries.iter().map(|entry| entry.index.get()).collect()
+}
+
+#[test]
+fn entries_are_addressed_by_index() {
+    let log = log(10);
+    assert_eq!(log.first_index(), LogIndex::new(1));
+    assert_eq!(log.last_log_index(), LogIndex::new(10));
+    assert_eq!(log.last_log_term(), Term::new(3));
+    assert_eq!(log.get(LogIndex::new(7)).as_deref(), Some(&entry(7, 2)));
+    assert_eq!(log.get(LogIndex::ZERO), None);
+    assert_eq!(log.get(LogIndex::new(11)), None);
+    assert_eq!(log.term_at(LogIndex::ZERO), Some(Term::ZERO));
+
+    assert_eq!(indices(&log.entries_from(LogIndex::new(8))), [8, 9, 10]);
+    assert_eq!(indices(&log.entries_from(LogIndex::ZERO)).len(), 10);
+    assert!(log.entries_from(LogIndex::new(11)).is_empty());
+    assert_eq!(
+        indices(&log.entries_between(LogIndex::new(3), LogIndex::new(5))),
+        [3, 4, 5]
+    );
+    assert_eq!(
+        indices(&log.entries_between(LogIndex::new(9), LogIndex::new(20))),
+        [9, 10]
+    );
+    assert!(
+        log.entries_between(LogIndex::new(6), LogIndex::new(5))
+            .is_empty()
+    );
+}
+
+#[test]
+fn truncate_drops_the_suffix() {
+    let mut log = log(10);
+    log.take_unstable_from();
+
+    log.truncate(LogIndex::new(6));
+    assert_eq!(log.last_log_index(), LogIndex::new(5));
+    assert_eq!(log.get(LogIndex::new(6)), None);
+    assert_eq!(log.take_unstable_from(), Some(LogIndex::new(6)));
+
+    log.append(entry(6, 5));
+    assert_eq!(log.last_log_term(), Term::new(5));
+    assert_eq!(log.take_unstable_from(), Some(LogIndex::new(6)));
+}
+
+// Once a prefix is compacted the offset moves, and the last compacted entry
+// still answers for its term.
+#[test]
+fn compaction_moves_the_offset() {
+    let mut log = log(10);
+    log.compact(LogIndex::new(6));
+
+    assert_eq!(log.first_index(), LogIndex::new(7));
+    assert_eq!(log.len(), 4);
+    assert_eq!(log.get(LogIndex::new(6)), None);
+    assert_eq!(log.term_at(LogIndex::new(6)), Some(Term::new(2)));
+    assert_eq!(log.term_at(LogIndex::new(5)), None);
+    assert_eq!(log.get(LogIndex::new(8)).as_deref(), Some(&entry(8, 2)));
+    assert_eq!(indices(&log.entries_from(LogIndex::new(2))), [7, 8, 9, 10]);
+
+    log.compact(LogIndex::new(10));
+    assert!(log.is_empty());
+    assert_eq!(log.last_log_index(), LogIndex::new(10));
+    assert_eq!(log.last_log_term(), Term::new(3));
+    log.append(entry(11, 4));
+    assert_eq!(log.get(LogIndex::new(11)).as_deref(), Some(&entry(11, 4)));
+}
+
+#[test]
+fn a_log_restored_past_a_compaction_keeps_the_offset_term() {
+    let log = LogStore::from_entries(
+        (7..=10).map(|index| entry(index, 3)).collect(),
+        offset(6, 2),
+    );
+    assert_eq!(log.first_index(), LogIndex::new(7));
+    assert_eq!(log.term_at(LogIndex::new(6)), Some(Term::new(2)));
+    assert_eq!(log.last_log_term(), Term::new(3));
+
+    let empty = LogStore::from_entries(Vec::new(), LogOffset::default());
+    assert_eq!(empty.last_log_index(), LogIndex::ZERO);
+    assert_eq!(empty.term_at(LogIndex::ZERO), Some(Term::ZERO));
+
+    // Compacted all the way, the log has no entry to take its offset from.
+    let compacted = LogStore::from_entries(Vec::new(), offset(10, 3));
+    assert_eq!(compacted.first_index(), LogIndex::new(11));
+    assert_eq!(compacted.last_log_index(), LogIndex::new(10));
+    assert_eq!(compacted.last_log_term(), Term::new(3));
+}
+
+fn cached(last: u64, budget: u64) -> LogStore {
+    let mut log = log(last);
+    let mut storage = MemStorage::new();
+    storage.append(&log.entries());
+    storage.sync();
+    let first = log.first_index();
+    log.set_cache(storage.log_reader().unwrap(), budget, first);
+    log
+}
+
+// Over its budget the log drops the oldest commands it holds, keeps their
+// terms, and reads them back from storage.
+#[test]
+fn evicted_entries_are_read_back() {
+    let mut log = cached(10, 24);
+    assert_eq!(log.cached_bytes(), 80);
+
+    log.evict(LogIndex::new(10));
+    assert_eq!(log.cached_bytes(), 24);
+    assert_eq!(log.term_at(LogIndex::new(2)), Some(Term::new(1)));
+    assert_eq!(log.get(LogIndex::new(2)).as_deref(), Some(&entry(2, 1)));
+    assert!(matches!(
+        log.entries_from(LogIndex::new(8)),
+        Cow::Borrowed(_)
+    ));
+
+    let entries = log.entries_between(LogIndex::new(6), LogIndex::new(9));
+    assert!(matches!(entries, Cow::Owned(_)));
+    assert_eq!(
+        *entries,
+        [entry(6, 2), entry(7, 2), entry(8, 2), entry(9, 3)]
+    );
+    assert_eq!(log.entries().len(), 10);
+    assert_eq!(log.entries()[0], entry(1, 1));
+}
+
+// Only entries on disk can be dropped, and dropping them again after a
+// truncation keeps the count right.
+#[test]
+fn eviction_stops_at_the_durable_index() {
+    let mut log = cached(10, 0);
+    log.evict(LogIndex::new(4));
+    assert_eq!(log.cached_bytes(), 48);
+
+    log.truncate(LogIndex::new(3));
+    assert_eq!(log.cached_bytes(), 0);
+    log.append(entry(3, 5));
+    assert_eq!(log.cached_bytes(), 8);
+    assert_eq!(log.get(LogIndex::new(3)).as_deref(), Some(&entry(3, 5)));
+    assert_eq!(log.get(LogIndex::new(1)).as_deref(), Some(&entry(1, 1)));
+
+    log.compact(LogIndex::new(2));
+    assert_eq!(log.cached_bytes(), 8);
+}
+
+// Reading back a window stops at the byte limit, across the commands read
+// from storage and those still held.
+#[test]
+fn windows_stop_at_the_byte_limit() {
+    let mut log = cached(10, 24);
+    log.evict(LogIndex::new(10));
+    let (from, to) = (LogIndex::new(5), LogIndex::new(10));
+
+    assert_eq!(indices(&log.entries_within(from, to, 16)), [5, 6]);
+    assert_eq!(indices(&log.entries_within(from, to, 40)), [5, 6, 7, 8, 9]);
+    assert_eq!(indices(&log.entries_within(from, to, 0)), [5]);
+    let held = log.entries_within(LogIndex::new(9), to, 8);
+    assert!(matches!(held, Cow::Borrowed(_)));
+    assert_eq!(indices(&held), [9]);
+}
+
+// Storage reads see unsynced writes over the synced log, as `load` does.
+#[test]
+fn mem_storage_reads_through_unsynced_writes() {
+    let mut storage = MemStorage::new();
+    storage.append(&(1..=6).map(|index| entry(index, 1)).collect::<Vec<_>>());
+    storage.sync();
+    storage.truncate(LogIndex::new(4));
+    storage.append(&[entry(4, 2), entry(5, 2)]);
+
+    let reader = storage.log_reader().unwrap();
+    let (from, to) = (LogIndex::new(2), LogIndex::new(6));
+    let read = reader.read(from, to, u64::MAX);
+    assert_eq!(read, [entry(2, 1), entry(3, 1), entry(4, 2), entry(5, 2)]);
+    assert_eq!(read, storage.load().2[1..]);
+    assert_eq!(reader.read(from, to, 20).len(), 2);
+}
+
+// The offset of a compacted log is stored with the hard state, so a node
+// restored from it still knows the term before its first entry.
+#[test]
+fn mem_storage_keeps_the_offset_of_a_compacted_log() {
+    let mut storage = MemStorage::new();
+    storage.append(&log(10).entries());
+    storage.sync();
+    storage.compact(offset(6, 2));
+
+    let reader = storage.log_reader().unwrap();
+    let read = reader.read(LogIndex::new(1), LogIndex::new(8), u64::MAX);
+    assert_eq!(indices(&read), [7, 8]);
+    storage.sync();
+
+    let (hard_state, loaded, entries) = storage.load();
+    assert_eq!(loaded, offset(6, 2));
+    assert_eq!(indices(&entries), [7, 8, 9, 10]);
+    let mut node = RaftNode::new(NodeId::new(1), Vec::new());
+    node.restore(hard_state, loaded, entries);
+    assert_eq!(node.log.first_index(), LogIndex::new(7));
+    assert_eq!(node.log.term_at(LogIndex::new(6)), Some(Term::new(2)));
+}
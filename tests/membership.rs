@@ -0,0 +1,196 @@
+mod common;
+
+use std::time::Duration;
+
+use mini_raft::log::LogEntry;
+use mini_raft::node::RaftNode;
+use mini_raft::rpc::AppendEntriesRequest;
+use mini_raft::session::{Command, ProposeOutcome};
+use mini_raft::simulator::Simulator;
+use mini_raft::trace;
+use mini_raft::types::{LogIndex, NodeId, Term};
+
+use common::elect;
+
+fn id(id: u64) -> NodeId {
+    NodeId::new(id)
+}
+
+fn ids(ids: &[u64]) -> Vec<NodeId> {
+    ids.iter().copied().map(NodeId::new).collect()
+}
+
+fn cluster() -> (Simulator, NodeId) {
+    let mut sim = Simulator::with_seed(ids(&[1, 2, 3]), 5);
+    let leader = elect(&mut sim);
+    (sim, leader)
+}
+
+fn followers(sim: &Simulator, leader: NodeId) -> Vec<NodeId> {
+    let node = sim.node(leader).unwrap();
+    node.members()
+        .iter()
+        .copied()
+        .filter(|&member| member != leader)
+        .collect()
+}
+
+fn change(sim: &mut Simulator, leader: NodeId, members: Vec<NodeId>) -> LogIndex {
+    let index = sim
+        .runner_mut(leader)
+        .unwrap()
+        .change_membership(members)
+        .expect("leader refused the change");
+    sim.advance(Duration::from_millis(300));
+    assert!(sim.node(leader).unwrap().commit_index >= index);
+    index
+}
+
+fn commits(sim: &mut Simulator, leader: NodeId, sequence: u64) -> bool {
+    let outcome = sim
+        .runner_mut(leader)
+        .unwrap()
+        .propose(1, sequence, b"x=1".to_vec());
+    assert_eq!(outcome, Some(ProposeOutcome::Queued));
+    sim.advance(Duration::from_millis(300));
+    let results = sim.runner_mut(leader).unwrap().take_command_results();
+    results.iter().any(|result| result.sequence == sequence)
+}
+
+// A new node starts outside the cluster, catches up once added, and then
+// counts towards the quorum: four nodes need three acks.
+#[test]
+fn added_node_catches_up_and_counts_towards_the_quorum() {
+    let (mut sim, leader) = cluster();
+    sim.add_node(id(4));
+    sim.advance(Duration::from_millis(500));
+    assert!(!sim.node(id(4)).unwrap().is_member());
+    assert_eq!(sim.find_leader(), Some(leader));
+
+    let index = change(&mut sim, leader, ids(&[1, 2, 3, 4]));
+    let added = sim.node(id(4)).unwrap();
+    assert!(added.is_member());
+    assert_eq!(added.log.last_log_index(), index);
+    assert_eq!(added.cluster_size(), 4);
+
+    let follower = followers(&sim, leader)[0];
+    sim.crash(follower);
+    assert!(commits(&mut sim, leader, 1));
+    sim.crash(id(4));
+    assert!(!commits(&mut sim, leader, 2));
+}
+
+// Once removed, a node no longer counts towards the quorum. It may never
+// hear of its removal, but the campaigns it starts then cannot unseat the
+// leader.
+#[test]
+fn removed_node_stops_counting() {
+    let (mut sim, leader) = cluster();
+    let others = followers(&sim, leader);
+    let (removed, kept) = (others[0], others[1]);
+
+    change(&mut sim, leader, vec![leader, kept]);
+    assert!(!sim.node(leader).unwrap().members().contains(&removed));
+    assert_eq!(sim.node(leader).unwrap().cluster_size(), 2);
+    assert_eq!(sim.node(kept).unwrap().peers, vec![leader]);
+
+    sim.advance(Duration::from_secs(2));
+    assert_eq!(sim.find_leader(), Some(leader));
+    assert!(commits(&mut sim, leader, 1));
+    sim.crash(kept);
+    assert!(!commits(&mut sim, leader, 2));
+}
+
+#[test]
+fn leader_takes_one_change_of_one_other_node_at_a_time() {
+    let (mut sim, leader) = cluster();
+    let others = followers(&sim, leader);
+    sim.add_node(id(4));
+    sim.add_node(id(5));
+    let runner = sim.runner_mut(leader).unwrap();
+
+    assert_eq!(runner.change_membership(ids(&[1, 2, 3, 4, 5])), None);
+    assert_eq!(runner.change_membership(vec![leader]), None);
+    assert_eq!(runner.change_membership(others.clone()), None);
+    assert_eq!(runner.change_membership(ids(&[1, 2, 3, 4, 4])), None);
+    assert!(runner.change_membership(ids(&[1, 2, 3, 4])).is_some());
+    // The first change has not committed yet.
+    assert_eq!(runner.change_membership(ids(&[1, 2, 3])), None);
+
+    sim.advance(Duration::from_millis(300));
+    let runner = sim.runner_mut(leader).unwrap();
+    assert!(runner.change_membership(ids(&[1, 2, 3, 4, 5])).is_some());
+}
+
+// Membership lives in the log, so a restarted node picks it up again, and a
+// new node that never got it stays outside.
+#[test]
+fn membership_survives_a_restart() {
+    let (mut sim, leader) = cluster();
+    sim.add_node(id(4));
+    sim.add_node(id(5));
+    change(&mut sim, leader, ids(&[1, 2, 3, 4]));
+
+    for node in [leader, id(4), id(5)] {
+        sim.crash(node);
+        sim.restart(node);
+    }
+    assert_eq!(sim.node(leader).unwrap().members(), ids(&[1, 2, 3, 4]));
+    assert!(sim.node(id(4)).unwrap().is_member());
+    assert!(!sim.node(id(5)).unwrap().is_member());
+
+    sim.advance(Duration::from_secs(1));
+    for node in [1, 2, 3, 4, 5] {
+        if let Err(err) = trace::replay(&sim.node_trace(id(node))) {
+            panic!("node {}: {}", node, err);
+        }
+    }
+}
+
+// A change takes effect as soon as it is in the log, so one that is
+// truncated away is undone as well.
+#[test]
+fn truncated_change_is_undone() {
+    let mut node = RaftNode::new(id(2), ids(&[1, 3]));
+    let append = |term: u64, leader: u64, entries: Vec<LogEntry>| AppendEntriesRequest {
+        term: Term::new(term),
+        leader_id: id(leader),
+        prev_log_index: LogIndex::ZERO,
+        prev_log_term: Term::ZERO,
+        entries,
+        leader_commit: LogIndex::ZERO,
+        heartbeat_round: 0,
+    };
+    let entry = |term: u64, command: Vec<u8>| LogEntry {
+        term: Term::new(term),
+        index: LogIndex::new(1),
+        command,
+    };
+
+    let change = Command::Membership(ids(&[1, 2, 3, 4])).encode();
+    assert!(
+        node.handle_append_entries(append(1, 1, vec![entry(1, change)]))
+            .success
+    );
+    assert_eq!(node.members(), ids(&[1, 2, 3, 4]));
+    assert_eq!(node.peers, ids(&[1, 3, 4]));
+    assert_eq!(node.membership_index(), LogIndex::new(1));
+
+    let noop = entry(2, Vec::new());
+    assert!(node.handle_append_entries(append(2, 3, vec![noop])).success);
+    assert_eq!(node.members(), ids(&[1, 3, 2]));
+    assert_eq!(node.peers, ids(&[1, 3]));
+    assert_eq!(node.membership_index(), LogIndex::ZERO);
+}
+
+#[test]
+fn membership_commands_round_trip() {
+    let command = Command::Membership(ids(&[3, 1, 7]));
+    assert_eq!(Command::decode(&command.encode()), Some(command.clone()));
+    assert_eq!(
+        Command::membership(&command.encode()),
+        Some(ids(&[3, 1, 7]))
+    );
+    assert_eq!(Command::membership(&Command::Plain(vec![2]).encode()), None);
+    assert_eq!(Command::decode(&[2, 0, 0, 1]), None);
+}
@@ -0,0 +1,126 @@
+mod common;
+
+use std::collections::BTreeMap;
+use std::net::TcpListener;
+use std::time::Duration;
+
+use mini_raft::grpc::{GrpcTransport, Listener, PeerAddr};
+use mini_raft::handshake::Handshake;
+use mini_raft::peer::PeerConfig;
+use mini_raft::types::NodeId;
+
+use common::{Runners, eventually, replicates_a_proposal, start};
+
+// An address nothing listens on.
+fn dead_addr() -> PeerAddr {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    PeerAddr::Tcp(listener.local_addr().unwrap())
+}
+
+// Node 3 never comes up. Nodes 1 and 2 still elect a leader and replicate,
+// and the leader sees node 3 as unreachable while node 2 stays reachable.
+#[tokio::test]
+async fn dead_peer_does_not_hold_up_the_cluster() {
+    let all: Vec<_> = (1..=3).map(NodeId::new).collect();
+    let mut listeners = BTreeMap::new();
+    let mut addrs = BTreeMap::new();
+    for &id in &all[..2] {
+        let listener = Listener::bind(&"127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        addrs.insert(id, listener.local_addr().unwrap());
+        listeners.insert(id, listener);
+    }
+    addrs.insert(all[2], dead_addr());
+
+    let (mut runners, mut managers) = (Runners::new(), BTreeMap::new());
+    for (id, listener) in listeners {
+        let mut peers = addrs.clone();
+        peers.remove(&id);
+        let transport = GrpcTransport::new(Handshake::new("test"), &peers);
+        let manager = transport.peers();
+        tokio::spawn(transport.serve(listener));
+        runners.insert(id, start(id, &all, transport));
+        managers.insert(id, manager);
+    }
+
+    let leader = replicates_a_proposal(&runners).await;
+
+    let peers = &managers[&leader];
+    let follower = all[..2].iter().copied().find(|&id| id != leader).unwrap();
+    assert!(peers.reachable(follower));
+    let dead = peers.status(all[2]).unwrap();
+    assert!(!dead.reachable);
+    assert!(dead.failures >= 1);
+    // A failed peer is only probed one call at a time.
+    assert!(dead.in_flight <= 1);
+    assert_eq!(peers.status(NodeId::new(9)), None);
+}
+
+// A peer that accepts connections but never answers is cut off by the RPC
+// deadline, and backed off from more and more between probes.
+#[tokio::test]
+async fn silent_peer_times_out_and_backs_off() {
+    let silent = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = PeerAddr::Tcp(silent.local_addr().unwrap());
+    tokio::spawn(async move {
+        let mut held = Vec::new();
+        while let Ok((stream, _)) = silent.accept().await {
+            held.push(stream);
+        }
+    });
+
+    let config = PeerConfig {
+        rpc_timeout: Duration::from_millis(30),
+        max_in_flight: 2,
+        backoff_min: Duration::from_millis(10),
+        backoff_max: Duration::from_millis(40),
+    };
+    let all = [NodeId::new(1), NodeId::new(2)];
+    let transport = GrpcTransport::new(Handshake::new("test"), &BTreeMap::from([(all[1], addr)]))
+        .with_peer_config(config.clone());
+    let peers = transport.peers();
+    start(all[0], &all, transport);
+    assert_eq!(peers.config(), &config);
+
+    let status = eventually(|| {
+        let status = peers.status(all[1]).unwrap();
+        assert!(status.in_flight <= config.max_in_flight);
+        (status.failures >= 3).then_some(status)
+    })
+    .await;
+    assert!(!status.reachable);
+}
+
+// A peer of another cluster answers every call with an error. It is up, so
+// it stays reachable however often it refuses.
+#[tokio::test]
+async fn peer_answering_with_an_error_stays_reachable() {
+    let all = [NodeId::new(1), NodeId::new(2)];
+    let mut addrs = BTreeMap::new();
+    let mut runners = Runners::new();
+    let mut managers = BTreeMap::new();
+    let mut listeners = Vec::new();
+    for &id in &all {
+        let listener = Listener::bind(&"127.0.0.1:0".parse().unwrap())
+            .await
+            .unwrap();
+        addrs.insert(id, listener.local_addr().unwrap());
+        listeners.push((id, listener));
+    }
+    for ((id, listener), cluster) in listeners.into_iter().zip(["test", "other"]) {
+        let mut peers = addrs.clone();
+        peers.remove(&id);
+        let transport = GrpcTransport::new(Handshake::new(cluster), &peers);
+        managers.insert(id, transport.peers());
+        tokio::spawn(transport.serve(listener));
+        runners.insert(id, start(id, &all, transport));
+    }
+
+    // Each new term is another round of vote requests the peer refused.
+    eventually(|| (runners[&all[0]].lock().unwrap().node().current_term.get() >= 4).then_some(()))
+        .await;
+    let status = managers[&all[0]].status(all[1]).unwrap();
+    assert!(status.reachable);
+    assert_eq!(status.failures, 0);
+}
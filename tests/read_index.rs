@@ -0,0 +1,81 @@
+mod common;
+
+use std::time::{Duration, Instant};
+
+use mini_raft::read_index::{ReadConsistency, ReadError, ReadIndexQueue};
+use mini_raft::simulator::Simulator;
+use mini_raft::timer::min_election_timeout;
+use mini_raft::types::{LogIndex, NodeId};
+
+use common::elect;
+
+// Reads registered before a round all wait for it, and one quorum of acks
+// for that round confirms them together. Later reads need the next round.
+#[test]
+fn reads_before_a_round_share_it() {
+    let peers = [NodeId::new(2), NodeId::new(3)];
+    let mut queue = ReadIndexQueue::new();
+    let first = queue.register(LogIndex::new(4));
+    let second = queue.register(LogIndex::new(5));
+    assert!(queue.needs_round());
+
+    let round = queue.start_round(Instant::now());
+    let later = queue.register(LogIndex::new(6));
+    queue.acknowledge(peers[0], round - 1);
+    queue.confirm(&peers, 2);
+    assert!(queue.take_confirmed().is_empty());
+
+    queue.acknowledge(peers[0], round);
+    queue.confirm(&peers, 2);
+    assert_eq!(
+        queue.take_confirmed(),
+        vec![(first, LogIndex::new(4)), (second, LogIndex::new(5))]
+    );
+    assert_eq!(queue.len(), 1);
+    assert!(queue.needs_round());
+
+    queue.abort_all();
+    assert_eq!(queue.take_aborted(), vec![later]);
+    assert!(queue.is_empty());
+}
+
+#[test]
+fn leader_read_sees_a_committed_write() {
+    let mut sim = Simulator::with_seed((1..=3).map(NodeId::new).collect(), 5);
+    let leader = elect(&mut sim);
+    sim.runner_mut(leader)
+        .unwrap()
+        .propose(1, 1, b"x=1".to_vec())
+        .unwrap();
+    sim.advance(Duration::from_millis(50));
+
+    let runner = sim.runner_mut(leader).unwrap();
+    let id = runner
+        .read(b"x".to_vec(), ReadConsistency::LinearizableLeader)
+        .expect("leader should accept reads");
+    assert_eq!(runner.node().read_queue.len(), 1);
+    sim.advance(Duration::from_millis(20));
+    let results = sim.runner_mut(leader).unwrap().take_read_results();
+    assert_eq!(results, vec![(id, Ok(b"1".to_vec()))]);
+}
+
+// Without acks from a quorum the read is never served, and it fails once
+// the leader steps down.
+#[test]
+fn isolated_leader_does_not_serve_reads() {
+    let mut sim = Simulator::with_seed((1..=3).map(NodeId::new).collect(), 6);
+    let leader = elect(&mut sim);
+    sim.isolate(leader);
+
+    let id = sim
+        .runner_mut(leader)
+        .unwrap()
+        .read(b"x".to_vec(), ReadConsistency::LinearizableLeader)
+        .expect("leader should accept reads");
+    sim.advance(Duration::from_millis(50));
+    assert!(sim.runner_mut(leader).unwrap().take_read_results().is_empty());
+
+    sim.advance(min_election_timeout() * 2);
+    let results = sim.runner_mut(leader).unwrap().take_read_results();
+    assert_eq!(results, vec![(id, Err(ReadError::NotLeader))]);
+}
@@ -0,0 +1,53 @@
+use std::fs;
+use std::path::Path;
+
+use mini_raft::scenario::Scenario;
+
+// Every `.scenario` file under tests/scenarios must pass.
+#[test]
+fn scenario_files_pass() {
+    let dir = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/scenarios");
+    let mut paths: Vec<_> = fs::read_dir(&dir)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "scenario"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "no scenarios in {}", dir.display());
+
+    for path in paths {
+        let text = fs::read_to_string(&path).unwrap();
+        let scenario = Scenario::parse(&text)
+            .unwrap_or_else(|err| panic!("{}: {}", path.display(), err));
+        if let Err(err) = scenario.run() {
+            panic!("{}: {}", path.display(), err);
+        }
+    }
+}
+
+#[test]
+fn failures_point_at_the_line() {
+    let scenario = Scenario::parse("start 3 nodes\nwait leader\n\nassert committed 1 on all\n").unwrap();
+    let err = scenario.run().unwrap_err();
+
+    assert_eq!(err.line, 4);
+    assert!(err.message.contains("has 0 committed commands, expected 1"), "{}", err);
+}
+
+// An old leader cut off in a minority does not count once the majority has
+// elected a newer one.
+#[test]
+fn leader_condition_honors_its_nodes() {
+    let start = "seed 2\nstart 5 nodes\nwait leader\npartition {1,2} {3,4,5}\nadvance 2s\n";
+
+    let text = format!("{}wait leader on 3,4,5\nassert no leader on 3,4,5\n", start);
+    let err = Scenario::parse(&text).unwrap().run().unwrap_err();
+    assert_eq!(err.line, 7);
+
+    let text = format!("{}assert leader on 1,2\n", start);
+    let err = Scenario::parse(&text).unwrap().run().unwrap_err();
+    assert_eq!(err.line, 6);
+    assert!(err.message.contains("is leader"), "{}", err);
+
+    assert!(Scenario::parse("start 3 nodes\nwait leader 1 on 2\n").is_err());
+}
@@ -0,0 +1,249 @@
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::{env, process};
+
+use mini_raft::log::{LogEntry, LogOffset, LogReader};
+use mini_raft::segment::SegmentStorage;
+use mini_raft::storage::{HardState, Storage};
+use mini_raft::types::{LogIndex, NodeId, Term};
+
+fn dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("mini-raft-segment-{}-{}", name, process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    dir
+}
+
+fn entry(index: u64, term: u64) -> LogEntry {
+    LogEntry {
+        term: Term::new(term),
+        index: LogIndex::new(index),
+        command: format!("command {}", index).into_bytes(),
+    }
+}
+
+fn entries(from: u64, to: u64, term: u64) -> Vec<LogEntry> {
+    (from..=to).map(|index| entry(index, term)).collect()
+}
+
+fn segments(dir: &Path) -> Vec<PathBuf> {
+    let mut paths: Vec<_> = fs::read_dir(dir)
+        .unwrap()
+        .map(|file| file.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|ext| ext == "log"))
+        .collect();
+    paths.sort();
+    paths
+}
+
+// Flips a bit `from_end` bytes before the end of the file.
+fn flip(path: &Path, from_end: usize) {
+    let mut bytes = fs::read(path).unwrap();
+    let at = bytes.len() - from_end;
+    bytes[at] ^= 1;
+    fs::write(path, bytes).unwrap();
+}
+
+// The log spreads over segments of bounded size, and a reopened storage
+// finds the hard state and every entry where they were left.
+#[test]
+fn log_survives_reopening_across_segments() {
+    let dir = dir("reopen");
+    let state = HardState {
+        current_term: Term::new(3),
+        voted_for: Some(NodeId::new(2)),
+    };
+
+    let mut storage = SegmentStorage::with_max_segment_bytes(&dir, 256).unwrap();
+    storage.save_hard_state(state);
+    storage.append(&entries(1, 40, 1));
+    storage.sync();
+    assert!(storage.segment_count() > 4);
+    assert_eq!(
+        storage.read(LogIndex::new(9), LogIndex::new(12), u64::MAX),
+        entries(9, 12, 1)
+    );
+    drop(storage);
+
+    let storage = SegmentStorage::with_max_segment_bytes(&dir, 256).unwrap();
+    assert_eq!(
+        storage.load(),
+        (state, LogOffset::default(), entries(1, 40, 1))
+    );
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// Truncating removes the segments past the cut and shortens the one it
+// falls in; appending goes on from there.
+#[test]
+fn truncate_cuts_across_segments() {
+    let dir = dir("truncate");
+    let mut storage = SegmentStorage::with_max_segment_bytes(&dir, 256).unwrap();
+    storage.append(&entries(1, 40, 1));
+    let segments = storage.segment_count();
+
+    storage.truncate(LogIndex::new(15));
+    assert!(storage.segment_count() < segments);
+    storage.append(&entries(15, 20, 2));
+    storage.sync();
+    drop(storage);
+
+    let storage = SegmentStorage::open(&dir).unwrap();
+    let mut expected = entries(1, 14, 1);
+    expected.extend(entries(15, 20, 2));
+    assert_eq!(storage.load().2, expected);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// A record cut short by a crash at the end of the log is dropped on open.
+#[test]
+fn torn_tail_is_cut_off() {
+    let dir = dir("torn");
+    let mut storage = SegmentStorage::open(&dir).unwrap();
+    storage.append(&entries(1, 5, 1));
+    storage.sync();
+    drop(storage);
+
+    let segment = fs::read_dir(&dir)
+        .unwrap()
+        .map(|file| file.unwrap().path())
+        .find(|path| path.extension().is_some_and(|ext| ext == "log"))
+        .unwrap();
+    let mut file = OpenOptions::new().append(true).open(&segment).unwrap();
+    file.write_all(&[0, 0, 0, 0, 0, 0, 0, 1, 0, 0]).unwrap();
+    drop(file);
+
+    let mut storage = SegmentStorage::open(&dir).unwrap();
+    assert_eq!(storage.load().2, entries(1, 5, 1));
+    storage.append(&entries(6, 6, 1));
+    assert_eq!(storage.load().2, entries(1, 6, 1));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// A record whose checksum fails is a torn write at the end of the log, and
+// corruption anywhere else. Open only checksums the last segment; in the
+// others it shows up on read, or on open if a record header is off.
+#[test]
+fn records_are_checksummed() {
+    let dir = dir("checksum");
+    let mut storage = SegmentStorage::with_max_segment_bytes(&dir, 256).unwrap();
+    storage.append(&entries(1, 40, 1));
+    storage.sync();
+    drop(storage);
+    let paths = segments(&dir);
+
+    flip(paths.last().unwrap(), 1);
+    let storage = SegmentStorage::with_max_segment_bytes(&dir, 256).unwrap();
+    assert_eq!(storage.load().2, entries(1, 39, 1));
+
+    flip(&paths[1], 1);
+    let read = panic::catch_unwind(AssertUnwindSafe(|| {
+        storage.read(LogIndex::new(1), LogIndex::new(20), u64::MAX)
+    }));
+    assert!(read.is_err());
+    drop(storage);
+
+    let storage = SegmentStorage::with_max_segment_bytes(&dir, 256).unwrap();
+    let read = panic::catch_unwind(AssertUnwindSafe(|| {
+        storage.read(LogIndex::new(1), LogIndex::new(20), u64::MAX)
+    }));
+    assert!(read.is_err());
+    drop(storage);
+
+    // The low byte of the first record's index.
+    let len = fs::metadata(&paths[2]).unwrap().len() as usize;
+    flip(&paths[2], len - 15);
+    let error = SegmentStorage::with_max_segment_bytes(&dir, 256).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// Reads stop once the commands would add up to more than asked for, but
+// always return the first entry.
+#[test]
+fn reads_stop_at_the_byte_limit() {
+    let dir = dir("limit");
+    let mut storage = SegmentStorage::with_max_segment_bytes(&dir, 256).unwrap();
+    storage.append(&entries(1, 40, 1));
+
+    let (from, to) = (LogIndex::new(8), LogIndex::new(40));
+    assert_eq!(storage.read(from, to, 39), entries(8, 11, 1));
+    assert_eq!(storage.read(from, to, 0), entries(8, 8, 1));
+    assert_eq!(storage.read(from, to, u64::MAX), entries(8, 40, 1));
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// Loading for a cache budget reads every term but only the newest commands.
+#[test]
+fn load_tail_reads_only_the_newest_commands() {
+    let dir = dir("tail");
+    let mut storage = SegmentStorage::with_max_segment_bytes(&dir, 256).unwrap();
+    storage.append(&entries(1, 40, 1));
+    storage.append(&entries(41, 45, 2));
+
+    let (_, _, loaded, cached_from) = storage.load_tail(55);
+    assert_eq!(cached_from, LogIndex::new(41));
+    assert_eq!(loaded.len(), 45);
+    for (entry, expected) in loaded
+        .iter()
+        .zip(entries(1, 40, 1).iter().chain(&entries(41, 45, 2)))
+    {
+        assert_eq!((entry.index, entry.term), (expected.index, expected.term));
+        if entry.index < cached_from {
+            assert!(entry.command.is_empty());
+        } else {
+            assert_eq!(entry.command, expected.command);
+        }
+    }
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+// The offset is kept with the hard state, and segments wholly before it are
+// removed once it is synced.
+#[test]
+fn compaction_keeps_the_offset_across_a_restart() {
+    let dir = dir("compact");
+    let mut storage = SegmentStorage::with_max_segment_bytes(&dir, 256).unwrap();
+    storage.append(&entries(1, 40, 1));
+    storage.sync();
+    let count = storage.segment_count();
+
+    let offset = LogOffset {
+        index: LogIndex::new(20),
+        term: Term::new(1),
+    };
+    storage.compact(offset);
+    assert_eq!(storage.load().2, entries(21, 40, 1));
+    storage.sync();
+    assert!(storage.segment_count() < count);
+    drop(storage);
+
+    let mut storage = SegmentStorage::with_max_segment_bytes(&dir, 256).unwrap();
+    assert_eq!(
+        storage.load(),
+        (HardState::default(), offset, entries(21, 40, 1))
+    );
+    let (_, _, loaded, _) = storage.load_tail(0);
+    assert_eq!(
+        loaded.first().map(|entry| entry.index),
+        Some(LogIndex::new(21))
+    );
+
+    // With every entry compacted, the log goes on after the offset.
+    let offset = LogOffset {
+        index: LogIndex::new(40),
+        term: Term::new(1),
+    };
+    storage.compact(offset);
+    storage.sync();
+    assert_eq!(storage.segment_count(), 0);
+    storage.append(&entries(41, 42, 2));
+    storage.sync();
+    drop(storage);
+
+    let storage = SegmentStorage::with_max_segment_bytes(&dir, 256).unwrap();
+    assert_eq!(storage.load().1, offset);
+    assert_eq!(storage.load().2, entries(41, 42, 2));
+    fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,228 @@
+mod common;
+
+use std::time::Duration;
+
+use mini_raft::config::RaftConfig;
+use mini_raft::read_index::ReadConsistency;
+use mini_raft::session::{
+    Command, CommandResult, ProposeOutcome, SessionCommand, SessionExpired, SessionStatus,
+    SessionTable,
+};
+use mini_raft::simulator::Simulator;
+use mini_raft::types::{LogIndex, NodeId};
+
+use common::elect;
+
+fn simulator(session_timeout: u64) -> (Simulator, NodeId) {
+    let config = RaftConfig {
+        session_timeout,
+        ..RaftConfig::default()
+    };
+    let mut sim = Simulator::with_config((1..=3).map(NodeId::new).collect(), config, 7);
+    let leader = elect(&mut sim);
+    (sim, leader)
+}
+
+fn propose(
+    sim: &mut Simulator,
+    leader: NodeId,
+    client: u64,
+    sequence: u64,
+    command: &[u8],
+) -> ProposeOutcome {
+    sim.runner_mut(leader)
+        .unwrap()
+        .propose(client, sequence, command.to_vec())
+        .expect("leader rejected the proposal")
+}
+
+fn read(sim: &mut Simulator, leader: NodeId, key: &[u8]) -> Vec<u8> {
+    let id = sim
+        .runner_mut(leader)
+        .unwrap()
+        .read(key.to_vec(), ReadConsistency::LinearizableLeader)
+        .unwrap();
+    sim.advance(Duration::from_millis(20));
+    let results = sim.runner_mut(leader).unwrap().take_read_results();
+    let (_, result) = results
+        .into_iter()
+        .find(|&(result, _)| result == id)
+        .unwrap();
+    result.unwrap()
+}
+
+fn result(client_id: u64, sequence: u64, response: &[u8]) -> CommandResult {
+    CommandResult {
+        client_id,
+        sequence,
+        response: Ok(response.to_vec()),
+    }
+}
+
+// A retry that reaches the log before the first copy commits is applied
+// once; both copies answer with the first one's response.
+#[test]
+fn duplicate_in_the_log_is_applied_once() {
+    let (mut sim, leader) = simulator(100);
+    assert_eq!(
+        propose(&mut sim, leader, 1, 1, b"n=a"),
+        ProposeOutcome::Queued
+    );
+    assert_eq!(
+        propose(&mut sim, leader, 1, 1, b"n=a"),
+        ProposeOutcome::Queued
+    );
+    assert_eq!(
+        propose(&mut sim, leader, 2, 1, b"n=b"),
+        ProposeOutcome::Queued
+    );
+    sim.advance(Duration::from_millis(50));
+
+    let results = sim.runner_mut(leader).unwrap().take_command_results();
+    assert_eq!(
+        results,
+        [result(1, 1, b""), result(1, 1, b""), result(2, 1, b"a")]
+    );
+    assert_eq!(read(&mut sim, leader, b"n"), b"b");
+}
+
+// Once applied, a retry gets the cached response without a new entry, and
+// anything older than the last command is stale.
+#[test]
+fn applied_command_answers_from_the_cache() {
+    let (mut sim, leader) = simulator(100);
+    propose(&mut sim, leader, 1, 1, b"n=a");
+    propose(&mut sim, leader, 1, 2, b"n=b");
+    sim.advance(Duration::from_millis(50));
+    let last = sim.node(leader).unwrap().log.last_log_index();
+
+    assert_eq!(
+        propose(&mut sim, leader, 1, 2, b"n=b"),
+        ProposeOutcome::Cached(b"a".to_vec())
+    );
+    assert_eq!(
+        propose(&mut sim, leader, 1, 1, b"n=a"),
+        ProposeOutcome::Stale
+    );
+    sim.advance(Duration::from_millis(50));
+    assert_eq!(sim.node(leader).unwrap().log.last_log_index(), last);
+    assert_eq!(read(&mut sim, leader, b"n"), b"b");
+}
+
+// A session idle for more than the timeout is dropped. A retry of its
+// last command is then refused rather than applied a second time, as is
+// anything newer; only a new client can go on.
+#[test]
+fn retry_after_expiry_is_refused() {
+    let (mut sim, leader) = simulator(3);
+    propose(&mut sim, leader, 1, 1, b"n=a");
+    propose(&mut sim, leader, 1, 2, b"n=b");
+    sim.advance(Duration::from_millis(50));
+    for sequence in 1..=4 {
+        propose(&mut sim, leader, 2, sequence, b"m=x");
+        sim.advance(Duration::from_millis(20));
+    }
+    propose(&mut sim, leader, 3, 1, b"n=c");
+    sim.advance(Duration::from_millis(50));
+    sim.runner_mut(leader).unwrap().take_command_results();
+
+    propose(&mut sim, leader, 1, 2, b"n=b");
+    propose(&mut sim, leader, 1, 3, b"n=d");
+    sim.advance(Duration::from_millis(50));
+    let results = sim.runner_mut(leader).unwrap().take_command_results();
+    let expired = |sequence| CommandResult {
+        client_id: 1,
+        sequence,
+        response: Err(SessionExpired),
+    };
+    assert_eq!(results, [expired(2), expired(3)]);
+    assert_eq!(read(&mut sim, leader, b"n"), b"c");
+}
+
+#[test]
+fn session_table_expires_by_log_index() {
+    let mut table = SessionTable::new(10);
+    table.record(1, 3, b"r".to_vec(), LogIndex::new(5));
+    let now = LogIndex::new(15);
+    table.expire(now);
+    assert_eq!(
+        table.status(1, 3, now),
+        SessionStatus::Duplicate(b"r".to_vec())
+    );
+    assert_eq!(table.status(1, 2, now), SessionStatus::Stale);
+    assert_eq!(table.status(1, 4, now), SessionStatus::New);
+
+    // Past the timeout the session is gone, whether or not it was dropped.
+    let later = LogIndex::new(16);
+    assert_eq!(table.status(1, 3, later), SessionStatus::Expired);
+    table.expire(later);
+    assert!(table.is_empty());
+    assert_eq!(table.status(1, 3, later), SessionStatus::Expired);
+    assert_eq!(table.status(2, 1, later), SessionStatus::New);
+}
+
+#[test]
+fn session_timeout_does_not_overflow() {
+    let mut table = SessionTable::new(u64::MAX);
+    table.record(1, 1, b"r".to_vec(), LogIndex::new(5));
+    table.expire(LogIndex::new(6));
+    assert_eq!(table.len(), 1);
+    assert_eq!(
+        table.status(1, 1, LogIndex::new(6)),
+        SessionStatus::Duplicate(b"r".to_vec())
+    );
+}
+
+// A command whose bytes look like a session header still decodes as what
+// it is, in either kind of envelope.
+#[test]
+fn command_bytes_never_pass_for_a_session() {
+    let session = SessionCommand {
+        client_id: 7,
+        sequence: 9,
+        command: b"x=1".to_vec(),
+    };
+    let lookalike = session.encode();
+    for command in [
+        Command::Plain(lookalike.clone()),
+        Command::Session(SessionCommand {
+            client_id: 1,
+            sequence: 1,
+            command: lookalike.clone(),
+        }),
+        Command::Session(session),
+        Command::Plain(Vec::new()),
+    ] {
+        assert_eq!(Command::decode(&command.encode()), Some(command));
+    }
+    assert_eq!(Command::decode(&[9, 1, 2]), None);
+}
+
+// A plain command in the log is applied as is, even if it starts like a
+// session command.
+#[test]
+fn plain_command_is_applied_as_is() {
+    let (mut sim, leader) = simulator(100);
+    let mut command = SessionCommand {
+        client_id: 7,
+        sequence: 9,
+        command: Vec::new(),
+    }
+    .encode();
+    command.extend_from_slice(b"=v");
+    let key = command[..command.len() - 2].to_vec();
+
+    sim.runner_mut(leader)
+        .unwrap()
+        .node_mut()
+        .propose(Command::Plain(command).encode());
+    sim.advance(Duration::from_millis(50));
+
+    assert_eq!(read(&mut sim, leader, &key), b"v");
+    assert!(
+        sim.runner_mut(leader)
+            .unwrap()
+            .take_command_results()
+            .is_empty()
+    );
+}
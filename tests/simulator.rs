@@ -0,0 +1,243 @@
+mod common;
+
+use std::time::Duration;
+
+use mini_raft::invariants::{InvariantChecker, Violation};
+use mini_raft::log::LogEntry;
+use mini_raft::network::{Latency, LinkConfig, Network};
+use mini_raft::node::RaftNode;
+use mini_raft::raft::RaftAction;
+use mini_raft::rpc::RequestVoteRequest;
+use mini_raft::session::ProposeOutcome;
+use mini_raft::simulator::Simulator;
+use mini_raft::storage::Storage;
+use mini_raft::types::{LogIndex, NodeId, Term};
+use rand::SeedableRng;
+use rand::rngs::StdRng;
+
+use common::elect;
+
+fn run(seed: u64) -> String {
+    let mut sim = Simulator::with_seed((1..=3).map(NodeId::new).collect(), seed);
+    let leader = elect(&mut sim);
+    for sequence in 1..=5 {
+        sim.runner_mut(leader).unwrap().propose(
+            1,
+            sequence,
+            format!("x={}", sequence).into_bytes(),
+        );
+        sim.advance(Duration::from_millis(10));
+    }
+    sim.crash(leader);
+    sim.advance(Duration::from_millis(500));
+    sim.restart(leader);
+    sim.advance(Duration::from_millis(200));
+    sim.trace_json_lines()
+}
+
+#[test]
+fn same_seed_gives_the_same_trace() {
+    assert_eq!(run(11), run(11));
+    assert_ne!(run(11), run(12));
+}
+
+fn id(id: u64) -> NodeId {
+    NodeId::new(id)
+}
+
+// A vote request whose term numbers the message, so tests can tell which
+// one arrived.
+fn message(to: NodeId, number: u64) -> RaftAction {
+    RaftAction::SendRequestVote(
+        to,
+        RequestVoteRequest {
+            term: Term::new(number),
+            candidate_id: id(1),
+            last_log_index: LogIndex::ZERO,
+            last_log_term: Term::ZERO,
+        },
+    )
+}
+
+fn numbers(delivered: Vec<(NodeId, RaftAction)>) -> Vec<u64> {
+    delivered
+        .into_iter()
+        .map(|(_, action)| match action {
+            RaftAction::SendRequestVote(_, request) => request.term.get(),
+            action => panic!("unexpected message {:?}", action),
+        })
+        .collect()
+}
+
+fn network(link: LinkConfig) -> Network {
+    let mut network = Network::new(StdRng::seed_from_u64(5));
+    network.set_default_link(link);
+    network
+}
+
+fn send_all(network: &mut Network, count: u64) -> Vec<u64> {
+    for number in 1..=count {
+        network.send(Duration::from_millis(number), id(1), message(id(2), number));
+    }
+    numbers(network.deliver(Duration::from_secs(10)))
+}
+
+#[test]
+fn dropped_messages_never_arrive() {
+    let mut network = network(LinkConfig {
+        drop_rate: 1.0,
+        ..LinkConfig::default()
+    });
+    assert!(send_all(&mut network, 10).is_empty());
+    assert!(network.is_empty());
+}
+
+#[test]
+fn duplicated_messages_arrive_twice() {
+    let mut network = network(LinkConfig {
+        duplicate_rate: 1.0,
+        ..LinkConfig::default()
+    });
+    assert_eq!(send_all(&mut network, 3), [1, 1, 2, 2, 3, 3]);
+}
+
+#[test]
+fn messages_arrive_in_order_unless_reordered() {
+    let jitter = Latency::Uniform {
+        min: Duration::from_millis(1),
+        max: Duration::from_millis(50),
+    };
+    let mut in_order = network(LinkConfig {
+        latency: jitter,
+        ..LinkConfig::default()
+    });
+    assert_eq!(send_all(&mut in_order, 50), (1..=50).collect::<Vec<_>>());
+
+    let mut reordered = network(LinkConfig {
+        latency: jitter,
+        reorder_rate: 0.5,
+        reorder_window: Duration::from_millis(100),
+        ..LinkConfig::default()
+    });
+    let delivered = send_all(&mut reordered, 50);
+    assert_ne!(delivered, (1..=50).collect::<Vec<_>>());
+    let mut sorted = delivered.clone();
+    sorted.sort();
+    assert_eq!(sorted, (1..=50).collect::<Vec<_>>());
+}
+
+#[test]
+fn one_way_partition_blocks_one_direction() {
+    let mut network = network(LinkConfig::default());
+    network.block(id(1), id(2));
+    network.send(Duration::ZERO, id(1), message(id(2), 1));
+    network.send(Duration::ZERO, id(2), message(id(1), 2));
+    assert_eq!(numbers(network.deliver(Duration::from_secs(1))), [2]);
+
+    // A message already in flight is lost when its link is cut.
+    network.unblock(id(1), id(2));
+    network.send(Duration::ZERO, id(1), message(id(2), 3));
+    network.block(id(1), id(2));
+    assert!(network.deliver(Duration::from_secs(1)).is_empty());
+}
+
+// A plain crash keeps writes that were not synced, as the page cache does;
+// a power failure loses them.
+#[test]
+fn unsynced_writes_are_lost_only_on_power_failure() {
+    for lose_unsynced in [false, true] {
+        let mut sim = Simulator::with_seed((1..=3).map(NodeId::new).collect(), 8);
+        let leader = elect(&mut sim);
+        sim.advance(Duration::from_millis(10));
+
+        let index = LogIndex::new(sim.node(leader).unwrap().log.last_log_index().get() + 1);
+        let outcome = sim
+            .runner_mut(leader)
+            .unwrap()
+            .propose(1, 1, b"x=1".to_vec());
+        assert_eq!(outcome, Some(ProposeOutcome::Queued));
+        sim.tick();
+        assert!(sim.storage(leader).unwrap().unsynced_writes() > 0);
+
+        if lose_unsynced {
+            sim.crash_losing_unsynced(leader);
+        } else {
+            sim.crash(leader);
+        }
+        let (_, _, entries) = sim.storage(leader).unwrap().load();
+        assert_eq!(
+            entries.iter().any(|entry| entry.index == index),
+            !lose_unsynced
+        );
+    }
+}
+
+fn node(node_id: u64, terms: &[(u64, &str)]) -> RaftNode {
+    let mut node = RaftNode::new(id(node_id), Vec::new());
+    for (position, &(term, command)) in terms.iter().enumerate() {
+        node.log.append(LogEntry {
+            term: Term::new(term),
+            index: LogIndex::new(position as u64 + 1),
+            command: command.as_bytes().to_vec(),
+        });
+    }
+    node.log.take_changed_from();
+    node
+}
+
+#[test]
+fn same_index_and_term_with_another_command_breaks_log_matching() {
+    let mut checker = InvariantChecker::new();
+    assert_eq!(checker.check(&node(1, &[(1, "a"), (1, "b")]), None), Ok(()));
+    assert_eq!(
+        checker.check(&node(2, &[(1, "a"), (1, "c")]), None),
+        Err(Violation::LogMatching {
+            nodes: (id(1), id(2)),
+            index: LogIndex::new(2),
+        })
+    );
+}
+
+// Equal entries on top of logs that differ further down.
+#[test]
+fn diverged_prefix_breaks_log_matching() {
+    let mut checker = InvariantChecker::new();
+    assert_eq!(checker.check(&node(1, &[(1, "a"), (3, "c")]), None), Ok(()));
+    assert_eq!(
+        checker.check(&node(2, &[(2, "b"), (3, "c")]), None),
+        Err(Violation::LogMatching {
+            nodes: (id(1), id(2)),
+            index: LogIndex::new(2),
+        })
+    );
+}
+
+// Only what changed since a node's last check is looked at again, but a
+// change anywhere in the log is still seen.
+#[test]
+fn broken_entry_is_caught_after_the_first_check() {
+    let mut checker = InvariantChecker::new();
+    let mut follower = node(2, &[(1, "a"), (1, "b"), (2, "c")]);
+    assert_eq!(
+        checker.check(&node(1, &[(1, "a"), (1, "b"), (2, "c")]), None),
+        Ok(())
+    );
+    assert_eq!(checker.check(&follower, None), Ok(()));
+
+    follower.log.truncate(LogIndex::new(2));
+    for (index, command) in [(2, "broken"), (3, "c")] {
+        follower.log.append(LogEntry {
+            term: Term::new(index - 1),
+            index: LogIndex::new(index),
+            command: command.as_bytes().to_vec(),
+        });
+    }
+    let changed_from = follower.log.take_changed_from();
+    assert_eq!(
+        checker.check(&follower, changed_from),
+        Err(Violation::LogMatching {
+            nodes: (id(1), id(2)),
+            index: LogIndex::new(2),
+        })
+    );
+}
@@ -0,0 +1,82 @@
+use mini_raft::fuzz::{Driver, Schedule};
+use mini_raft::raft::RaftAction;
+use mini_raft::spec;
+use mini_raft::trace::{self, TraceEvent};
+use mini_raft::types::{LogIndex, NodeId};
+
+fn drive(seed: u64, nodes: u64) -> Driver {
+    let schedule = Schedule::generate(seed, nodes, 40);
+    let mut driver = Driver::new(schedule.nodes, schedule.seed);
+    for step in &schedule.steps {
+        driver.apply(step).unwrap();
+    }
+    driver
+}
+
+fn run(seed: u64, nodes: u64) -> Vec<(NodeId, TraceEvent)> {
+    drive(seed, nodes).sim().cluster_trace()
+}
+
+#[test]
+fn simulator_traces_follow_the_spec() {
+    for seed in 0..60 {
+        let nodes = if seed % 2 == 0 { 3 } else { 5 };
+        let cluster_trace = run(seed, nodes);
+        if let Err(violation) = spec::check(&cluster_trace) {
+            panic!("seed {} with {} nodes: {}", seed, nodes, violation);
+        }
+    }
+}
+
+#[test]
+fn exported_traces_are_checked_the_same() {
+    let driver = drive(3, 3);
+    let cluster_trace = trace::from_cluster_json_lines(&driver.sim().trace_json_lines()).unwrap();
+
+    assert_eq!(cluster_trace, driver.sim().cluster_trace());
+    assert_eq!(spec::check(&cluster_trace), Ok(()));
+}
+
+#[test]
+fn flipped_vote_points_at_the_vote_handler() {
+    let mut cluster_trace = run(0, 3);
+    let position = cluster_trace
+        .iter()
+        .position(|(_, event)| {
+            matches!(
+                event,
+                TraceEvent::Sent {
+                    action: RaftAction::SendRequestVoteResponse(_, response),
+                    ..
+                } if response.vote_granted
+            )
+        })
+        .unwrap();
+    if let TraceEvent::Sent {
+        action: RaftAction::SendRequestVoteResponse(_, response),
+        ..
+    } = &mut cluster_trace[position].1
+    {
+        response.vote_granted = false;
+    }
+
+    let violation = spec::check(&cluster_trace).unwrap_err();
+    assert_eq!(violation.position, position);
+    assert_eq!(violation.handler, "handle_request_vote");
+}
+
+#[test]
+fn early_commit_is_rejected() {
+    let mut cluster_trace = run(1, 3);
+    let position = cluster_trace
+        .iter()
+        .position(|(_, event)| matches!(event, TraceEvent::Committed { .. }))
+        .unwrap();
+    if let TraceEvent::Committed { index, .. } = &mut cluster_trace[position].1 {
+        *index = LogIndex::new(index.get() + 1);
+    }
+
+    let violation = spec::check(&cluster_trace).unwrap_err();
+    assert_eq!(violation.position, position);
+    assert!(violation.to_string().contains("committed"), "{}", violation);
+}
@@ -0,0 +1,188 @@
+mod common;
+
+use std::collections::BTreeMap;
+use std::{env, fs, process};
+
+use rcgen::{
+    BasicConstraints, CertificateParams, DnType, ExtendedKeyUsagePurpose, IsCa, Issuer, KeyPair,
+};
+use tonic::transport::{ClientTlsConfig, Endpoint};
+use tonic::{Code, Request};
+
+use mini_raft::grpc::{GrpcTransport, Listener, PeerAddr};
+use mini_raft::handshake::Handshake;
+use mini_raft::raft_proto::RequestVoteRequest;
+use mini_raft::raft_proto::raft_client::RaftClient;
+use mini_raft::tls::{self, TlsConfig};
+use mini_raft::types::NodeId;
+
+use common::{Runners, replicates_a_proposal, start};
+
+struct Ca {
+    issuer: Issuer<'static, KeyPair>,
+    pem: String,
+}
+
+impl Ca {
+    fn new() -> Self {
+        let mut params = CertificateParams::new(Vec::<String>::new()).unwrap();
+        params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+        params
+            .distinguished_name
+            .push(DnType::CommonName, "mini-raft test ca");
+        let key = KeyPair::generate().unwrap();
+        let pem = params.self_signed(&key).unwrap().pem();
+        Self {
+            issuer: Issuer::new(params, key),
+            pem,
+        }
+    }
+
+    // A certificate for `name` and its key, both PEM-encoded.
+    fn issue(&self, name: &str) -> (String, String, Vec<u8>) {
+        let mut params = CertificateParams::new(vec![name.to_string()]).unwrap();
+        params.extended_key_usages = vec![
+            ExtendedKeyUsagePurpose::ServerAuth,
+            ExtendedKeyUsagePurpose::ClientAuth,
+        ];
+        let key = KeyPair::generate().unwrap();
+        let cert = params.signed_by(&key, &self.issuer).unwrap();
+        (cert.pem(), key.serialize_pem(), cert.der().to_vec())
+    }
+
+    fn node(&self, id: u64) -> TlsConfig {
+        let (cert, key, _) = self.issue(&tls::node_name(NodeId::new(id)));
+        TlsConfig::new(cert, key, &self.pem)
+    }
+}
+
+// Starts a cluster whose nodes use the given TLS configs, and returns the
+// runners with the nodes' addresses.
+async fn cluster(
+    configs: Vec<TlsConfig>,
+    addrs: Vec<PeerAddr>,
+) -> (Runners, BTreeMap<NodeId, PeerAddr>) {
+    let all: Vec<_> = (1..=configs.len() as u64).map(NodeId::new).collect();
+    let mut listeners = Vec::new();
+    let mut bound = BTreeMap::new();
+    for (&id, addr) in all.iter().zip(&addrs) {
+        let listener = Listener::bind(addr).await.unwrap();
+        bound.insert(id, listener.local_addr().unwrap());
+        listeners.push(listener);
+    }
+
+    let mut runners = BTreeMap::new();
+    for ((&id, listener), config) in all.iter().zip(listeners).zip(configs) {
+        let mut peers = bound.clone();
+        peers.remove(&id);
+        let transport = GrpcTransport::with_tls(Handshake::new("test"), &peers, config).unwrap();
+        tokio::spawn(transport.serve(listener));
+        runners.insert(id, start(id, &all, transport));
+    }
+    (runners, bound)
+}
+
+fn tcp_addrs(size: usize) -> Vec<PeerAddr> {
+    vec!["127.0.0.1:0".parse().unwrap(); size]
+}
+
+async fn client(
+    addr: &PeerAddr,
+    tls: Option<ClientTlsConfig>,
+) -> RaftClient<tonic::transport::Channel> {
+    let PeerAddr::Tcp(addr) = addr else {
+        panic!("tcp address expected");
+    };
+    let scheme = if tls.is_some() { "https" } else { "http" };
+    let mut endpoint = Endpoint::from_shared(format!("{}://{}", scheme, addr)).unwrap();
+    if let Some(tls) = tls {
+        endpoint = endpoint.tls_config(tls).unwrap();
+    }
+    RaftClient::new(endpoint.connect_lazy())
+}
+
+fn vote_request(candidate: u64) -> Request<RequestVoteRequest> {
+    let mut request = Request::new(RequestVoteRequest {
+        term: 1,
+        candidate_id: candidate,
+        last_log_index: 0,
+        last_log_term: 0,
+    });
+    Handshake::new("test").write(request.metadata_mut());
+    request
+}
+
+#[tokio::test]
+async fn mtls_cluster_over_tcp_replicates_a_proposal() {
+    let ca = Ca::new();
+    let (runners, _) = cluster((1..=3).map(|id| ca.node(id)).collect(), tcp_addrs(3)).await;
+
+    replicates_a_proposal(&runners).await;
+}
+
+#[tokio::test]
+async fn mtls_cluster_over_unix_sockets_replicates_a_proposal() {
+    let dir = env::temp_dir().join(format!("mini-raft-tls-{}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let addrs = (1..=3)
+        .map(|i| PeerAddr::Unix(dir.join(format!("node-{}.sock", i))))
+        .collect();
+
+    let ca = Ca::new();
+    let (runners, _) = cluster((1..=3).map(|id| ca.node(id)).collect(), addrs).await;
+
+    replicates_a_proposal(&runners).await;
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn node_may_only_send_as_itself() {
+    let ca = Ca::new();
+    let (_, addrs) = cluster(vec![ca.node(1)], tcp_addrs(1)).await;
+    let node3 = ca.node(3);
+
+    let mut client = client(&addrs[&NodeId::new(1)], Some(node3.client(NodeId::new(1)))).await;
+    let status = client.request_vote(vote_request(2)).await.unwrap_err();
+    assert_eq!(status.code(), Code::PermissionDenied, "{:?}", status);
+
+    client.request_vote(vote_request(3)).await.unwrap();
+}
+
+#[tokio::test]
+async fn server_must_carry_the_expected_node_name() {
+    let ca = Ca::new();
+    let (_, addrs) = cluster(vec![ca.node(1)], tcp_addrs(1)).await;
+
+    // Node 1 answers at the address configured for node 2.
+    let node3 = ca.node(3);
+    let mut client = client(&addrs[&NodeId::new(1)], Some(node3.client(NodeId::new(2)))).await;
+    assert!(client.request_vote(vote_request(3)).await.is_err());
+}
+
+#[tokio::test]
+async fn untrusted_and_plaintext_clients_are_rejected() {
+    let ca = Ca::new();
+    let (_, addrs) = cluster(vec![ca.node(1)], tcp_addrs(1)).await;
+    let addr = &addrs[&NodeId::new(1)];
+
+    // Signed by another CA, though it trusts node 1's.
+    let other = Ca::new();
+    let (cert, key, _) = other.issue(&tls::node_name(NodeId::new(3)));
+    let rogue = TlsConfig::new(cert, key, &ca.pem);
+    let mut rogue_client = client(addr, Some(rogue.client(NodeId::new(1)))).await;
+    assert!(rogue_client.request_vote(vote_request(3)).await.is_err());
+
+    let mut plaintext = client(addr, None).await;
+    assert!(plaintext.request_vote(vote_request(3)).await.is_err());
+}
+
+#[test]
+fn certificates_name_their_node() {
+    let ca = Ca::new();
+    let (_, _, der) = ca.issue(&tls::node_name(NodeId::new(7)));
+
+    assert!(tls::names_node(&der, NodeId::new(7)));
+    assert!(!tls::names_node(&der, NodeId::new(70)));
+    assert!(!tls::names_node(b"not a certificate", NodeId::new(7)));
+}
@@ -0,0 +1,94 @@
+use mini_raft::fuzz::{Driver, Schedule};
+use mini_raft::trace::{self, ReplayError, TraceEvent};
+use mini_raft::types::{LogIndex, NodeId};
+
+fn run(seed: u64) -> Driver {
+    let schedule = Schedule::generate(seed, 3, 40);
+    let mut driver = Driver::new(schedule.nodes, schedule.seed);
+    for step in &schedule.steps {
+        driver.apply(step).unwrap();
+    }
+    driver
+}
+
+// Schedules include crashes, restarts and added nodes, so some node traces
+// hold several runs and there may be more traces than initial nodes.
+#[test]
+fn exported_traces_replay_identically() {
+    for seed in 0..20 {
+        let driver = run(seed);
+        let traces = trace::by_node(
+            trace::from_cluster_json_lines(&driver.sim().trace_json_lines()).unwrap(),
+        );
+        assert_eq!(traces.len(), driver.sim().node_ids().len());
+
+        for (&id, node_trace) in &traces {
+            assert_eq!(node_trace, &driver.sim().node_trace(id));
+            if let Err(err) = trace::replay(node_trace) {
+                panic!("seed {} node {}: {}", seed, id.get(), err);
+            }
+        }
+    }
+}
+
+#[test]
+fn node_trace_round_trips_through_json_lines() {
+    let driver = run(1);
+    let node_trace = driver.sim().node_trace(NodeId::new(1));
+
+    let parsed = trace::from_json_lines(&trace::to_json_lines(&node_trace)).unwrap();
+    assert_eq!(parsed, node_trace);
+}
+
+#[test]
+fn malformed_lines_are_reported_with_their_line() {
+    let lines = [
+        r#"{"event":"committed","at_ns":5,"index":2}"#,
+        r#"{"event":"proposed","at_ns":6,"client_id":1,"sequence":1,"command":"7"}"#,
+    ];
+    assert!(trace::from_json_lines(lines[0]).is_ok());
+
+    let err = trace::from_json_lines(&lines.join("\n")).unwrap_err();
+    assert!(err.0.starts_with("line 2: invalid hex `7`"), "{}", err);
+    let response = concat!(
+        r#"{"event":"received","at_ns":1,"from":null,"message":{"type":"append_entries_response","#,
+        r#""term":1,"success":true,"match_index":1,"heartbeat_round":0}}"#
+    );
+    let err = trace::from_json_lines(response).unwrap_err();
+    assert!(err.0.contains("missing `from`"), "{}", err);
+}
+
+#[test]
+fn replay_reports_where_outputs_diverge() {
+    let driver = run(2);
+    let leader = driver
+        .sim()
+        .node_ids()
+        .into_iter()
+        .find(|&id| {
+            driver
+                .sim()
+                .node_trace(id)
+                .iter()
+                .any(|event| matches!(event, TraceEvent::Committed { .. }))
+        })
+        .unwrap();
+    let mut node_trace = driver.sim().node_trace(leader);
+
+    let position = node_trace
+        .iter()
+        .position(|event| matches!(event, TraceEvent::Committed { .. }))
+        .unwrap();
+    if let TraceEvent::Committed { index, .. } = &mut node_trace[position] {
+        *index = LogIndex::new(index.get() + 1);
+    }
+
+    match trace::replay(&node_trace) {
+        Err(ReplayError::Diverged { position: at, .. }) => assert_eq!(at, position),
+        other => panic!("expected divergence, got {:?}", other),
+    }
+    assert_eq!(
+        trace::replay(&node_trace[position..]),
+        Err(ReplayError::NotStarted)
+    );
+}
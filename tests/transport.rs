@@ -0,0 +1,83 @@
+mod common;
+
+use std::collections::BTreeMap;
+use std::{env, fs, process};
+
+use mini_raft::grpc::{GrpcTransport, Listener, PeerAddr};
+use mini_raft::handshake::Handshake;
+use mini_raft::transport::ChannelTransport;
+use mini_raft::types::NodeId;
+
+use common::{Runners, replicates_a_proposal, start};
+
+fn ids(size: u64) -> Vec<NodeId> {
+    (1..=size).map(NodeId::new).collect()
+}
+
+async fn grpc_cluster(addrs: &[PeerAddr]) -> Runners {
+    let all = ids(addrs.len() as u64);
+    let mut listeners = Vec::new();
+    let mut bound = BTreeMap::new();
+    for (&id, addr) in all.iter().zip(addrs) {
+        let listener = Listener::bind(addr).await.unwrap();
+        bound.insert(id, listener.local_addr().unwrap());
+        listeners.push(listener);
+    }
+
+    let mut runners = BTreeMap::new();
+    for (&id, listener) in all.iter().zip(listeners) {
+        let mut peers = bound.clone();
+        peers.remove(&id);
+        let transport = GrpcTransport::new(Handshake::new("test"), &peers);
+        tokio::spawn(transport.serve(listener));
+        runners.insert(id, start(id, &all, transport));
+    }
+    runners
+}
+
+#[tokio::test]
+async fn channel_cluster_replicates_a_proposal() {
+    let all = ids(3);
+    let runners = ChannelTransport::cluster(&all)
+        .into_iter()
+        .map(|(id, transport)| (id, start(id, &all, transport)))
+        .collect();
+
+    replicates_a_proposal(&runners).await;
+}
+
+#[tokio::test]
+async fn grpc_cluster_over_tcp_replicates_a_proposal() {
+    let addrs = vec!["127.0.0.1:0".parse().unwrap(); 3];
+    let runners = grpc_cluster(&addrs).await;
+
+    replicates_a_proposal(&runners).await;
+}
+
+#[tokio::test]
+async fn grpc_cluster_over_unix_sockets_replicates_a_proposal() {
+    let dir = env::temp_dir().join(format!("mini-raft-transport-{}", process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    let addrs: Vec<_> = (1..=3)
+        .map(|i| PeerAddr::Unix(dir.join(format!("node-{}.sock", i))))
+        .collect();
+    let runners = grpc_cluster(&addrs).await;
+
+    replicates_a_proposal(&runners).await;
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn peer_addresses_parse_and_display() {
+    let tcp: PeerAddr = "127.0.0.1:7000".parse().unwrap();
+    assert_eq!(tcp, PeerAddr::Tcp("127.0.0.1:7000".parse().unwrap()));
+    assert_eq!(tcp.to_string(), "127.0.0.1:7000");
+
+    let unix: PeerAddr = "unix:/tmp/raft.sock".parse().unwrap();
+    assert_eq!(unix, PeerAddr::Unix("/tmp/raft.sock".into()));
+    assert_eq!(unix.to_string(), "unix:/tmp/raft.sock");
+
+    assert!("unix:".parse::<PeerAddr>().is_err());
+    assert!("localhost".parse::<PeerAddr>().is_err());
+}
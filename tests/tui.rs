@@ -0,0 +1,50 @@
+use mini_raft::tui::{App, Key};
+use mini_raft::types::NodeId;
+
+fn run_frames(app: &mut App, frames: usize) {
+    for _ in 0..frames {
+        app.frame();
+    }
+}
+
+fn press(app: &mut App, keys: &str) {
+    for c in keys.chars() {
+        app.handle_key(Key::Char(c));
+    }
+}
+
+#[test]
+fn keys_drive_the_simulator() {
+    let mut app = App::new(3, 0);
+    press(&mut app, " ");
+    run_frames(&mut app, 100);
+    let leader = app.driver().sim().find_leader().expect("a leader after 2s");
+    assert!(app.render().contains("replication:"));
+
+    press(&mut app, "x");
+    app.handle_key(Key::Enter);
+    run_frames(&mut app, 10);
+    assert!(app.render().contains("proposed \"k1=v1\""));
+
+    press(&mut app, &leader.get().to_string());
+    press(&mut app, "c");
+    assert!(app.driver().sim().is_crashed(leader));
+    assert!(app.render().contains("crashed"));
+
+    press(&mut app, "r");
+    press(&mut app, "q");
+    assert!(!app.driver().sim().is_crashed(leader));
+    assert!(app.should_quit());
+}
+
+#[test]
+fn stepping_pauses_and_advances_one_tick() {
+    let mut app = App::new(3, 0);
+    press(&mut app, " ..");
+    assert!(app.is_paused());
+    assert_eq!(app.driver().sim().elapsed().as_millis(), 2);
+
+    press(&mut app, "2i");
+    assert!(app.driver().sim().network().is_blocked(NodeId::new(1), NodeId::new(2)));
+    assert!(app.render().contains("cut links: 1-2 2-3"));
+}